@@ -0,0 +1,53 @@
+//! Benchmarks of syntax highlighting.
+//!
+//! The first file which is highlighted needs to load the syntax set and compile the regular
+//! expressions of its syntax, which is measured separately from highlighting once they are
+//! compiled. Run with `cargo bench --bench highlight`.
+
+use bytes::Bytes;
+use criterion::{criterion_group, criterion_main, Criterion};
+use diff_rs::syntax::{
+    highlight_changes, highlight_changes_with, infer_syntax_for_file, load_syntax_set,
+    load_theme_set, theme_css, DEFAULT_LIGHT_THEME,
+};
+use similar::ChangeTag;
+
+/// Source which is highlighted, `src/lib.rs` of `log-0.4.17`.
+///
+/// This is a copy of a published file, so that the results are comparable between revisions.
+const SOURCE: &str = include_str!("../data/log-0.4.17-lib.rs");
+
+fn changes() -> Vec<(ChangeTag, Bytes)> {
+    SOURCE
+        .split_inclusive('\n')
+        .map(|line| (ChangeTag::Equal, Bytes::from_static(line.as_bytes())))
+        .collect()
+}
+
+fn load(c: &mut Criterion) {
+    c.bench_function("load syntax set", |b| {
+        b.iter_with_large_drop(load_syntax_set)
+    });
+    c.bench_function("load theme set", |b| b.iter_with_large_drop(load_theme_set));
+    c.bench_function("theme css", |b| b.iter(|| theme_css(DEFAULT_LIGHT_THEME)));
+}
+
+fn highlight(c: &mut Criterion) {
+    let changes = changes();
+    c.bench_function("first highlight", |b| {
+        b.iter_with_large_drop(|| {
+            let syntax_set = load_syntax_set();
+            let syntax = syntax_set.find_syntax_by_extension("rs").unwrap();
+            let lines = highlight_changes_with(&syntax_set, syntax, &changes);
+            (syntax_set, lines)
+        })
+    });
+
+    let syntax = infer_syntax_for_file("src/lib.rs", SOURCE.lines());
+    c.bench_function("highlight", |b| {
+        b.iter(|| highlight_changes(syntax, &changes))
+    });
+}
+
+criterion_group!(benches, load, highlight);
+criterion_main!(benches);
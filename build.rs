@@ -1,8 +1,27 @@
+use std::{env, path::Path};
+use syntect::{dumps::dump_to_uncompressed_file, highlighting::ThemeSet, parsing::SyntaxSet};
 use vergen_gitcl::{Emitter, GitclBuilder};
 
+/// Directory of syntax definitions which are added to the default syntaxes of syntect.
+const SYNTAXES: &str = "syntaxes";
+
+/// Themes of syntect which can be chosen for highlighting, the others are left out of the dump.
+const THEMES: [&str; 5] = [
+    "InspiredGitHub",
+    "Solarized (light)",
+    "base16-ocean.light",
+    "base16-ocean.dark",
+    "Solarized (dark)",
+];
+
 /// This build script will query your `git` executable to fetch the current commit hash, and make
 /// it available to the application using an environment variable. This is used to show the commit
 /// hash that diff.rs was built with in the footer.
+///
+/// It also compiles the syntax definitions in `syntaxes/` along with the default syntaxes of
+/// syntect, and dumps them to `syntaxes.packdump` in the output directory, along with the themes
+/// in `themes.themedump`. Both are embedded into the application. The dumps are not compressed,
+/// so that loading them is fast, the application is served compressed anyways.
 fn main() {
     let gitcl = GitclBuilder::all_git().unwrap();
     Emitter::default()
@@ -10,4 +29,24 @@ fn main() {
         .unwrap()
         .emit()
         .unwrap();
+
+    let out_dir = env::var("OUT_DIR").unwrap();
+    let out_dir = Path::new(&out_dir);
+
+    println!("cargo:rerun-if-changed={SYNTAXES}");
+    let mut builder = SyntaxSet::load_defaults_newlines().into_builder();
+    builder.add_from_folder(SYNTAXES, true).unwrap();
+    let syntaxes = builder.build();
+    dump_to_uncompressed_file(&syntaxes, out_dir.join("syntaxes.packdump")).unwrap();
+
+    let defaults = ThemeSet::load_defaults();
+    let mut themes = ThemeSet::new();
+    for name in THEMES {
+        let theme = defaults
+            .themes
+            .get(name)
+            .unwrap_or_else(|| panic!("missing theme {name}"));
+        themes.themes.insert(name.into(), theme.clone());
+    }
+    dump_to_uncompressed_file(&themes, out_dir.join("themes.themedump")).unwrap();
 }
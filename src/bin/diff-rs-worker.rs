@@ -0,0 +1,14 @@
+//! Entry point of the web worker which fetches and diffs crate sources, see
+//! [`diff_rs::worker`].
+
+use diff_rs::worker::DiffWorker;
+use gloo_worker::Registrable;
+
+fn main() {
+    #[cfg(debug_assertions)]
+    wasm_logger::init(wasm_logger::Config::new(log::Level::Debug));
+    #[cfg(not(debug_assertions))]
+    wasm_logger::init(wasm_logger::Config::new(log::Level::Warn));
+
+    DiffWorker::registrar().register();
+}
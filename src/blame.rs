@@ -0,0 +1,53 @@
+//! Annotation of the lines of a file with the release which introduced them.
+//!
+//! The file is followed through a series of versions, from oldest to newest. Lines which are
+//! unchanged between two consecutive versions keep their annotation, lines which are inserted are
+//! annotated with the newer version.
+
+use bytes::Bytes;
+use semver::Version;
+use similar::{ChangeTag, TextDiff};
+
+/// Line of a file, annotated with the version which introduced it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BlameLine {
+    /// Contents of the line, including the line ending
+    pub text: Bytes,
+    /// Version which introduced the line
+    pub version: Version,
+}
+
+/// Annotate the lines of the newest version of a file with the versions which introduced them.
+///
+/// Takes the contents of the file in every version, ordered from oldest to newest, or `None` for
+/// versions which do not contain the file. Lines which were present in the oldest version are
+/// annotated with it, even though they may have been introduced earlier.
+pub fn blame(versions: &[(Version, Option<Bytes>)]) -> Vec<BlameLine> {
+    let mut lines: Vec<BlameLine> = vec![];
+    let mut previous: Option<&Bytes> = None;
+    for (version, data) in versions {
+        let Some(data) = data else {
+            lines.clear();
+            previous = None;
+            continue;
+        };
+
+        let old = previous.map(|data| &data[..]).unwrap_or_default();
+        let diff = TextDiff::from_lines(old, &data[..]);
+        let mut old_lines = std::mem::take(&mut lines).into_iter();
+        for change in diff.iter_all_changes() {
+            match change.tag() {
+                ChangeTag::Equal => lines.extend(old_lines.next()),
+                ChangeTag::Delete => {
+                    old_lines.next();
+                }
+                ChangeTag::Insert => lines.push(BlameLine {
+                    text: data.slice_ref(change.value()),
+                    version: version.clone(),
+                }),
+            }
+        }
+        previous = Some(data);
+    }
+    lines
+}
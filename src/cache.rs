@@ -1,54 +1,373 @@
-use crate::data::*;
+use crate::{data::*, worker::*};
 use anyhow::Result;
+use futures::channel::oneshot;
 use log::*;
 use semver::Version;
 use std::{
-    collections::BTreeMap,
+    cell::RefCell,
+    collections::{BTreeMap, VecDeque},
+    future::Future,
+    rc::Rc,
     sync::{Arc, Mutex},
 };
 
+mod storage;
+
+pub use self::storage::*;
+
+/// Approximate memory usage of a cached value, in bytes.
+///
+/// This is used to enforce the byte budget of the in-memory caches. It does not need to be
+/// exact, but it should account for the bulk of the data held by the value.
+pub trait CacheSize {
+    fn cache_size(&self) -> usize;
+}
+
+impl CacheSize for CrateResponse {
+    fn cache_size(&self) -> usize {
+        let detail = &self.krate;
+        let versions: usize = self
+            .versions
+            .iter()
+            .map(|version| {
+                std::mem::size_of::<VersionInfo>()
+                    + version.checksum.len()
+                    + version.krate.len()
+                    + version.dl_path.len()
+            })
+            .sum();
+        std::mem::size_of::<CrateResponse>() + detail.id.len() + detail.description.len() + versions
+    }
+}
+
+impl CacheSize for CrateSource {
+    fn cache_size(&self) -> usize {
+        let files: usize = self
+            .files
+            .iter()
+            .map(|(path, data)| path.as_str().len() + data.len())
+            .sum();
+        std::mem::size_of::<CrateSource>() + files
+    }
+}
+
+/// Statistics of an in-memory cache.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct CacheStats {
+    /// Number of lookups which found an entry.
+    pub hits: u64,
+    /// Number of lookups which did not find an entry.
+    pub misses: u64,
+    /// Number of entries which were evicted to stay within the budget.
+    pub evictions: u64,
+    /// Number of entries currently held.
+    pub entries: usize,
+    /// Approximate number of bytes currently held.
+    pub bytes: usize,
+    /// Maximum number of bytes to hold.
+    pub budget: usize,
+}
+
+/// Least-recently-used cache with a byte budget.
+///
+/// Every entry remembers the tick of its last access, and `order` maps these ticks back to the
+/// keys, so that the least recently used entry is always the first one in `order`.
+struct LruCache<K, V> {
+    entries: BTreeMap<K, (u64, usize, Arc<V>)>,
+    order: BTreeMap<u64, K>,
+    tick: u64,
+    stats: CacheStats,
+}
+
+impl<K: Ord + Clone, V: CacheSize> LruCache<K, V> {
+    const fn new(budget: usize) -> Self {
+        Self {
+            entries: BTreeMap::new(),
+            order: BTreeMap::new(),
+            tick: 0,
+            stats: CacheStats {
+                hits: 0,
+                misses: 0,
+                evictions: 0,
+                entries: 0,
+                bytes: 0,
+                budget,
+            },
+        }
+    }
+
+    fn next_tick(&mut self) -> u64 {
+        self.tick += 1;
+        self.tick
+    }
+
+    /// Lookup an entry, marking it as most recently used.
+    fn get(&mut self, key: &K) -> Option<Arc<V>> {
+        let tick = self.next_tick();
+        let Some((last, _, value)) = self.entries.get_mut(key) else {
+            self.stats.misses += 1;
+            return None;
+        };
+        self.stats.hits += 1;
+        let key = self.order.remove(last).unwrap();
+        self.order.insert(tick, key);
+        *last = tick;
+        Some(value.clone())
+    }
+
+    /// Lookup an entry without marking it as used or counting towards the statistics.
+    fn peek(&self, key: &K) -> Option<Arc<V>> {
+        self.entries.get(key).map(|(_, _, value)| value.clone())
+    }
+
+    /// Insert an entry, evicting least recently used entries until it fits.
+    ///
+    /// An entry which is larger than the budget is still stored, but it evicts all other entries.
+    fn insert(&mut self, key: K, value: Arc<V>) {
+        self.remove(&key);
+        let size = value.cache_size();
+        while !self.entries.is_empty() && self.stats.bytes + size > self.stats.budget {
+            self.evict();
+        }
+        let tick = self.next_tick();
+        self.order.insert(tick, key.clone());
+        self.entries.insert(key, (tick, size, value));
+        self.stats.bytes += size;
+        self.stats.entries = self.entries.len();
+    }
+
+    fn remove(&mut self, key: &K) -> Option<Arc<V>> {
+        let (tick, size, value) = self.entries.remove(key)?;
+        self.order.remove(&tick);
+        self.stats.bytes -= size;
+        self.stats.entries = self.entries.len();
+        Some(value)
+    }
+
+    /// Evict the least recently used entry.
+    fn evict(&mut self) {
+        if let Some((_, key)) = self.order.pop_first() {
+            let (_, size, _) = self.entries.remove(&key).unwrap();
+            self.stats.bytes -= size;
+            self.stats.evictions += 1;
+            self.stats.entries = self.entries.len();
+        }
+    }
+
+    /// Change the budget, evicting entries if needed.
+    fn set_budget(&mut self, budget: usize) {
+        self.stats.budget = budget;
+        while self.stats.bytes > budget {
+            self.evict();
+        }
+    }
+}
+
+/// Result of a fetch, shared with callers waiting for it.
+///
+/// Errors are not cloneable, so they are shared in their formatted form.
+type SharedResult<V> = Result<V, String>;
+
+/// Fetches which are currently in flight.
+///
+/// When a fetch for a key is already in flight, other callers for the same key register
+/// themselves as waiters and receive the result of that fetch, rather than starting an identical
+/// fetch themselves.
+struct InFlight<K, V>(Mutex<BTreeMap<K, Vec<oneshot::Sender<SharedResult<V>>>>>);
+
+impl<K: Ord + Clone, V: Clone> InFlight<K, V> {
+    const fn new() -> Self {
+        Self(Mutex::new(BTreeMap::new()))
+    }
+
+    /// Run the fetch for this key, or wait for the one already in flight.
+    async fn run<F, Fut>(&self, key: &K, fetch: F) -> Result<V>
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<V>>,
+    {
+        loop {
+            let receiver = {
+                let mut lock = self.0.lock().unwrap();
+                match lock.get_mut(key) {
+                    Some(waiters) => {
+                        let (sender, receiver) = oneshot::channel();
+                        waiters.push(sender);
+                        receiver
+                    }
+                    None => {
+                        lock.insert(key.clone(), vec![]);
+                        break;
+                    }
+                }
+            };
+
+            // if the fetch in flight is dropped before completing, the sender is dropped and we
+            // retry, possibly running the fetch ourselves.
+            if let Ok(result) = receiver.await {
+                return result.map_err(anyhow::Error::msg);
+            }
+        }
+
+        let guard = InFlightGuard {
+            in_flight: self,
+            key: Some(key.clone()),
+        };
+        let result = fetch().await;
+        let shared = match &result {
+            Ok(value) => Ok(value.clone()),
+            Err(error) => Err(format!("{error:#}")),
+        };
+        for waiter in guard.complete() {
+            let _ = waiter.send(shared.clone());
+        }
+        result
+    }
+
+    /// Number of keys currently in flight.
+    #[cfg(test)]
+    fn len(&self) -> usize {
+        self.0.lock().unwrap().len()
+    }
+}
+
+/// Removes the key from the in-flight map when the fetch completes or is dropped.
+struct InFlightGuard<'a, K: Ord, V> {
+    in_flight: &'a InFlight<K, V>,
+    key: Option<K>,
+}
+
+impl<K: Ord, V> InFlightGuard<'_, K, V> {
+    /// Mark fetch as complete, returning the waiters.
+    fn complete(mut self) -> Vec<oneshot::Sender<SharedResult<V>>> {
+        let key = self.key.take().unwrap();
+        let mut lock = self.in_flight.0.lock().unwrap();
+        lock.remove(&key).unwrap_or_default()
+    }
+}
+
+impl<K: Ord, V> Drop for InFlightGuard<'_, K, V> {
+    fn drop(&mut self) {
+        if let Some(key) = self.key.take() {
+            self.in_flight.0.lock().unwrap().remove(&key);
+        }
+    }
+}
+
 /// Crate response cache
-pub struct CrateResponseCache(Mutex<BTreeMap<String, Arc<CrateResponse>>>);
+pub struct CrateResponseCache {
+    cache: Mutex<LruCache<String, CrateResponse>>,
+    in_flight: InFlight<String, Arc<CrateResponse>>,
+}
 
 /// Global crate response cache instance
 pub static CRATE_RESPONSE_CACHE: CrateResponseCache = CrateResponseCache::new();
 
 impl CrateResponseCache {
-    /// Create new, empty cache
+    /// Default budget, 16 MiB.
+    pub const DEFAULT_BUDGET: usize = 16 * 1024 * 1024;
+
+    /// Create new, empty cache with the default budget
     pub const fn new() -> Self {
-        CrateResponseCache(Mutex::new(BTreeMap::new()))
+        Self::with_budget(Self::DEFAULT_BUDGET)
+    }
+
+    /// Create new, empty cache with the given budget in bytes
+    pub const fn with_budget(budget: usize) -> Self {
+        CrateResponseCache {
+            cache: Mutex::new(LruCache::new(budget)),
+            in_flight: InFlight::new(),
+        }
+    }
+
+    /// Get cache statistics
+    pub fn stats(&self) -> CacheStats {
+        self.cache.lock().unwrap().stats
     }
 
     /// Lookup in cache or fetch
     pub async fn fetch_cached(&self, name: &str) -> Result<Arc<CrateResponse>> {
+        self.fetch_cached_with(name, || Self::fetch(name)).await
+    }
+
+    /// Lookup in cache or fetch using the given fetcher
+    ///
+    /// Concurrent calls for the same crate share a single fetch.
+    async fn fetch_cached_with<F, Fut>(&self, name: &str, fetch: F) -> Result<Arc<CrateResponse>>
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<CrateResponse>>,
+    {
         if let Some(info) = self.cached(name) {
             info!("Fetching crate metadata for {name} from cache");
             return Ok(info);
         }
 
-        // fetch it
-        let info = CrateResponse::fetch(name).await?;
-        let info = Arc::new(info);
+        self.in_flight
+            .run(&name.to_string(), || async {
+                let info = Arc::new(fetch().await?);
+
+                // save back into cache
+                self.cache(info.clone());
+
+                Ok(info)
+            })
+            .await
+    }
 
-        // save back into cache
-        self.cache(info.clone());
+    /// Lookup in persistent cache or fetch from network
+    async fn fetch(name: &str) -> Result<CrateResponse> {
+        let key = CacheKey::Metadata { krate: name.into() };
+        if let Some(info) = Self::persisted(&key).await {
+            return Ok(info);
+        }
 
+        let info = CrateResponse::fetch(name).await?;
+        let result = match serde_json::to_vec(&info) {
+            Ok(data) => PERSISTENT_CACHE.store(&key, &data).await,
+            Err(error) => Err(error.into()),
+        };
+        if let Err(error) = result {
+            warn!("Error storing {key} in persistent cache: {error}");
+        }
         Ok(info)
     }
 
+    /// Lookup in persistent cache
+    async fn persisted(key: &CacheKey) -> Option<CrateResponse> {
+        let data = match PERSISTENT_CACHE.load(key).await {
+            Ok(data) => data?,
+            Err(error) => {
+                warn!("Error loading {key} from persistent cache: {error}");
+                return None;
+            }
+        };
+        match serde_json::from_slice(&data) {
+            Ok(info) => {
+                info!("Fetching {key} from persistent cache");
+                Some(info)
+            }
+            Err(error) => {
+                warn!("Error decoding {key} from persistent cache: {error}");
+                None
+            }
+        }
+    }
+
     /// Store in cache
     fn cache<T: Into<Arc<CrateResponse>>>(&self, response: T) {
-        let mut lock = self.0.lock().unwrap();
+        let mut lock = self.cache.lock().unwrap();
         let response: Arc<CrateResponse> = response.into();
         info!("Storing crate metadata for {} in cache", response.krate.id);
         lock.insert(response.krate.id.clone(), response);
+        debug!("Crate metadata cache: {:?}", lock.stats);
     }
 
     /// Lookup in cache
     pub fn cached(&self, name: &str) -> Option<Arc<CrateResponse>> {
         // check if we have it cached
-        let lock = self.0.lock().unwrap();
-        lock.get(name).cloned()
+        let mut lock = self.cache.lock().unwrap();
+        lock.get(&name.to_string())
     }
 }
 
@@ -82,20 +401,86 @@ fn test_crate_response_cache_store() {
     assert_eq!(crate_response, cache.cached("serde").unwrap());
 }
 
+/// Identity of a crate source: crate name, version and checksum.
+///
+/// The checksum tells apart crate sources which share the name and version, such as uploads.
+type SourceId = (String, Version, Vec<u8>);
+
+fn source_id(version: &VersionInfo) -> SourceId {
+    (
+        version.krate.clone(),
+        version.version.clone(),
+        version.checksum.clone(),
+    )
+}
+
 /// Crate source cache
-pub struct CrateSourceCache(Mutex<BTreeMap<(String, Version), Arc<CrateSource>>>);
+pub struct CrateSourceCache {
+    cache: Mutex<LruCache<SourceId, CrateSource>>,
+    in_flight: InFlight<SourceId, Arc<CrateSource>>,
+}
 
 /// Global crate source cache instance
 pub static CRATE_SOURCE_CACHE: CrateSourceCache = CrateSourceCache::new();
 
 impl CrateSourceCache {
-    /// Create new, empty cache
+    /// Default budget, 256 MiB.
+    pub const DEFAULT_BUDGET: usize = 256 * 1024 * 1024;
+
+    /// Create new, empty cache with the default budget
     pub const fn new() -> Self {
-        CrateSourceCache(Mutex::new(BTreeMap::new()))
+        Self::with_budget(Self::DEFAULT_BUDGET)
     }
 
-    /// Lookup in cache or fetch
-    pub async fn fetch_cached(&self, version: &VersionInfo) -> Result<Arc<CrateSource>> {
+    /// Create new, empty cache with the given budget in bytes
+    pub const fn with_budget(budget: usize) -> Self {
+        CrateSourceCache {
+            cache: Mutex::new(LruCache::new(budget)),
+            in_flight: InFlight::new(),
+        }
+    }
+
+    /// Change the budget in bytes, evicting entries if needed
+    pub fn set_budget(&self, budget: usize) {
+        self.cache.lock().unwrap().set_budget(budget);
+    }
+
+    /// Get cache statistics
+    pub fn stats(&self) -> CacheStats {
+        self.cache.lock().unwrap().stats
+    }
+
+    /// Fetch without storing in the cache
+    ///
+    /// Concurrent calls for the same crate version share a single fetch. This is used by the diff
+    /// worker, which hands the crate sources to the main thread rather than keeping them. The
+    /// archive is checked against the given limits, progress of fetching it is reported using the
+    /// `progress` callback.
+    pub async fn fetch_coalesced(
+        &self,
+        version: &VersionInfo,
+        limits: ArchiveLimits,
+        progress: &dyn Fn(Progress),
+    ) -> Result<Arc<CrateSource>> {
+        self.in_flight
+            .run(&source_id(version), || async {
+                Ok(Arc::new(Self::fetch(version, limits, progress).await?))
+            })
+            .await
+    }
+
+    /// Lookup in cache or fetch using the given fetcher
+    ///
+    /// Concurrent calls for the same crate version share a single fetch.
+    pub async fn fetch_cached_with<F, Fut>(
+        &self,
+        version: &VersionInfo,
+        fetch: F,
+    ) -> Result<Arc<CrateSource>>
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<CrateSource>>,
+    {
         if let Some(source) = self.cached(version) {
             info!(
                 "Fetching crate source for {} v{} from cache",
@@ -104,36 +489,220 @@ impl CrateSourceCache {
             return Ok(source);
         }
 
-        // fetch it
-        let source = version.fetch().await?;
-        let source = Arc::new(source);
+        let key = source_id(version);
+        self.in_flight
+            .run(&key, || async {
+                let source = fetch().await?;
 
-        // save back into cache
-        self.cache(source.clone());
+                // save back into cache
+                Ok(self.cache(source))
+            })
+            .await
+    }
 
+    /// Lookup in persistent cache or fetch from network, bypassing the in-memory cache
+    ///
+    /// The archive is checked against the given limits, progress is reported using the
+    /// `progress` callback.
+    pub async fn fetch(
+        version: &VersionInfo,
+        limits: ArchiveLimits,
+        progress: &dyn Fn(Progress),
+    ) -> Result<CrateSource> {
+        // the archive is verified again when loading it from the persistent cache.
+        let key = CacheKey::Source {
+            krate: version.krate.clone(),
+            version: version.version.clone(),
+            checksum: version.checksum.clone(),
+        };
+        if let Some(source) = Self::persisted(version, &key, limits, progress).await {
+            return Ok(source);
+        }
+
+        let decoder = version
+            .fetch_archive(limits, |bytes, total| {
+                progress(Progress::Downloading {
+                    krate: version.krate.clone(),
+                    version: version.version.clone(),
+                    bytes,
+                    total,
+                })
+            })
+            .await?;
+        let data = decoder.data().to_vec();
+        let source = decoder.finish(version.clone(), Self::unpack_progress(version, progress))?;
+        if let Err(error) = PERSISTENT_CACHE.store(&key, &data).await {
+            warn!("Error storing {key} in persistent cache: {error}");
+        }
         Ok(source)
     }
 
+    /// Lookup in persistent cache
+    async fn persisted(
+        version: &VersionInfo,
+        key: &CacheKey,
+        limits: ArchiveLimits,
+        progress: &dyn Fn(Progress),
+    ) -> Option<CrateSource> {
+        let data = match PERSISTENT_CACHE.load(key).await {
+            Ok(data) => data?,
+            Err(error) => {
+                warn!("Error loading {key} from persistent cache: {error}");
+                return None;
+            }
+        };
+        match CrateSource::new_with_progress(
+            version.clone(),
+            &data[..],
+            limits,
+            Self::unpack_progress(version, progress),
+        ) {
+            Ok(source) => {
+                info!("Fetching {key} from persistent cache");
+                Some(source)
+            }
+            Err(error) => {
+                warn!("Error parsing {key} from persistent cache: {error}");
+                None
+            }
+        }
+    }
+
+    /// Progress callback for unpacking the archive of the given version
+    fn unpack_progress<'a>(
+        version: &'a VersionInfo,
+        progress: &'a dyn Fn(Progress),
+    ) -> impl FnMut(usize) + 'a {
+        move |files| {
+            if files % PROGRESS_INTERVAL == 0 {
+                progress(Progress::Unpacking {
+                    krate: version.krate.clone(),
+                    version: version.version.clone(),
+                    files,
+                });
+            }
+        }
+    }
+
     /// Store in cache
-    fn cache<T: Into<Arc<CrateSource>>>(&self, source: T) {
-        let mut lock = self.0.lock().unwrap();
+    ///
+    /// If the same crate source is already cached, the cached instance is kept and returned.
+    pub fn cache<T: Into<Arc<CrateSource>>>(&self, source: T) -> Arc<CrateSource> {
+        let mut lock = self.cache.lock().unwrap();
         let source: Arc<CrateSource> = source.into();
+        let key = source_id(&source.version);
+        if let Some(cached) = lock.peek(&key) {
+            if cached.version == source.version {
+                return cached;
+            }
+        }
         info!(
             "Storing crate source {} v{} in cache",
             source.version.krate, source.version.version
         );
-        lock.insert(
-            (source.version.krate.clone(), source.version.version.clone()),
-            source,
-        );
+        lock.insert(key, source.clone());
+        debug!("Crate source cache: {:?}", lock.stats);
+        source
     }
 
     /// Lookup in cache
     pub fn cached(&self, version: &VersionInfo) -> Option<Arc<CrateSource>> {
         // check if we have it cached
-        let lock = self.0.lock().unwrap();
-        lock.get(&(version.krate.clone(), version.version.clone()))
-            .cloned()
+        let mut lock = self.cache.lock().unwrap();
+        lock.get(&source_id(version))
+    }
+}
+
+/// Identity of a pair of crate sources that are diffed.
+type DiffId = (SourceId, SourceId);
+
+/// Version diff cache
+///
+/// Keeps the most recently used version diffs, so that navigating between files or returning to
+/// a previously viewed pair of versions does not recompute the diff. Version diffs compute file
+/// diffs lazily and are not thread-safe, so unlike the other caches this one is thread-local.
+pub struct VersionDiffCache {
+    entries: RefCell<VecDeque<(DiffId, Rc<VersionDiff>)>>,
+    capacity: usize,
+    in_flight: InFlight<DiffId, Rc<VersionDiff>>,
+}
+
+thread_local! {
+    /// Global version diff cache instance
+    ///
+    /// This is reference counted, so that it can be used across await points.
+    pub static VERSION_DIFF_CACHE: Rc<VersionDiffCache> = Rc::new(VersionDiffCache::new(8));
+}
+
+impl VersionDiffCache {
+    /// Create new, empty cache holding at most `capacity` diffs
+    pub const fn new(capacity: usize) -> Self {
+        VersionDiffCache {
+            entries: RefCell::new(VecDeque::new()),
+            capacity,
+            in_flight: InFlight::new(),
+        }
+    }
+
+    /// Lookup in cache or compute using the given function
+    ///
+    /// Concurrent calls for the same pair of crate versions share a single computation. Cached
+    /// diffs which were computed with other limits are computed again, as different files may be
+    /// too large to diff.
+    pub async fn fetch_cached_with<F, Fut>(
+        &self,
+        left: &VersionInfo,
+        right: &VersionInfo,
+        limits: &ArchiveLimits,
+        compute: F,
+    ) -> Result<Rc<VersionDiff>>
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<Rc<VersionDiff>>>,
+    {
+        if let Some(diff) = self
+            .cached(left, right)
+            .filter(|diff| diff.limits == *limits)
+        {
+            return Ok(diff);
+        }
+
+        let key = (source_id(left), source_id(right));
+        self.in_flight
+            .run(&key, || async {
+                let diff = compute().await?;
+
+                // save back into cache
+                self.insert(diff.clone());
+                Ok(diff)
+            })
+            .await
+    }
+
+    /// Lookup in cache
+    pub fn cached(&self, left: &VersionInfo, right: &VersionInfo) -> Option<Rc<VersionDiff>> {
+        let key = (source_id(left), source_id(right));
+        let mut entries = self.entries.borrow_mut();
+
+        // move entry to the front if it exists
+        let index = entries.iter().position(|(other, _)| *other == key)?;
+        let entry = entries.remove(index).unwrap();
+        let diff = entry.1.clone();
+        entries.push_front(entry);
+        info!("Fetching diff for {} and {} from cache", key.0 .0, key.1 .0);
+        Some(diff)
+    }
+
+    /// Store in cache, evicting the least recently used diff if needed
+    pub fn insert(&self, diff: Rc<VersionDiff>) {
+        let key = (
+            source_id(&diff.left.version),
+            source_id(&diff.right.version),
+        );
+        let mut entries = self.entries.borrow_mut();
+        entries.retain(|(other, _)| *other != key);
+        entries.push_front((key, diff));
+        entries.truncate(self.capacity);
     }
 }
 
@@ -146,6 +715,8 @@ fn test_crate_source_cache_missing() {
         krate: "serde".into(),
         version: "0.1.0".parse().unwrap(),
         yanked: false,
+        crate_size: None,
+        created_at: None,
     };
     assert!(cache.cached(&version).is_none());
 }
@@ -159,12 +730,376 @@ fn test_crate_source_cache_store() {
         krate: "serde".into(),
         version: "0.1.0".parse().unwrap(),
         yanked: false,
+        crate_size: None,
+        created_at: None,
     };
     assert!(cache.cached(&version).is_none());
     let source = Arc::new(CrateSource {
         version: version.clone(),
         files: Default::default(),
+        warnings: Default::default(),
     });
     cache.cache(source.clone());
     assert_eq!(source, cache.cached(&version).unwrap());
 }
+
+#[cfg(test)]
+fn test_source(version: &str, files: &[(&str, usize)]) -> Arc<CrateSource> {
+    Arc::new(CrateSource {
+        version: VersionInfo {
+            checksum: "abc".into(),
+            dl_path: "/path".into(),
+            krate: "serde".into(),
+            version: version.parse().unwrap(),
+            yanked: false,
+            crate_size: None,
+            created_at: None,
+        },
+        files: files
+            .iter()
+            .map(|(path, size)| ((*path).into(), vec![0; *size].into()))
+            .collect(),
+        warnings: Default::default(),
+    })
+}
+
+#[test]
+fn test_crate_source_cache_size() {
+    let source = test_source("0.1.0", &[("Cargo.toml", 100), ("src/lib.rs", 1000)]);
+    assert_eq!(
+        source.cache_size(),
+        std::mem::size_of::<CrateSource>() + "Cargo.toml".len() + 100 + "src/lib.rs".len() + 1000
+    );
+}
+
+#[test]
+fn test_crate_source_cache_checks_checksum() {
+    let cache = CrateSourceCache::new();
+    let source = test_source("0.1.0", &[("src/lib.rs", 1000)]);
+    cache.cache(source.clone());
+
+    // a crate source of the same name and version with another checksum, such as an upload
+    let mut other = source.version.clone();
+    other.checksum = "def".into();
+    assert!(cache.cached(&other).is_none());
+    assert!(cache.cached(&source.version).is_some());
+}
+
+#[test]
+fn test_crate_source_cache_stats() {
+    let cache = CrateSourceCache::new();
+    let source = test_source("0.1.0", &[("src/lib.rs", 1000)]);
+    assert!(cache.cached(&source.version).is_none());
+    cache.cache(source.clone());
+    assert!(cache.cached(&source.version).is_some());
+    assert!(cache.cached(&source.version).is_some());
+    assert_eq!(
+        cache.stats(),
+        CacheStats {
+            hits: 2,
+            misses: 1,
+            evictions: 0,
+            entries: 1,
+            bytes: source.cache_size(),
+            budget: CrateSourceCache::DEFAULT_BUDGET,
+        }
+    );
+}
+
+#[test]
+fn test_crate_source_cache_evicts_least_recently_used() {
+    let first = test_source("0.1.0", &[("src/lib.rs", 1000)]);
+    let second = test_source("0.2.0", &[("src/lib.rs", 1000)]);
+    let third = test_source("0.3.0", &[("src/lib.rs", 1000)]);
+    let cache = CrateSourceCache::with_budget(first.cache_size() * 2);
+    cache.cache(first.clone());
+    cache.cache(second.clone());
+
+    // access first, so that second is the least recently used
+    assert!(cache.cached(&first.version).is_some());
+    cache.cache(third.clone());
+
+    assert!(cache.cached(&first.version).is_some());
+    assert!(cache.cached(&second.version).is_none());
+    assert!(cache.cached(&third.version).is_some());
+    assert_eq!(cache.stats().evictions, 1);
+    assert_eq!(cache.stats().entries, 2);
+    assert!(cache.stats().bytes <= cache.stats().budget);
+}
+
+#[test]
+fn test_crate_source_cache_oversized_entry() {
+    let small = test_source("0.1.0", &[("src/lib.rs", 10)]);
+    let large = test_source("0.2.0", &[("src/lib.rs", 10000)]);
+    let cache = CrateSourceCache::with_budget(large.cache_size() / 2);
+    cache.cache(small.clone());
+    cache.cache(large.clone());
+
+    // the most recently inserted entry is always kept, even if it exceeds the budget
+    assert!(cache.cached(&small.version).is_none());
+    assert_eq!(large, cache.cached(&large.version).unwrap());
+    assert_eq!(cache.stats().entries, 1);
+}
+
+#[test]
+fn test_crate_source_cache_set_budget() {
+    let first = test_source("0.1.0", &[("src/lib.rs", 1000)]);
+    let second = test_source("0.2.0", &[("src/lib.rs", 1000)]);
+    let cache = CrateSourceCache::new();
+    cache.cache(first.clone());
+    cache.cache(second.clone());
+    assert_eq!(cache.stats().entries, 2);
+
+    cache.set_budget(second.cache_size());
+    assert_eq!(cache.stats().entries, 1);
+    assert!(cache.cached(&first.version).is_none());
+    assert!(cache.cached(&second.version).is_some());
+}
+
+#[test]
+fn test_crate_response_cache_evicts() {
+    let response = |name: &str| {
+        let mut response = parse_canned_log();
+        response.krate.id = name.into();
+        Arc::new(response)
+    };
+    let log = response("log");
+    let cache = CrateResponseCache::with_budget(log.cache_size());
+    cache.cache(log.clone());
+    cache.cache(response("other"));
+    assert!(cache.cached("log").is_none());
+    assert!(cache.cached("other").is_some());
+    assert_eq!(cache.stats().evictions, 1);
+}
+
+#[cfg(test)]
+fn parse_canned_log() -> CrateResponse {
+    let file = std::fs::File::open("data/log.json").unwrap();
+    serde_json::from_reader(file).unwrap()
+}
+
+/// Future which completes once the sender sends.
+///
+/// Used by mock fetchers to stay pending until the test lets them complete.
+#[cfg(test)]
+fn gate() -> (
+    oneshot::Sender<()>,
+    futures::future::Shared<oneshot::Receiver<()>>,
+) {
+    use futures::FutureExt;
+    let (sender, receiver) = oneshot::channel();
+    (sender, receiver.shared())
+}
+
+#[test]
+fn test_crate_response_cache_coalesces_fetches() {
+    let cache = CrateResponseCache::new();
+    let fetches = std::cell::Cell::new(0);
+    let (open, gated) = gate();
+    let fetch = || async {
+        fetches.set(fetches.get() + 1);
+        gated.clone().await.unwrap();
+        Ok(parse_canned_log())
+    };
+
+    let (first, second, ()) = futures::executor::block_on(async {
+        futures::join!(
+            cache.fetch_cached_with("log", fetch),
+            cache.fetch_cached_with("log", fetch),
+            async {
+                open.send(()).unwrap();
+            }
+        )
+    });
+
+    assert_eq!(fetches.get(), 1);
+    assert!(Arc::ptr_eq(&first.unwrap(), &second.unwrap()));
+    assert_eq!(cache.in_flight.len(), 0);
+    assert!(cache.cached("log").is_some());
+}
+
+#[test]
+fn test_crate_response_cache_shares_errors() {
+    let cache = CrateResponseCache::new();
+    let fetches = std::cell::Cell::new(0);
+    let (open, gated) = gate();
+    let fetch = || async {
+        fetches.set(fetches.get() + 1);
+        gated.clone().await.unwrap();
+        Err(anyhow::anyhow!("crate not found"))
+    };
+
+    let (first, second, ()) = futures::executor::block_on(async {
+        futures::join!(
+            cache.fetch_cached_with("log", fetch),
+            cache.fetch_cached_with("log", fetch),
+            async {
+                open.send(()).unwrap();
+            }
+        )
+    });
+
+    assert_eq!(fetches.get(), 1);
+    assert_eq!(first.unwrap_err().to_string(), "crate not found");
+    assert_eq!(second.unwrap_err().to_string(), "crate not found");
+    assert_eq!(cache.in_flight.len(), 0);
+    assert!(cache.cached("log").is_none());
+}
+
+#[test]
+fn test_crate_source_cache_coalesces_fetches() {
+    let cache = CrateSourceCache::new();
+    let source = test_source("0.1.0", &[("src/lib.rs", 1000)]);
+    let other = test_source("0.2.0", &[("src/lib.rs", 1000)]);
+    let fetches = std::cell::Cell::new(0);
+    let (open, gated) = gate();
+    let fetch = |source: &Arc<CrateSource>| {
+        let source = (**source).clone();
+        let gated = gated.clone();
+        let fetches = &fetches;
+        move || async move {
+            fetches.set(fetches.get() + 1);
+            gated.await.unwrap();
+            Ok(source)
+        }
+    };
+
+    let (first, second, third, ()) = futures::executor::block_on(async {
+        futures::join!(
+            cache.fetch_cached_with(&source.version, fetch(&source)),
+            cache.fetch_cached_with(&source.version, fetch(&source)),
+            cache.fetch_cached_with(&other.version, fetch(&other)),
+            async {
+                open.send(()).unwrap();
+            }
+        )
+    });
+
+    // different versions are fetched separately
+    assert_eq!(fetches.get(), 2);
+    let (first, second) = (first.unwrap(), second.unwrap());
+    assert!(Arc::ptr_eq(&first, &second));
+    assert_eq!(first, source);
+    assert_eq!(third.unwrap(), other);
+    assert_eq!(cache.in_flight.len(), 0);
+}
+
+#[test]
+fn test_crate_source_cache_retries_dropped_fetch() {
+    use futures::FutureExt;
+
+    let cache = CrateSourceCache::new();
+    let source = test_source("0.1.0", &[("src/lib.rs", 1000)]);
+    let fetches = std::cell::Cell::new(0);
+    let fetch = || async {
+        fetches.set(fetches.get() + 1);
+        Ok((*source).clone())
+    };
+
+    futures::executor::block_on(async {
+        // start a fetch which never completes, and drop it while a second caller waits on it
+        let mut pending =
+            Box::pin(cache.fetch_cached_with(&source.version, futures::future::pending));
+        assert!((&mut pending).now_or_never().is_none());
+        let mut waiting = Box::pin(cache.fetch_cached_with(&source.version, fetch));
+        assert!((&mut waiting).now_or_never().is_none());
+        drop(pending);
+
+        // the waiting caller runs the fetch itself
+        assert_eq!(waiting.await.unwrap(), source);
+    });
+
+    assert_eq!(fetches.get(), 1);
+    assert_eq!(cache.in_flight.len(), 0);
+}
+
+#[test]
+fn test_version_diff_cache_reuses_diff() {
+    let cache = VersionDiffCache::new(2);
+    let first = test_source("0.1.0", &[("src/lib.rs", 10)]);
+    let second = test_source("0.2.0", &[("src/lib.rs", 20)]);
+    assert!(cache.cached(&first.version, &second.version).is_none());
+
+    let diff = Rc::new(VersionDiff::new(first.clone(), second.clone()));
+    cache.insert(diff.clone());
+    assert!(Rc::ptr_eq(
+        &diff,
+        &cache.cached(&first.version, &second.version).unwrap()
+    ));
+
+    // diffs are directional
+    assert!(cache.cached(&second.version, &first.version).is_none());
+}
+
+#[test]
+fn test_version_diff_cache_evicts_least_recently_used() {
+    let cache = VersionDiffCache::new(2);
+    let sources: Vec<_> = ["0.1.0", "0.2.0", "0.3.0"]
+        .iter()
+        .map(|version| test_source(version, &[("src/lib.rs", 10)]))
+        .collect();
+    let diff = |left: usize, right: usize| {
+        let diff = Rc::new(VersionDiff::new(
+            sources[left].clone(),
+            sources[right].clone(),
+        ));
+        cache.insert(diff.clone());
+        diff
+    };
+    let first = diff(0, 1);
+    diff(1, 2);
+
+    // access first, so that second is the least recently used
+    cache.cached(&sources[0].version, &sources[1].version);
+    diff(0, 2);
+
+    assert!(Rc::ptr_eq(
+        &first,
+        &cache
+            .cached(&sources[0].version, &sources[1].version)
+            .unwrap()
+    ));
+    assert!(cache
+        .cached(&sources[1].version, &sources[2].version)
+        .is_none());
+}
+
+#[test]
+fn test_version_diff_cache_coalesces_computations() {
+    let cache = VersionDiffCache::new(2);
+    let first = test_source("0.1.0", &[("src/lib.rs", 10)]);
+    let second = test_source("0.2.0", &[("src/lib.rs", 20)]);
+    let computations = std::cell::Cell::new(0);
+    let (open, gated) = gate();
+    let compute = || async {
+        computations.set(computations.get() + 1);
+        gated.clone().await.unwrap();
+        Ok(Rc::new(VersionDiff::new(first.clone(), second.clone())))
+    };
+
+    let limits = ArchiveLimits::DEFAULT;
+    let (left, right) = (&first.version, &second.version);
+    let (one, two, ()) = futures::executor::block_on(async {
+        futures::join!(
+            cache.fetch_cached_with(left, right, &limits, compute),
+            cache.fetch_cached_with(left, right, &limits, compute),
+            async {
+                open.send(()).unwrap();
+            }
+        )
+    });
+
+    assert_eq!(computations.get(), 1);
+    let diff = one.unwrap();
+    assert!(Rc::ptr_eq(&diff, &two.unwrap()));
+    assert_eq!(cache.in_flight.len(), 0);
+    assert!(Rc::ptr_eq(&diff, &cache.cached(left, right).unwrap()));
+
+    // diffs computed with other limits are computed again
+    let limits = ArchiveLimits {
+        max_diff_size: 1,
+        ..ArchiveLimits::DEFAULT
+    };
+    futures::executor::block_on(cache.fetch_cached_with(left, right, &limits, compute)).unwrap();
+    assert_eq!(computations.get(), 2);
+}
@@ -0,0 +1,669 @@
+//! Persistent cache tier.
+//!
+//! The in-memory caches are lost whenever the page is reloaded. This module implements a second
+//! tier which stores crate metadata and crate archives persistently: in IndexedDB when running in
+//! the browser, and in a directory when running natively (for example, in tests).
+//!
+//! Entries are stored alongside some metadata (size, time of storage and time of last access),
+//! which is used to expire crate metadata after some time and to evict the least recently used
+//! entries once the configured size limit is reached. Crate archives never expire, because
+//! published crate versions are immutable.
+
+use anyhow::Result;
+use bytes::Bytes;
+use log::*;
+use semver::Version;
+use serde::{Deserialize, Serialize};
+use std::fmt::{Display, Formatter, Result as FmtResult};
+
+/// Kind of cache entry.
+///
+/// This determines whether an entry expires.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum EntryKind {
+    /// Crate metadata, as returned by the crates.io API. New versions can be published at any
+    /// time, so these entries expire.
+    Metadata,
+    /// Crate archive. Published crate versions are immutable, so these entries never expire.
+    Source,
+}
+
+/// Key of a persistent cache entry.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum CacheKey {
+    /// Metadata for the crate with the given name.
+    Metadata { krate: String },
+    /// Archive of the given crate version, with the expected checksum.
+    Source {
+        krate: String,
+        version: Version,
+        checksum: Vec<u8>,
+    },
+}
+
+impl CacheKey {
+    /// Kind of this entry.
+    pub fn kind(&self) -> EntryKind {
+        match self {
+            Self::Metadata { .. } => EntryKind::Metadata,
+            Self::Source { .. } => EntryKind::Source,
+        }
+    }
+}
+
+impl Display for CacheKey {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        match self {
+            Self::Metadata { krate } => write!(f, "metadata/{krate}"),
+            Self::Source {
+                krate,
+                version,
+                checksum,
+            } => write!(f, "source/{krate}/{version}/{}", hex::encode(checksum)),
+        }
+    }
+}
+
+/// Metadata of an entry in the persistent cache.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct CacheEntry {
+    /// Key of this entry, see [`CacheKey`].
+    pub key: String,
+    /// Kind of entry.
+    pub kind: EntryKind,
+    /// Size of the stored data, in bytes.
+    pub size: u64,
+    /// Time this entry was stored, in milliseconds since the Unix epoch.
+    pub stored: u64,
+    /// Time this entry was last accessed, in milliseconds since the Unix epoch.
+    pub accessed: u64,
+}
+
+impl CacheEntry {
+    /// Determines if this entry has expired.
+    pub fn is_expired(&self, limits: &CacheLimits, now: u64) -> bool {
+        match self.kind {
+            EntryKind::Metadata => now.saturating_sub(self.stored) > limits.metadata_ttl,
+            EntryKind::Source => false,
+        }
+    }
+}
+
+/// Limits for the persistent cache.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CacheLimits {
+    /// Maximum total size of all stored entries, in bytes.
+    pub max_bytes: u64,
+    /// Time after which metadata entries expire, in milliseconds.
+    pub metadata_ttl: u64,
+}
+
+impl CacheLimits {
+    /// Default limits: 256 MiB of storage, metadata expires after 30 minutes.
+    pub const DEFAULT: Self = Self {
+        max_bytes: 256 * 1024 * 1024,
+        metadata_ttl: 30 * 60 * 1000,
+    };
+}
+
+impl Default for CacheLimits {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+/// Persistent cache.
+pub struct PersistentCache {
+    name: &'static str,
+    limits: CacheLimits,
+    /// Directory the cache is stored in, instead of the default one.
+    #[cfg(not(target_arch = "wasm32"))]
+    directory: Option<std::path::PathBuf>,
+}
+
+/// Global persistent cache instance
+pub static PERSISTENT_CACHE: PersistentCache =
+    PersistentCache::new("diff-rs-cache", CacheLimits::DEFAULT);
+
+impl PersistentCache {
+    /// Create new persistent cache handle with the given database name and limits.
+    pub const fn new(name: &'static str, limits: CacheLimits) -> Self {
+        Self {
+            name,
+            limits,
+            #[cfg(not(target_arch = "wasm32"))]
+            directory: None,
+        }
+    }
+
+    /// Open the storage backend of this cache.
+    #[cfg(target_arch = "wasm32")]
+    async fn open(&self) -> Result<Store> {
+        Store::open(self.name).await
+    }
+
+    /// Open the storage backend of this cache.
+    #[cfg(not(target_arch = "wasm32"))]
+    async fn open(&self) -> Result<Store> {
+        Store::open(self.name, self.directory.as_deref()).await
+    }
+
+    /// Limits of this cache.
+    pub fn limits(&self) -> &CacheLimits {
+        &self.limits
+    }
+
+    /// Lookup data in the cache.
+    ///
+    /// Returns `None` if there is no entry for the key, or if the entry has expired.
+    pub async fn load(&self, key: &CacheKey) -> Result<Option<Bytes>> {
+        self.load_at(key, now()).await
+    }
+
+    /// Lookup data in the cache at the given time, in milliseconds since the Unix epoch.
+    async fn load_at(&self, key: &CacheKey, now: u64) -> Result<Option<Bytes>> {
+        let key = key.to_string();
+        let store = self.open().await?;
+        let Some(mut entry) = store.entry(&key).await? else {
+            return Ok(None);
+        };
+
+        if entry.is_expired(&self.limits, now) {
+            debug!("Persistent cache entry {key} has expired");
+            store.remove(&key).await?;
+            return Ok(None);
+        }
+
+        let Some(data) = store.data(&key).await? else {
+            warn!("Persistent cache entry {key} is missing data");
+            store.remove(&key).await?;
+            return Ok(None);
+        };
+
+        entry.accessed = now;
+        store.put_entry(&entry).await?;
+        Ok(Some(data))
+    }
+
+    /// Store data in the cache.
+    ///
+    /// Evicts expired entries, and then least recently used entries, until the data fits within
+    /// the size limit.
+    pub async fn store(&self, key: &CacheKey, data: &[u8]) -> Result<()> {
+        self.store_at(key, data, now()).await
+    }
+
+    /// Store data in the cache at the given time, in milliseconds since the Unix epoch.
+    async fn store_at(&self, key: &CacheKey, data: &[u8], now: u64) -> Result<()> {
+        let size = data.len() as u64;
+        if size > self.limits.max_bytes {
+            info!("Not storing {key} in persistent cache, exceeds size limit");
+            return Ok(());
+        }
+
+        let key_string = key.to_string();
+        let store = self.open().await?;
+
+        // determine which entries to evict, oldest first
+        let mut entries = store.entries().await?;
+        entries.retain(|entry| entry.key != key_string);
+        entries.sort_by_key(|entry| (!entry.is_expired(&self.limits, now), entry.accessed));
+        let mut total: u64 = entries.iter().map(|entry| entry.size).sum();
+        for entry in &entries {
+            if total + size <= self.limits.max_bytes && !entry.is_expired(&self.limits, now) {
+                break;
+            }
+            info!("Evicting {} from persistent cache", entry.key);
+            store.remove(&entry.key).await?;
+            total -= entry.size;
+        }
+
+        let entry = CacheEntry {
+            key: key_string,
+            kind: key.kind(),
+            size,
+            stored: now,
+            accessed: now,
+        };
+        info!("Storing {} in persistent cache ({size} bytes)", entry.key);
+        store.put(&entry, data).await
+    }
+
+    /// List all entries in the cache, most recently used first.
+    pub async fn entries(&self) -> Result<Vec<CacheEntry>> {
+        let store = self.open().await?;
+        let mut entries = store.entries().await?;
+        entries.sort_by_key(|entry| std::cmp::Reverse(entry.accessed));
+        Ok(entries)
+    }
+
+    /// Remove a single entry from the cache.
+    pub async fn remove(&self, key: &str) -> Result<()> {
+        self.open().await?.remove(key).await
+    }
+
+    /// Remove all entries from the cache.
+    pub async fn clear(&self) -> Result<()> {
+        info!("Clearing persistent cache");
+        self.open().await?.clear().await
+    }
+}
+
+/// Current time, in milliseconds since the Unix epoch.
+#[cfg(target_arch = "wasm32")]
+pub fn now() -> u64 {
+    js_sys::Date::now() as u64
+}
+
+/// Current time, in milliseconds since the Unix epoch.
+#[cfg(not(target_arch = "wasm32"))]
+pub fn now() -> u64 {
+    use std::time::{SystemTime, UNIX_EPOCH};
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_millis() as u64)
+        .unwrap_or_default()
+}
+
+#[cfg(target_arch = "wasm32")]
+use self::indexed_db::Store;
+
+#[cfg(not(target_arch = "wasm32"))]
+use self::directory::Store;
+
+/// IndexedDB storage backend, used in the browser.
+///
+/// Uses two object stores: one for the entry metadata, and one for the data. This allows listing
+/// the entries without having to load all of the data.
+#[cfg(target_arch = "wasm32")]
+mod indexed_db {
+    use super::CacheEntry;
+    use anyhow::{anyhow, Result};
+    use bytes::Bytes;
+    use futures::channel::oneshot;
+    use js_sys::{Array, Reflect, Uint8Array};
+    use std::{cell::RefCell, rc::Rc};
+    use wasm_bindgen::{closure::Closure, JsCast, JsValue};
+    use web_sys::{
+        IdbDatabase, IdbFactory, IdbObjectStore, IdbRequest, IdbTransaction, IdbTransactionMode,
+    };
+
+    const DATABASE_VERSION: u32 = 1;
+    const ENTRIES: &str = "entries";
+    const DATA: &str = "data";
+
+    /// Convert a JavaScript error into an [`anyhow::Error`].
+    fn js_error(error: JsValue) -> anyhow::Error {
+        anyhow!("IndexedDB error: {error:?}")
+    }
+
+    /// Wait for an IndexedDB request to complete and return its result.
+    async fn complete(request: &IdbRequest) -> Result<JsValue> {
+        let (sender, receiver) = oneshot::channel::<bool>();
+        let sender = Rc::new(RefCell::new(Some(sender)));
+        let callback = |success| {
+            let sender = sender.clone();
+            Closure::<dyn FnMut()>::new(move || {
+                if let Some(sender) = sender.borrow_mut().take() {
+                    let _ = sender.send(success);
+                }
+            })
+        };
+
+        // closures need to stay alive until the request completes
+        let onsuccess = callback(true);
+        let onerror = callback(false);
+        request.set_onsuccess(Some(onsuccess.as_ref().unchecked_ref()));
+        request.set_onerror(Some(onerror.as_ref().unchecked_ref()));
+        let success = receiver.await?;
+        request.set_onsuccess(None);
+        request.set_onerror(None);
+
+        if success {
+            request.result().map_err(js_error)
+        } else {
+            Err(request
+                .error()
+                .map_err(js_error)?
+                .map(|error| anyhow!("IndexedDB error: {}", error.message()))
+                .unwrap_or_else(|| anyhow!("IndexedDB request failed")))
+        }
+    }
+
+    /// Handle to the IndexedDB database.
+    pub struct Store(IdbDatabase);
+
+    impl Store {
+        /// Open the database, creating the object stores if needed.
+        pub async fn open(name: &str) -> Result<Self> {
+            // look up the factory on the global object, so that this also works in workers
+            let factory: IdbFactory = Reflect::get(&js_sys::global(), &"indexedDB".into())
+                .map_err(js_error)?
+                .dyn_into()
+                .map_err(|_| anyhow!("IndexedDB is not available"))?;
+            let request = factory
+                .open_with_u32(name, DATABASE_VERSION)
+                .map_err(js_error)?;
+
+            let onupgradeneeded = {
+                let request = request.clone();
+                Closure::<dyn FnMut()>::new(move || {
+                    let Ok(database) = request.result() else {
+                        return;
+                    };
+                    let database: IdbDatabase = database.unchecked_into();
+                    let names = database.object_store_names();
+                    for store in [ENTRIES, DATA] {
+                        if !names.contains(store) {
+                            let _ = database.create_object_store(store);
+                        }
+                    }
+                })
+            };
+            request.set_onupgradeneeded(Some(onupgradeneeded.as_ref().unchecked_ref()));
+            let database = complete(&request).await?;
+            request.set_onupgradeneeded(None);
+
+            Ok(Self(database.unchecked_into()))
+        }
+
+        fn store(&self, name: &str, mode: IdbTransactionMode) -> Result<IdbObjectStore> {
+            self.0
+                .transaction_with_str_and_mode(name, mode)
+                .map_err(js_error)?
+                .object_store(name)
+                .map_err(js_error)
+        }
+
+        /// Start a transaction which writes both object stores, so that entries and their data
+        /// are always changed together.
+        fn write_both(&self) -> Result<IdbTransaction> {
+            let names = Array::of2(&ENTRIES.into(), &DATA.into());
+            self.0
+                .transaction_with_str_sequence_and_mode(&names, IdbTransactionMode::Readwrite)
+                .map_err(js_error)
+        }
+
+        pub async fn entry(&self, key: &str) -> Result<Option<CacheEntry>> {
+            let store = self.store(ENTRIES, IdbTransactionMode::Readonly)?;
+            let value = complete(&store.get(&key.into()).map_err(js_error)?).await?;
+            match value.as_string() {
+                Some(value) => Ok(Some(serde_json::from_str(&value)?)),
+                None => Ok(None),
+            }
+        }
+
+        pub async fn entries(&self) -> Result<Vec<CacheEntry>> {
+            let store = self.store(ENTRIES, IdbTransactionMode::Readonly)?;
+            let values: Array = complete(&store.get_all().map_err(js_error)?)
+                .await?
+                .unchecked_into();
+            values
+                .iter()
+                .filter_map(|value| value.as_string())
+                .map(|value| Ok(serde_json::from_str(&value)?))
+                .collect()
+        }
+
+        pub async fn data(&self, key: &str) -> Result<Option<Bytes>> {
+            let store = self.store(DATA, IdbTransactionMode::Readonly)?;
+            let value = complete(&store.get(&key.into()).map_err(js_error)?).await?;
+            Ok(value
+                .dyn_into::<Uint8Array>()
+                .ok()
+                .map(|array| array.to_vec().into()))
+        }
+
+        pub async fn put_entry(&self, entry: &CacheEntry) -> Result<()> {
+            let store = self.store(ENTRIES, IdbTransactionMode::Readwrite)?;
+            let value = serde_json::to_string(entry)?;
+            let request = store
+                .put_with_key(&value.into(), &entry.key.as_str().into())
+                .map_err(js_error)?;
+            complete(&request).await?;
+            Ok(())
+        }
+
+        pub async fn put(&self, entry: &CacheEntry, data: &[u8]) -> Result<()> {
+            let transaction = self.write_both()?;
+            let key = entry.key.as_str().into();
+            let data = Uint8Array::from(data).into();
+            let entry = serde_json::to_string(entry)?.into();
+            let requests = [
+                transaction
+                    .object_store(DATA)
+                    .map_err(js_error)?
+                    .put_with_key(&data, &key),
+                transaction
+                    .object_store(ENTRIES)
+                    .map_err(js_error)?
+                    .put_with_key(&entry, &key),
+            ];
+            for request in requests {
+                complete(&request.map_err(js_error)?).await?;
+            }
+            Ok(())
+        }
+
+        pub async fn remove(&self, key: &str) -> Result<()> {
+            let transaction = self.write_both()?;
+            let requests = [ENTRIES, DATA]
+                .into_iter()
+                .map(|name| transaction.object_store(name)?.delete(&key.into()))
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(js_error)?;
+            for request in requests {
+                complete(&request).await?;
+            }
+            Ok(())
+        }
+
+        pub async fn clear(&self) -> Result<()> {
+            let transaction = self.write_both()?;
+            let requests = [ENTRIES, DATA]
+                .into_iter()
+                .map(|name| transaction.object_store(name)?.clear())
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(js_error)?;
+            for request in requests {
+                complete(&request).await?;
+            }
+            Ok(())
+        }
+    }
+
+    impl Drop for Store {
+        fn drop(&mut self) {
+            self.0.close();
+        }
+    }
+}
+
+/// Directory storage backend, used natively.
+///
+/// Every entry is stored as two files, named after the hex-encoded key: a JSON file containing
+/// the entry metadata and a binary file containing the data. The directory defaults to a
+/// subdirectory of the system temporary directory, it can be overridden by setting the
+/// `DIFF_RS_CACHE_DIR` environment variable. Tests use a temporary directory of their own.
+#[cfg(not(target_arch = "wasm32"))]
+mod directory {
+    use super::CacheEntry;
+    use anyhow::Result;
+    use bytes::Bytes;
+    use std::{
+        io::ErrorKind,
+        path::{Path, PathBuf},
+    };
+
+    /// Handle to the cache directory.
+    pub struct Store(PathBuf);
+
+    /// Turn a not found error into `None`.
+    fn optional<T>(result: std::io::Result<T>) -> Result<Option<T>> {
+        match result {
+            Ok(value) => Ok(Some(value)),
+            Err(error) if error.kind() == ErrorKind::NotFound => Ok(None),
+            Err(error) => Err(error.into()),
+        }
+    }
+
+    impl Store {
+        pub async fn open(name: &str, base: Option<&Path>) -> Result<Self> {
+            let base = base
+                .map(Path::to_path_buf)
+                .or_else(|| std::env::var_os("DIFF_RS_CACHE_DIR").map(PathBuf::from))
+                .unwrap_or_else(std::env::temp_dir);
+            let path = base.join(name);
+            std::fs::create_dir_all(&path)?;
+            Ok(Self(path))
+        }
+
+        fn path(&self, key: &str, extension: &str) -> PathBuf {
+            self.0.join(hex::encode(key)).with_extension(extension)
+        }
+
+        fn read_entry(path: &Path) -> Result<Option<CacheEntry>> {
+            match optional(std::fs::read(path))? {
+                Some(data) => Ok(Some(serde_json::from_slice(&data)?)),
+                None => Ok(None),
+            }
+        }
+
+        pub async fn entry(&self, key: &str) -> Result<Option<CacheEntry>> {
+            Self::read_entry(&self.path(key, "json"))
+        }
+
+        pub async fn entries(&self) -> Result<Vec<CacheEntry>> {
+            let mut entries = vec![];
+            for file in std::fs::read_dir(&self.0)? {
+                let path = file?.path();
+                if path
+                    .extension()
+                    .is_some_and(|extension| extension == "json")
+                {
+                    entries.extend(Self::read_entry(&path)?);
+                }
+            }
+            Ok(entries)
+        }
+
+        pub async fn data(&self, key: &str) -> Result<Option<Bytes>> {
+            Ok(optional(std::fs::read(self.path(key, "bin")))?.map(Into::into))
+        }
+
+        pub async fn put_entry(&self, entry: &CacheEntry) -> Result<()> {
+            std::fs::write(self.path(&entry.key, "json"), serde_json::to_vec(entry)?)?;
+            Ok(())
+        }
+
+        pub async fn put(&self, entry: &CacheEntry, data: &[u8]) -> Result<()> {
+            std::fs::write(self.path(&entry.key, "bin"), data)?;
+            self.put_entry(entry).await
+        }
+
+        pub async fn remove(&self, key: &str) -> Result<()> {
+            for extension in ["json", "bin"] {
+                optional(std::fs::remove_file(self.path(key, extension)))?;
+            }
+            Ok(())
+        }
+
+        pub async fn clear(&self) -> Result<()> {
+            optional(std::fs::remove_dir_all(&self.0))?;
+            std::fs::create_dir_all(&self.0)?;
+            Ok(())
+        }
+    }
+}
+
+/// Create a cache in its own temporary directory, which is removed when it is dropped.
+#[cfg(all(test, not(target_arch = "wasm32")))]
+fn test_cache(limits: CacheLimits) -> (tempfile::TempDir, PersistentCache) {
+    let directory = tempfile::tempdir().unwrap();
+    let cache = PersistentCache {
+        directory: Some(directory.path().to_path_buf()),
+        ..PersistentCache::new("diff-rs-cache", limits)
+    };
+    (directory, cache)
+}
+
+#[cfg(test)]
+fn metadata_key(krate: &str) -> CacheKey {
+    CacheKey::Metadata {
+        krate: krate.into(),
+    }
+}
+
+#[test]
+fn test_cache_key_format() {
+    assert_eq!(metadata_key("serde").to_string(), "metadata/serde");
+    let key = CacheKey::Source {
+        krate: "serde".into(),
+        version: "1.0.0".parse().unwrap(),
+        checksum: vec![0xab, 0xcd],
+    };
+    assert_eq!(key.to_string(), "source/serde/1.0.0/abcd");
+    assert_eq!(key.kind(), EntryKind::Source);
+}
+
+#[test]
+#[cfg(not(target_arch = "wasm32"))]
+fn test_persistent_cache_store_load() {
+    let (_directory, cache) = test_cache(CacheLimits::DEFAULT);
+    futures::executor::block_on(async {
+        assert_eq!(cache.load(&metadata_key("serde")).await.unwrap(), None);
+        cache.store(&metadata_key("serde"), b"data").await.unwrap();
+        assert_eq!(
+            cache.load(&metadata_key("serde")).await.unwrap(),
+            Some(Bytes::from_static(b"data"))
+        );
+        assert_eq!(cache.entries().await.unwrap().len(), 1);
+        cache.remove("metadata/serde").await.unwrap();
+        assert_eq!(cache.load(&metadata_key("serde")).await.unwrap(), None);
+    });
+}
+
+#[test]
+#[cfg(not(target_arch = "wasm32"))]
+fn test_persistent_cache_metadata_expires() {
+    let limits = CacheLimits {
+        metadata_ttl: 0,
+        ..CacheLimits::DEFAULT
+    };
+    let (_directory, cache) = test_cache(limits);
+    futures::executor::block_on(async {
+        let key = metadata_key("serde");
+        cache.store_at(&key, b"data", 1000).await.unwrap();
+        assert!(cache.load_at(&key, 1000).await.unwrap().is_some());
+        assert_eq!(cache.load_at(&key, 1001).await.unwrap(), None);
+        assert!(cache.entries().await.unwrap().is_empty());
+    });
+}
+
+#[test]
+#[cfg(not(target_arch = "wasm32"))]
+fn test_persistent_cache_evicts_least_recently_used() {
+    let limits = CacheLimits {
+        max_bytes: 8,
+        ..CacheLimits::DEFAULT
+    };
+    let (_directory, cache) = test_cache(limits);
+    futures::executor::block_on(async {
+        let [a, b, c, d] = ["a", "b", "c", "d"].map(metadata_key);
+        cache.store_at(&a, b"aaaa", 1).await.unwrap();
+        cache.store_at(&b, b"bbbb", 2).await.unwrap();
+
+        // access a, so that b is the least recently used entry
+        assert!(cache.load_at(&a, 3).await.unwrap().is_some());
+        cache.store_at(&c, b"cccc", 4).await.unwrap();
+
+        assert!(cache.load_at(&a, 5).await.unwrap().is_some());
+        assert!(cache.load_at(&b, 5).await.unwrap().is_none());
+        assert!(cache.load_at(&c, 5).await.unwrap().is_some());
+
+        // entries larger than the limit are not stored
+        cache.store_at(&d, b"ddddddddd", 6).await.unwrap();
+        assert!(cache.load_at(&d, 6).await.unwrap().is_none());
+    });
+}
@@ -0,0 +1,164 @@
+//! Extraction of release notes from the changelogs which crates ship.
+//!
+//! Changelogs are expected to follow the [Keep a Changelog](https://keepachangelog.com) style
+//! loosely: every release has a markdown heading which contains its version, and everything up to
+//! the next heading of the same or a higher level belongs to that release.
+
+use crate::data::CrateSource;
+use camino::{Utf8Path, Utf8PathBuf};
+use semver::Version;
+
+/// File names which are commonly used for changelogs, in order of preference.
+///
+/// These are compared case-insensitively against the files in the crate root.
+const CHANGELOG_NAMES: &[&str] = &[
+    "CHANGELOG.md",
+    "CHANGES.md",
+    "HISTORY.md",
+    "RELEASES.md",
+    "NEWS.md",
+    "CHANGELOG",
+    "CHANGES",
+];
+
+/// Entry of a changelog for a single release.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ChangelogEntry {
+    /// Version of the release
+    pub version: Version,
+    /// Markdown of the entry, including its heading
+    pub markdown: String,
+}
+
+/// Changelog of a crate, parsed into entries per release.
+#[derive(Clone, Debug, PartialEq, Eq, Default)]
+pub struct Changelog {
+    /// Entries, in the order they appear in the changelog
+    pub entries: Vec<ChangelogEntry>,
+    /// Link reference definitions, which entries may refer to
+    pub links: Vec<String>,
+}
+
+/// Parse an ATX heading, returning its level and text.
+fn heading(line: &str) -> Option<(usize, &str)> {
+    let trimmed = line.trim_start_matches('#');
+    let level = line.len() - trimmed.len();
+    if !(1..=6).contains(&level) || !(trimmed.is_empty() || trimmed.starts_with([' ', '\t'])) {
+        return None;
+    }
+    Some((level, trimmed.trim().trim_end_matches('#').trim_end()))
+}
+
+/// Find the version which a heading refers to, such as in `[1.2.0] - 2024-01-01` or `v1.2.0`.
+fn heading_version(text: &str) -> Option<Version> {
+    text.split(|c: char| c.is_whitespace() || matches!(c, '[' | ']' | '(' | ')' | ',' | ':'))
+        .map(|word| word.trim_start_matches(['v', 'V']))
+        .find_map(|word| {
+            word.parse().ok().or_else(|| {
+                // versions are sometimes abbreviated to major and minor version
+                let (major, minor) = word.split_once('.')?;
+                Some(Version::new(major.parse().ok()?, minor.parse().ok()?, 0))
+            })
+        })
+}
+
+/// Determines if a line is a link reference definition, such as `[1.2.0]: https://...`.
+fn is_link_definition(line: &str) -> bool {
+    line.starts_with('[')
+        && line
+            .split_once("]:")
+            .is_some_and(|(label, _)| label.len() > 1 && !label.contains(']'))
+}
+
+impl Changelog {
+    /// Find the changelog file in the root of a crate source.
+    pub fn find(source: &CrateSource) -> Option<&Utf8Path> {
+        CHANGELOG_NAMES.iter().find_map(|name| {
+            source
+                .files
+                .keys()
+                .find(|path| {
+                    path.parent() == Some(Utf8Path::new(""))
+                        && path.as_str().eq_ignore_ascii_case(name)
+                })
+                .map(Utf8PathBuf::as_path)
+        })
+    }
+
+    /// Find and parse the changelog of a crate source.
+    pub fn from_source(source: &CrateSource) -> Option<(&Utf8Path, Self)> {
+        let path = Self::find(source)?;
+        let text = String::from_utf8_lossy(&source.files[path]);
+        Some((path, Self::parse(&text)))
+    }
+
+    /// Parse a changelog.
+    ///
+    /// The level of release headings is determined by the first heading which contains a version.
+    /// Headings of other sections, such as `Unreleased`, end the previous entry.
+    pub fn parse(text: &str) -> Self {
+        let mut changelog = Self::default();
+        let mut level = None;
+        let mut fenced = false;
+        let mut current: Option<ChangelogEntry> = None;
+        for line in text.lines() {
+            if line.trim_start().starts_with("```") || line.trim_start().starts_with("~~~") {
+                fenced = !fenced;
+            }
+            if !fenced && is_link_definition(line) {
+                changelog.links.push(line.into());
+                continue;
+            }
+
+            if let Some((heading_level, text)) = heading(line).filter(|_| !fenced) {
+                let version = heading_version(text);
+                let ends_entry = match level {
+                    Some(level) => heading_level <= level,
+                    None => version.is_some(),
+                };
+                if ends_entry {
+                    changelog.entries.extend(current.take());
+                    if let Some(version) = version {
+                        level = Some(heading_level);
+                        current = Some(ChangelogEntry {
+                            version,
+                            markdown: String::new(),
+                        });
+                    }
+                }
+            }
+
+            if let Some(entry) = &mut current {
+                entry.markdown.push_str(line);
+                entry.markdown.push('\n');
+            }
+        }
+        changelog.entries.extend(current);
+        changelog
+    }
+
+    /// Entries of releases after `old`, up to and including `new`.
+    ///
+    /// If `old` is newer than `new`, the entries between them are returned as well.
+    pub fn between(&self, old: &Version, new: &Version) -> Vec<&ChangelogEntry> {
+        let (low, high) = if old <= new { (old, new) } else { (new, old) };
+        self.entries
+            .iter()
+            .filter(|entry| &entry.version > low && &entry.version <= high)
+            .collect()
+    }
+
+    /// Markdown of the given entries, along with the link reference definitions they may use.
+    pub fn markdown(&self, entries: &[&ChangelogEntry]) -> String {
+        let mut markdown = String::new();
+        for entry in entries {
+            markdown.push_str(&entry.markdown);
+            markdown.push('\n');
+        }
+        for link in &self.links {
+            markdown.push_str(link);
+            markdown.push('\n');
+        }
+        markdown
+    }
+}
@@ -0,0 +1,213 @@
+//! Search of the contents of both crate versions of a diff.
+//!
+//! Changed files are searched through their diff, so that lines which are in both versions are
+//! only reported once and matches can be restricted to the lines which were added or removed.
+//! Other files are searched line by line, without diffing them.
+
+use crate::{
+    data::{Changes, VersionDiff},
+    export::is_binary,
+};
+use anyhow::Result;
+use bytes::Bytes;
+use camino::Utf8PathBuf;
+use regex::{Regex, RegexBuilder};
+use similar::ChangeTag;
+use std::ops::Range;
+
+/// Maximum number of matching lines which are reported, to keep the results responsive.
+pub const MAX_MATCHES: usize = 1000;
+
+/// Version of the crate which is searched.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SearchSide {
+    /// Lines of both versions
+    #[default]
+    Both,
+    /// Lines of the old version, which are removed or unchanged
+    Old,
+    /// Lines of the new version, which are added or unchanged
+    New,
+}
+
+impl SearchSide {
+    /// All sides, in the order in which they are offered.
+    pub const ALL: [SearchSide; 3] = [Self::Both, Self::Old, Self::New];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Both => "Both",
+            Self::Old => "Old",
+            Self::New => "New",
+        }
+    }
+
+    /// Determines if a line with the given tag is part of this side.
+    fn contains(&self, tag: ChangeTag) -> bool {
+        matches!(
+            (self, tag),
+            (Self::Both, _)
+                | (_, ChangeTag::Equal)
+                | (Self::Old, ChangeTag::Delete)
+                | (Self::New, ChangeTag::Insert)
+        )
+    }
+}
+
+/// What to search for, and which lines to search.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct SearchOptions {
+    /// Text or regular expression to search for
+    pub query: String,
+    /// Whether the query is a regular expression rather than plain text
+    pub regex: bool,
+    pub case_sensitive: bool,
+    /// Whether only lines which were added or removed are searched
+    pub changed_only: bool,
+    pub side: SearchSide,
+}
+
+impl SearchOptions {
+    /// Build the regular expression which lines are matched against.
+    pub fn matcher(&self) -> Result<Regex> {
+        let pattern = match self.regex {
+            true => self.query.clone(),
+            false => regex::escape(&self.query),
+        };
+        Ok(RegexBuilder::new(&pattern)
+            .case_insensitive(!self.case_sensitive)
+            .build()?)
+    }
+}
+
+/// Line which matches the query.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LineMatch {
+    pub tag: ChangeTag,
+    /// Line number in the old version, starting at 1, unless the line was added
+    pub old: Option<usize>,
+    /// Line number in the new version, starting at 1, unless the line was removed
+    pub new: Option<usize>,
+    /// Contents of the line, without the line ending
+    pub text: String,
+    /// Byte ranges of the matches within the text
+    pub ranges: Vec<Range<usize>>,
+}
+
+impl LineMatch {
+    /// Fragment of the diff view which refers to this line, such as `R12` for line 12 of the new
+    /// version. Lines which are in the new version refer to it.
+    pub fn anchor(&self) -> String {
+        match (self.old, self.new) {
+            (_, Some(line)) => format!("R{line}"),
+            (Some(line), None) => format!("L{line}"),
+            (None, None) => String::new(),
+        }
+    }
+}
+
+/// Lines of a file which match the query.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FileMatches {
+    pub path: Utf8PathBuf,
+    pub lines: Vec<LineMatch>,
+}
+
+/// Matches of a search, grouped by file in the order of the file tree.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct SearchResults {
+    pub files: Vec<FileMatches>,
+    /// Whether there are more than [`MAX_MATCHES`] matching lines, which were left out
+    pub truncated: bool,
+    /// Changed files which are too large to be diffed, and were not searched
+    pub too_large: Vec<Utf8PathBuf>,
+}
+
+impl SearchResults {
+    /// Number of matching lines.
+    pub fn line_count(&self) -> usize {
+        self.files.iter().map(|file| file.lines.len()).sum()
+    }
+}
+
+/// Split data into lines, keeping line endings like the line diff does.
+fn split_lines(data: &Bytes, tag: ChangeTag) -> Vec<(ChangeTag, Bytes)> {
+    data.split_inclusive(|byte| *byte == b'\n')
+        .map(|line| (tag, data.slice_ref(line)))
+        .collect()
+}
+
+/// Search the files of both crate versions of a diff.
+///
+/// Binary files are skipped, as are changed files which are too large to be diffed. Returns an error if the query is not a valid regular expression.
+pub fn search(diff: &VersionDiff, options: &SearchOptions) -> Result<SearchResults> {
+    let mut results = SearchResults::default();
+    if options.query.is_empty() {
+        return Ok(results);
+    }
+    let matcher = options.matcher()?;
+    let mut remaining = MAX_MATCHES;
+    'files: for (path, changes) in diff.tree_files(false) {
+        if options.changed_only && changes == Changes::default() {
+            continue;
+        }
+        let (left, right) = (diff.left.files.get(&path), diff.right.files.get(&path));
+        if is_binary(left) || is_binary(right) {
+            continue;
+        }
+        let lines = match (left, right) {
+            (Some(left), Some(right)) if left == right => split_lines(left, ChangeTag::Equal),
+            (Some(left), None) => split_lines(left, ChangeTag::Delete),
+            (None, Some(right)) => split_lines(right, ChangeTag::Insert),
+            _ if diff.is_too_large(&path) => {
+                results.too_large.push(path);
+                continue;
+            }
+            _ => diff
+                .file(&path)
+                .map(|file| file.changes.clone())
+                .unwrap_or_default(),
+        };
+
+        let mut matches = vec![];
+        let (mut old, mut new) = (0, 0);
+        for (tag, line) in lines {
+            old += usize::from(tag != ChangeTag::Insert);
+            new += usize::from(tag != ChangeTag::Delete);
+            if (options.changed_only && tag == ChangeTag::Equal) || !options.side.contains(tag) {
+                continue;
+            }
+            let text = String::from_utf8_lossy(&line);
+            let text = text.trim_end_matches(['\n', '\r']);
+            let ranges: Vec<_> = matcher.find_iter(text).map(|found| found.range()).collect();
+            if ranges.is_empty() {
+                continue;
+            }
+            if remaining == 0 {
+                results.truncated = true;
+                if !matches.is_empty() {
+                    results.files.push(FileMatches {
+                        path,
+                        lines: matches,
+                    });
+                }
+                break 'files;
+            }
+            remaining -= 1;
+            matches.push(LineMatch {
+                tag,
+                old: (tag != ChangeTag::Insert).then_some(old),
+                new: (tag != ChangeTag::Delete).then_some(new),
+                text: text.to_string(),
+                ranges,
+            });
+        }
+        if !matches.is_empty() {
+            results.files.push(FileMatches {
+                path,
+                lines: matches,
+            });
+        }
+    }
+    Ok(results)
+}
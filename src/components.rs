@@ -4,14 +4,24 @@
 //! views. Components which are only used by a single view can be kept inside the view's definition
 //! itself, unless they are generic to too complex.
 
+mod all_files;
+mod changelog;
+mod code_search;
 mod diff_view;
+mod export;
+mod file_finder;
 mod file_tree;
 mod footer;
 mod layout;
+mod line_counts;
+mod markdown;
 mod navigation;
 mod non_ideal;
 mod search;
+mod shortcuts;
 
 pub use self::{
-    diff_view::*, file_tree::*, footer::*, layout::*, navigation::*, non_ideal::*, search::*,
+    all_files::*, changelog::*, code_search::*, diff_view::*, export::*, file_finder::*,
+    file_tree::*, footer::*, layout::*, line_counts::*, markdown::*, navigation::*, non_ideal::*,
+    search::*, shortcuts::*,
 };
@@ -0,0 +1,173 @@
+use super::{DiffStyle, DiffView};
+use crate::data::{Changes, VersionDiff};
+use camino::{Utf8Path, Utf8PathBuf};
+use js_sys::Array;
+use std::rc::Rc;
+use wasm_bindgen::{prelude::*, JsCast};
+use web_sys::{
+    HtmlElement, IntersectionObserver, IntersectionObserverEntry, IntersectionObserverInit,
+};
+use yew::prelude::*;
+
+/// Distance from the viewport at which files are rendered, so they are ready when scrolled to.
+const RENDER_MARGIN: &str = "1000px 0px";
+
+/// Estimated height of a line of a diff in pixels, used before a file was rendered.
+const LINE_HEIGHT: u64 = 20;
+
+/// Estimated height of the header of a diff in pixels, including its margin.
+const HEADER_HEIGHT: u64 = 48;
+
+/// Estimated height of a diff in pixels which was not rendered yet, limited to what is shown
+/// before unchanged lines are folded.
+const MAX_ESTIMATED_HEIGHT: u64 = 4000;
+
+/// Id of the element which contains the diff of a file in the all files view.
+pub fn file_anchor(path: &Utf8Path) -> String {
+    format!("file-{path}")
+}
+
+/// Scroll to the diff of a file in the all files view.
+pub fn scroll_to_file(path: &Utf8Path) {
+    let element = web_sys::window()
+        .and_then(|window| window.document())
+        .and_then(|document| document.get_element_by_id(&file_anchor(path)));
+    if let Some(element) = element {
+        element.scroll_into_view();
+    }
+}
+
+#[derive(Properties, PartialEq, Clone)]
+pub struct AllFilesViewProps {
+    pub diff: Rc<VersionDiff>,
+    #[prop_or_default]
+    pub style: DiffStyle,
+    #[prop_or_default]
+    pub expand_all: bool,
+}
+
+/// Show the diffs of all changed files in sequence.
+///
+/// When both sides of the diff are the same version, all files are shown instead.
+#[function_component]
+pub fn AllFilesView(props: &AllFilesViewProps) -> Html {
+    let files = use_memo(props.diff.clone(), |diff| {
+        diff.tree_files(diff.left.version != diff.right.version)
+    });
+    if files.is_empty() {
+        return html! {
+            <div class="all-files">
+                <p class="empty">{"No files were changed."}</p>
+            </div>
+        };
+    }
+    html! {
+        <div class="all-files">
+            {
+                for files.iter().map(|(path, changes)| html! {
+                    <FileSection
+                        key={path.as_str()}
+                        diff={props.diff.clone()}
+                        path={path.clone()}
+                        changes={*changes}
+                        style={props.style}
+                        expand_all={props.expand_all}
+                    />
+                })
+            }
+        </div>
+    }
+}
+
+#[derive(Properties, PartialEq)]
+struct FileSectionProps {
+    diff: Rc<VersionDiff>,
+    path: Utf8PathBuf,
+    changes: Changes,
+    style: DiffStyle,
+    expand_all: bool,
+}
+
+/// Diff of a single file, which is only rendered while it is close to the viewport.
+///
+/// While the file is not rendered, a placeholder with the last rendered height (or an estimate)
+/// is shown instead, so that the scroll position does not jump.
+#[function_component]
+fn FileSection(props: &FileSectionProps) -> Html {
+    let node = use_node_ref();
+    let visible = use_state(|| false);
+    let height = use_state(|| None::<i32>);
+    let collapsed = use_state(|| false);
+
+    {
+        let visible = visible.clone();
+        let height = height.clone();
+        use_effect_with(node.clone(), move |node| {
+            let element = node.cast::<HtmlElement>();
+            let callback = {
+                let node = node.clone();
+                Closure::<dyn FnMut(Array)>::new(move |entries: Array| {
+                    let Some(entry) = entries.iter().last() else {
+                        return;
+                    };
+                    let entry: IntersectionObserverEntry = entry.unchecked_into();
+                    if !entry.is_intersecting() {
+                        // remember the rendered height, for the placeholder
+                        if let Some(element) = node.cast::<HtmlElement>() {
+                            height.set(Some(element.offset_height()));
+                        }
+                    }
+                    visible.set(entry.is_intersecting());
+                })
+            };
+            let options = IntersectionObserverInit::new();
+            options.set_root_margin(RENDER_MARGIN);
+            let observer =
+                IntersectionObserver::new_with_options(callback.as_ref().unchecked_ref(), &options)
+                    .ok();
+            if let (Some(observer), Some(element)) = (&observer, element) {
+                observer.observe(&element);
+            }
+            move || {
+                if let Some(observer) = observer {
+                    observer.disconnect();
+                }
+                drop(callback);
+            }
+        });
+    }
+
+    let content = if *visible || *collapsed {
+        html! {
+            <DiffView
+                diff={props.diff.clone()}
+                path={props.path.clone()}
+                collapsed={Some(collapsed.clone())}
+                style={props.style}
+                expand_all={props.expand_all}
+            />
+        }
+    } else {
+        // unchanged files are shown in full, changed files mostly show their changed lines
+        let lines = if props.changes == Changes::default() {
+            let data = props.diff.right.files.get(&props.path);
+            data.map(|data| data.iter().filter(|byte| **byte == b'\n').count() as u64)
+                .unwrap_or_default()
+        } else {
+            props.changes.added + props.changes.removed
+        };
+        let estimate = (HEADER_HEIGHT + lines * LINE_HEIGHT).min(MAX_ESTIMATED_HEIGHT) as i32;
+        let height = height.unwrap_or(estimate);
+        html! {
+            <div class="placeholder" style={format!("height: {height}px")}>
+                <span class="filename">{props.path.as_str()}</span>
+            </div>
+        }
+    };
+
+    html! {
+        <section id={file_anchor(&props.path)} class="file-section" ref={node}>
+            {content}
+        </section>
+    }
+}
@@ -0,0 +1,62 @@
+use super::render_markdown;
+use crate::{changelog::Changelog, data::VersionDiff};
+use camino::Utf8PathBuf;
+use std::rc::Rc;
+use yew::prelude::*;
+
+/// Changelog entries of the releases between the versions of a diff.
+#[derive(PartialEq)]
+struct ChangelogSection {
+    path: Utf8PathBuf,
+    releases: usize,
+    html: AttrValue,
+}
+
+impl ChangelogSection {
+    fn new(diff: &VersionDiff) -> Option<Self> {
+        let (old, new) = (&diff.left.version, &diff.right.version);
+        if old.krate != new.krate || old.version == new.version {
+            return None;
+        }
+        let (path, changelog) = Changelog::from_source(&diff.right)?;
+        let entries = changelog.between(&old.version, &new.version);
+        if entries.is_empty() {
+            return None;
+        }
+        Some(Self {
+            path: path.into(),
+            releases: entries.len(),
+            html: render_markdown(&changelog.markdown(&entries)).into(),
+        })
+    }
+}
+
+#[derive(Properties, PartialEq, Clone)]
+pub struct ChangelogPanelProps {
+    pub diff: Rc<VersionDiff>,
+}
+
+/// Show the changelog entries of the releases between the versions of a diff.
+#[function_component]
+pub fn ChangelogPanel(props: &ChangelogPanelProps) -> Html {
+    let section = use_memo(props.diff.clone(), |diff| ChangelogSection::new(diff));
+    let Some(section) = &*section else {
+        return html! {};
+    };
+    let releases = match section.releases {
+        1 => "1 release".to_string(),
+        releases => format!("{releases} releases"),
+    };
+    html! {
+        <details class="changelog">
+            <summary class="header">
+                <span class="title">{"Changelog"}</span>
+                <span class="filename">{section.path.as_str()}</span>
+                <span class="releases">{releases}</span>
+            </summary>
+            <div class="content prose prose-slate dark:prose-invert max-w-none">
+                { Html::from_html_unchecked(section.html.clone()) }
+            </div>
+        </details>
+    }
+}
@@ -0,0 +1,233 @@
+use crate::{
+    code_search::{search, LineMatch, SearchOptions, SearchSide, MAX_MATCHES},
+    components::Context,
+    data::VersionDiff,
+    Route,
+};
+use camino::Utf8PathBuf;
+use similar::ChangeTag;
+use std::rc::Rc;
+use web_sys::HtmlInputElement;
+use yew::prelude::*;
+use yew_router::prelude::*;
+
+/// Render the text of a matching line, highlighting the matches.
+fn highlight_matches(line: &LineMatch) -> Html {
+    let mut html = vec![];
+    let mut cursor = 0;
+    for range in &line.ranges {
+        html.push(html! { {&line.text[cursor..range.start]} });
+        html.push(html! { <mark>{&line.text[range.clone()]}</mark> });
+        cursor = range.end;
+    }
+    html.push(html! { {&line.text[cursor..]} });
+    html.into_iter().collect()
+}
+
+#[derive(Properties, PartialEq)]
+pub struct CodeSearchProps {
+    pub diff: Rc<VersionDiff>,
+    /// Route to a file of the diff, defaults to the file route of the crate versions
+    #[prop_or_default]
+    pub file_route: Option<Callback<Utf8PathBuf, Route>>,
+    pub onclose: Callback<()>,
+}
+
+/// Overlay to search the contents of both crate versions, linking to the matching lines.
+#[function_component]
+pub fn CodeSearch(props: &CodeSearchProps) -> Html {
+    let query = use_state(String::new);
+    let options = use_state(SearchOptions::default);
+    let input = use_node_ref();
+    let navigator = use_navigator().unwrap();
+    {
+        let input = input.clone();
+        use_effect_with((), move |_| {
+            if let Some(input) = input.cast::<HtmlInputElement>() {
+                let _ = input.focus();
+            }
+        });
+    }
+    let results = use_memo(
+        (props.diff.clone(), (*options).clone()),
+        |(diff, options)| search(diff, options).map_err(|error| error.to_string()),
+    );
+
+    let context = Rc::new(Context::new(&props.diff, props.file_route.clone(), None));
+    // navigate to a line, the diff view unfolds it when the fragment changes
+    let open_line = {
+        let context = context.clone();
+        let onclose = props.onclose.clone();
+        Callback::from(move |(path, anchor): (Utf8PathBuf, String)| {
+            onclose.emit(());
+            navigator.push(&context.file_route(path));
+            if let Some(window) = web_sys::window() {
+                let _ = window.location().set_hash(&anchor);
+            }
+        })
+    };
+
+    let oninput = {
+        let query = query.clone();
+        move |event: InputEvent| {
+            if let Some(input) = event.target_dyn_into::<HtmlInputElement>() {
+                query.set(input.value());
+            }
+        }
+    };
+    let onsubmit = {
+        let query = query.clone();
+        let options = options.clone();
+        move |event: SubmitEvent| {
+            event.prevent_default();
+            options.set(SearchOptions {
+                query: (*query).clone(),
+                ..(*options).clone()
+            });
+        }
+    };
+    let onkeydown = {
+        let onclose = props.onclose.clone();
+        move |event: KeyboardEvent| {
+            if event.key() == "Escape" {
+                onclose.emit(());
+            }
+        }
+    };
+    let toggle = |label: &str, get: fn(&SearchOptions) -> bool, set: fn(&mut SearchOptions)| {
+        let checked = get(&options);
+        let options = options.clone();
+        let onchange = move |_| {
+            let mut value = (*options).clone();
+            set(&mut value);
+            options.set(value);
+        };
+        html! {
+            <label>
+                <input type="checkbox" {checked} {onchange} />
+                {label}
+            </label>
+        }
+    };
+    let side_button = |side: SearchSide| {
+        let options = options.clone();
+        let active = options.side == side;
+        let onclick = move |_| {
+            options.set(SearchOptions {
+                side,
+                ..(*options).clone()
+            })
+        };
+        html! {
+            <button type="button" class={classes!(active.then_some("active"))} {onclick}>
+                {side.name()}
+            </button>
+        }
+    };
+
+    let too_large_html = match &*results {
+        Ok(results) if !options.query.is_empty() && !results.too_large.is_empty() => html! {
+            <div class="summary">
+                {format!(
+                    "{} changed files are too large to be diffed and were not searched: {}",
+                    results.too_large.len(),
+                    results.too_large.iter().map(|path| path.as_str()).collect::<Vec<_>>().join(", "),
+                )}
+            </div>
+        },
+        _ => html! {},
+    };
+
+    let results_html = match &*results {
+        _ if options.query.is_empty() => html! {},
+        Err(error) => html! {
+            <div class="error">{format!("Invalid regular expression: {error}")}</div>
+        },
+        Ok(results) if results.files.is_empty() => html! {
+            <>
+                <div class="empty">{"No matching lines."}</div>
+                {too_large_html}
+            </>
+        },
+        Ok(results) => html! {
+            <>
+                <div class="summary">
+                    {format!("{} matching lines in {} files", results.line_count(), results.files.len())}
+                    if results.truncated {
+                        {format!(", only the first {MAX_MATCHES} are shown")}
+                    }
+                </div>
+                {too_large_html}
+                {
+                    for results.files.iter().map(|file| html! {
+                        <section class="file">
+                            <div class="path">{file.path.as_str()}</div>
+                            {
+                                for file.lines.iter().map(|line| {
+                                    let anchor = line.anchor();
+                                    let href = format!("{}#{anchor}", context.file_route(file.path.clone()).to_path());
+                                    let onclick = {
+                                        let open_line = open_line.clone();
+                                        let path = file.path.clone();
+                                        move |event: MouseEvent| {
+                                            event.prevent_default();
+                                            open_line.emit((path.clone(), anchor.clone()));
+                                        }
+                                    };
+                                    let (sign, class) = match line.tag {
+                                        ChangeTag::Delete => ("-", "deletion"),
+                                        ChangeTag::Insert => ("+", "insertion"),
+                                        ChangeTag::Equal => (" ", "unchanged"),
+                                    };
+                                    html! {
+                                        <a class={classes!("line", class)} {href} {onclick}>
+                                            <span class="line-number">{line.old.map(|line| line.to_string())}</span>
+                                            <span class="line-number">{line.new.map(|line| line.to_string())}</span>
+                                            <span class="change-icon">{sign}</span>
+                                            <span class="text">{highlight_matches(line)}</span>
+                                        </a>
+                                    }
+                                })
+                            }
+                        </section>
+                    })
+                }
+            </>
+        },
+    };
+    let onclose = props.onclose.reform(|_| ());
+
+    html! {
+        <div class="overlay" onclick={onclose.clone()}>
+            <div class="dialog code-search" onclick={|event: MouseEvent| event.stop_propagation()}>
+                <div class="dialog-header">
+                    <span>{"Search code"}</span>
+                    <button class="button" onclick={onclose}>{"Close"}</button>
+                </div>
+                <form {onsubmit}>
+                    <input
+                        ref={input}
+                        type="search"
+                        aria-label="Search code"
+                        placeholder="Search both versions..."
+                        value={(*query).clone()}
+                        {oninput}
+                        {onkeydown}
+                    />
+                    <button type="submit" class="button">{"Search"}</button>
+                </form>
+                <div class="options">
+                    {toggle("Regex", |options| options.regex, |options| options.regex = !options.regex)}
+                    {toggle("Match case", |options| options.case_sensitive, |options| options.case_sensitive = !options.case_sensitive)}
+                    {toggle("Changed lines only", |options| options.changed_only, |options| options.changed_only = !options.changed_only)}
+                    <div class="view-mode" role="group" aria-label="Version">
+                        {for SearchSide::ALL.into_iter().map(side_button)}
+                    </div>
+                </div>
+                <div class="results">
+                    {results_html}
+                </div>
+            </div>
+        </div>
+    }
+}
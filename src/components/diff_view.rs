@@ -1,20 +1,23 @@
+use super::{estimate_prefix, render_markdown, ESTIMATE_TITLE};
 use crate::{
-    data::{ChunkInfo, FileDiff, VersionDiff},
-    syntax::{highlight_changes, infer_syntax_for_file, syntect_style_to_css},
+    data::{ChunkInfo, FileDiff, VersionDiff, VersionInfo},
+    syntax::{highlight_changes, infer_syntax_for_changes, HighlightedLine, LazyHighlighter},
+    Link, Route,
 };
 use bytes::Bytes;
-use camino::Utf8PathBuf;
+use camino::{Utf8Path, Utf8PathBuf};
 use log::*;
 use similar::ChangeTag;
-use std::rc::Rc;
-use syntect::highlighting::Style;
+use std::{collections::BTreeSet, rc::Rc};
+use wasm_bindgen::{prelude::*, JsCast};
+use web_sys::Element;
 use yew::prelude::*;
 
 /// Contains information about contiguous changes
 #[derive(PartialEq, Clone)]
 struct DiffGroupInfo {
     /// The actual changes
-    group: Vec<(ChangeTag, Vec<(Style, bytes::Bytes)>)>,
+    group: Vec<(ChangeTag, HighlightedLine)>,
     /// What range of lines the group covers (used as a Yew list key)
     range: ChunkInfo,
     /// Whether the group contains an actual diff (and therefore shows some context)
@@ -25,6 +28,16 @@ struct DiffGroupInfo {
 pub struct DiffViewProps {
     pub path: Utf8PathBuf,
     pub diff: Rc<VersionDiff>,
+    /// Whether the file is collapsed, makes the header collapsible and show the full path and the
+    /// lines changed.
+    #[prop_or_default]
+    pub collapsed: Option<UseStateHandle<bool>>,
+    /// Whether old and new lines are shown interleaved or side by side
+    #[prop_or_default]
+    pub style: DiffStyle,
+    /// Whether all folded groups of unchanged lines are expanded
+    #[prop_or_default]
+    pub expand_all: bool,
 }
 
 #[derive(Clone, Copy, Debug, PartialEq, Default)]
@@ -44,89 +57,319 @@ fn FileIcon() -> Html {
     }
 }
 
+#[derive(Properties, PartialEq)]
+struct CollapseIconProps {
+    collapsed: bool,
+}
+
+#[function_component]
+fn CollapseIcon(props: &CollapseIconProps) -> Html {
+    html! {
+        <svg viewBox="0 0 24 24" fill="currentColor" xmlns="http://www.w3.org/2000/svg" class={(!props.collapsed).then_some("rotate-90")}>
+            <path fill-rule="evenodd" clip-rule="evenodd" d="M8.79289 6.29289C9.18342 5.90237 9.81658 5.90237 10.2071 6.29289L15.2071 11.2929C15.5976 11.6834 15.5976 12.3166 15.2071 12.7071L10.2071 17.7071C9.81658 18.0976 9.18342 18.0976 8.79289 17.7071C8.40237 17.3166 8.40237 16.6834 8.79289 16.2929L13.0858 12L8.79289 7.70711C8.40237 7.31658 8.40237 6.68342 8.79289 6.29289Z" />
+        </svg>
+    }
+}
+
+#[derive(Properties, PartialEq)]
+struct FileHeaderProps {
+    path: Utf8PathBuf,
+    diff: Rc<VersionDiff>,
+    collapsed: Option<UseStateHandle<bool>>,
+    #[prop_or_default]
+    children: Html,
+}
+
+/// Header of a file, with the file name and the given controls.
+#[function_component]
+fn FileHeader(props: &FileHeaderProps) -> Html {
+    let Some(collapsed) = &props.collapsed else {
+        return html! {
+            <div class="header">
+                <FileIcon />
+                <span class="filename">{props.path.file_name().unwrap_or("")}</span>
+                {props.children.clone()}
+            </div>
+        };
+    };
+    let onclick = {
+        let collapsed = collapsed.clone();
+        move |_| collapsed.set(!*collapsed)
+    };
+    let (added, removed) = props
+        .diff
+        .summary
+        .get(&props.path)
+        .copied()
+        .unwrap_or_default();
+    let estimate = props.diff.is_estimate(&props.path);
+    let prefix = estimate_prefix(estimate);
+    html! {
+        <div class="header">
+            <button class="collapse" aria-expanded={(!**collapsed).to_string()} {onclick}>
+                <CollapseIcon collapsed={**collapsed} />
+            </button>
+            <FileIcon />
+            <span class="filename">{props.path.as_str()}</span>
+            <span class="stats" title={estimate.then_some(ESTIMATE_TITLE)}>
+                if added > 0 {
+                    <span class="added">{format!("+{prefix}{added}")}</span>
+                }
+                if removed > 0 {
+                    <span class="removed">{format!("-{prefix}{removed}")}</span>
+                }
+            </span>
+            {props.children.clone()}
+        </div>
+    }
+}
+
+/// Show the diff of a file, asking before diffing files which are too large.
 #[function_component]
 pub fn DiffView(props: &DiffViewProps) -> Html {
-    let empty = FileDiff::default();
-    let file_diff = props.diff.files.get(&props.path).unwrap_or(&empty);
+    // path of the large file which the user chose to show anyway
+    let show_anyway = use_state(|| None::<Utf8PathBuf>);
+    if props
+        .collapsed
+        .as_ref()
+        .is_some_and(|collapsed| **collapsed)
+    {
+        return html! {
+            <div class="diff-view collapsed">
+                <FileHeader
+                    path={props.path.clone()}
+                    diff={props.diff.clone()}
+                    collapsed={props.collapsed.clone()}
+                />
+            </div>
+        };
+    }
+    let too_large =
+        props.diff.is_too_large(&props.path) && show_anyway.as_ref() != Some(&props.path);
+    if !too_large {
+        return html! { <FileDiffView ..props.clone() /> };
+    }
+
+    let onclick = {
+        let path = props.path.clone();
+        move |_| show_anyway.set(Some(path.clone()))
+    };
+    html! {
+        <div class="diff-view">
+            <FileHeader
+                path={props.path.clone()}
+                diff={props.diff.clone()}
+                collapsed={props.collapsed.clone()}
+            />
+            <div class="content">
+                <div class="too-large">
+                    <span>{"File too large to diff, which could make the page unresponsive."}</span>
+                    <button class="button" {onclick}>{"Show anyway"}</button>
+                </div>
+            </div>
+        </div>
+    }
+}
+
+/// Determines if a file is markdown, which can be shown rendered.
+fn is_markdown(path: &Utf8Path) -> bool {
+    path.extension()
+        .is_some_and(|extension| ["md", "markdown"].contains(&&*extension.to_ascii_lowercase()))
+}
+
+#[function_component]
+fn FileDiffView(props: &DiffViewProps) -> Html {
+    // whether markdown files are shown rendered instead of as source
+    let rendered = use_state(|| false);
+    let markdown = is_markdown(&props.path);
+    // annotate lines with the versions between the old and the new version which introduced them
+    let (left, right) = (&props.diff.left.version, &props.diff.right.version);
+    let blame = (left.krate == right.krate
+        && left.version != right.version
+        && !props.diff.left.is_unverified()
+        && !props.diff.right.is_unverified()
+        && props.diff.right.files.contains_key(&props.path))
+    .then(|| Route::Blame {
+        krate: right.krate.clone(),
+        old_version: left.version.clone().into(),
+        new_version: right.version.clone().into(),
+        path: props.path.clone(),
+    });
+    let toggle = |show_rendered: bool, label: &str| {
+        let rendered = rendered.clone();
+        let active = *rendered == show_rendered;
+        html! {
+            <button
+                class={classes!(active.then_some("active"))}
+                onclick={move |_| rendered.set(show_rendered)}
+            >
+                {label}
+            </button>
+        }
+    };
+    let header = html! {
+        <FileHeader
+            path={props.path.clone()}
+            diff={props.diff.clone()}
+            collapsed={props.collapsed.clone()}
+        >
+            if let Some(blame) = blame {
+                <Link classes="blame-link" to={blame}>{"Blame"}</Link>
+            }
+            if markdown {
+                <div class="view-toggle">
+                    {toggle(false, "Source")}
+                    {toggle(true, "Rendered")}
+                </div>
+            }
+        </FileHeader>
+    };
+    if markdown && *rendered {
+        return html! {
+            <div class="diff-view">
+                {header}
+                <div class="content">
+                    <RenderedDiffView diff={props.diff.clone()} path={props.path.clone()} />
+                </div>
+            </div>
+        };
+    }
+
+    let file_diff = props.diff.file(&props.path).unwrap_or_default();
     let summary = props.diff.summary.get(&props.path).unwrap_or(&(0, 0));
     let is_identical_version = props.diff.left.version == props.diff.right.version;
+    let display = summary == &(0, 0);
 
-    // Determine which syntax should be used for this file. It will be based
-    // first on the file's name, then the file's extension, then the first line.
-    let syntax = infer_syntax_for_file(
-        props.path.as_str(),
-        file_diff
-            .changes
-            .iter()
-            .find(|(tag, _)| *tag != ChangeTag::Delete)
-            .and_then(|(_, line)| std::str::from_utf8(line).ok()),
-    );
-    info!("Highlighting {} as {}", syntax.name, props.path);
+    // Large files are only rendered and highlighted as they are scrolled into view.
+    if file_diff.changes.len() > VIRTUAL_THRESHOLD {
+        return html! {
+            <div class="diff-view">
+                {header}
+                <div class="content">
+                    <VirtualDiffView
+                        path={props.path.clone()}
+                        {file_diff}
+                        {is_identical_version}
+                        {display}
+                        expand_all={props.expand_all}
+                    />
+                </div>
+            </div>
+        };
+    }
 
     // Apply highlighting to every change in the file.
+    let syntax = infer_syntax_for_changes(props.path.as_str(), &file_diff.changes);
+    info!("Highlighting {} as {}", syntax.name, props.path);
     let mut changes = highlight_changes(syntax, &file_diff.changes).into_iter();
-    let ranges = file_diff.context_ranges.iter();
+    let stack: Vec<DiffGroupInfo> = diff_groups(&file_diff, is_identical_version)
+        .into_iter()
+        .map(|(range, in_context)| DiffGroupInfo {
+            group: changes.by_ref().take(range.range.len()).collect(),
+            range,
+            in_context,
+        })
+        .collect();
 
-    // Group contiguous lines by whether they contain an actual diff +/- some context buffer.
-    let mut cursor = 0;
-    let mut stack: Vec<DiffGroupInfo> = vec![];
+    html! {
+        <div class="diff-view">
+            {header}
+            <div class="content">
+                {
+                    match (display, props.style) {
+                        (true, _) => html! {<FileDisplayView {stack} />},
+                        (false, DiffStyle::Unified) => html! {
+                            <UnifiedDiffView {stack} expand_all={props.expand_all} />
+                        },
+                        (false, DiffStyle::Split) => html! {
+                            <SplitDiffView {stack} expand_all={props.expand_all} />
+                        },
+                    }
+                }
+            </div>
+        </div>
+    }
+}
 
-    for next_range in ranges {
+/// Group contiguous lines by whether they contain an actual diff +/- some context buffer.
+///
+/// Returns the range of every group, and whether it is in context (shown unfolded).
+fn diff_groups(file_diff: &FileDiff, is_identical_version: bool) -> Vec<(ChunkInfo, bool)> {
+    let mut cursor = 0;
+    let mut groups = vec![];
+    for next_range in &file_diff.context_ranges {
         // out of context lines
         if next_range.start() != 0 {
-            stack.push(DiffGroupInfo {
-                group: changes.by_ref().take(next_range.start() - cursor).collect(),
-                range: ChunkInfo {
-                    range: cursor..next_range.start(),
-                    left_start: (next_range.left_start + cursor).saturating_sub(next_range.start()),
-                    right_start: (next_range.right_start + cursor)
-                        .saturating_sub(next_range.start()),
-                },
-                in_context: false,
-            });
+            let range = ChunkInfo {
+                range: cursor..next_range.start(),
+                left_start: (next_range.left_start + cursor).saturating_sub(next_range.start()),
+                right_start: (next_range.right_start + cursor).saturating_sub(next_range.start()),
+            };
+            groups.push((range, false));
         }
         // in context lines
-        stack.push(DiffGroupInfo {
-            group: changes
-                .by_ref()
-                .take(next_range.end() - next_range.start())
-                .collect(),
-            range: next_range.clone(),
-            in_context: true,
-        });
+        groups.push((next_range.clone(), true));
         cursor = next_range.end();
     }
-    if changes.len() > 0 {
+    if cursor < file_diff.changes.len() {
         // Trailing unchanged lines at the end of a file
-        stack.push(DiffGroupInfo {
-            group: changes.by_ref().collect(),
-            range: ChunkInfo {
-                range: cursor..file_diff.changes.len(),
-                left_start: (cursor).saturating_sub(file_diff.summary.added as usize),
-                right_start: (cursor).saturating_sub(file_diff.summary.removed as usize),
-            },
-
-            // When comparing a version of the crate to itself, this group will
-            // always contain the full text of the file. Don't collapse it.
-            in_context: is_identical_version,
-        });
+        let range = ChunkInfo {
+            range: cursor..file_diff.changes.len(),
+            left_start: (cursor).saturating_sub(file_diff.summary.added as usize),
+            right_start: (cursor).saturating_sub(file_diff.summary.removed as usize),
+        };
+        // When comparing a version of the crate to itself, this group will
+        // always contain the full text of the file. Don't collapse it.
+        groups.push((range, is_identical_version));
     }
+    groups
+}
 
+#[derive(Properties, PartialEq)]
+struct RenderedFileProps {
+    title: String,
+    data: Option<Bytes>,
+}
+
+/// Show a markdown file rendered, or a placeholder if it does not exist.
+#[function_component]
+fn RenderedFile(props: &RenderedFileProps) -> Html {
+    let html = use_memo(props.data.clone(), |data| {
+        data.as_ref()
+            .map(|data| AttrValue::from(render_markdown(&String::from_utf8_lossy(data))))
+    });
     html! {
-        <div class="diff-view">
-            <div class="header">
-                <FileIcon />
-                <span class="filename">{props.path.file_name().unwrap_or("")}</span>
-            </div>
-            <div class="content">
-                {
-                    if summary == &(0,0) {
-                        html! {<FileDisplayView {stack} />}
-                    } else {
-                        html! {<UnifiedDiffView {stack} />}
-                    }
-                }
+        <div class="rendered-file">
+            <div class="title">{&props.title}</div>
+            if let Some(html) = &*html {
+                <div class="prose prose-slate dark:prose-invert max-w-none">
+                    { Html::from_html_unchecked(html.clone()) }
+                </div>
+            } else {
+                <div class="missing">{"File does not exist in this version"}</div>
+            }
+        </div>
+    }
+}
+
+/// Show old and new version of a markdown file rendered side by side.
+#[function_component]
+fn RenderedDiffView(props: &DiffViewProps) -> Html {
+    let diff = &props.diff;
+    let title = |version: &VersionInfo| format!("{} v{}", version.krate, version.version);
+    let left = diff.left.files.get(&props.path).cloned();
+    let right = diff.right.files.get(&props.path).cloned();
+    if diff.left.version == diff.right.version || left == right {
+        return html! {
+            <div class="rendered">
+                <RenderedFile title={title(&diff.right.version)} data={right} />
             </div>
+        };
+    }
+    html! {
+        <div class="rendered split">
+            <RenderedFile title={title(&diff.left.version)} data={left} />
+            <RenderedFile title={title(&diff.right.version)} data={right} />
         </div>
     }
 }
@@ -134,13 +377,15 @@ pub fn DiffView(props: &DiffViewProps) -> Html {
 #[derive(Properties, PartialEq)]
 pub struct AnyDiffViewProps {
     stack: Vec<DiffGroupInfo>,
+    #[prop_or_default]
+    expand_all: bool,
 }
 
 #[function_component]
 pub fn UnifiedDiffView(props: &AnyDiffViewProps) -> Html {
     let mut overall_index = 0;
     html! {
-        <div class="overflow-x-scroll bg-white">
+        <div class="overflow-x-scroll hl-code">
             <div class="unified">
             {
                 props.stack.iter()
@@ -151,6 +396,7 @@ pub fn UnifiedDiffView(props: &AnyDiffViewProps) -> Html {
                                 group={group.clone()}
                                 {in_context}
                                 group_start_index={(overall_index, range.left_start, range.right_start)}
+                                expand_all={props.expand_all}
                             />
                         };
                         overall_index += group.len();
@@ -167,7 +413,7 @@ pub fn UnifiedDiffView(props: &AnyDiffViewProps) -> Html {
 pub fn FileDisplayView(props: &AnyDiffViewProps) -> Html {
     let mut overall_index = 0;
     html! {
-        <div class="overflow-x-scroll bg-white">
+        <div class="overflow-x-scroll hl-code">
             <div class="unified">
             {
                 props.stack.iter()
@@ -189,12 +435,14 @@ pub fn FileDisplayView(props: &AnyDiffViewProps) -> Html {
     }
 }
 
+/// Show old and new lines side by side, pairing removed lines with the lines added in their
+/// place.
 #[function_component]
 pub fn SplitDiffView(props: &AnyDiffViewProps) -> Html {
     let mut overall_index = 0;
     html! {
-        <div class="p-2 overflow-x-scroll bg-white">
-            <pre class="bg-white">
+        <div class="overflow-x-scroll hl-code">
+            <div class="side-by-side">
             {
                 props.stack.iter()
                     .map(|DiffGroupInfo {group, range, in_context}| {
@@ -204,6 +452,8 @@ pub fn SplitDiffView(props: &AnyDiffViewProps) -> Html {
                                 group={group.clone()}
                                 {in_context}
                                 group_start_index={(overall_index, range.left_start, range.right_start)}
+                                expand_all={props.expand_all}
+                                style={DiffStyle::Split}
                             />
                         };
                         overall_index += group.len();
@@ -211,7 +461,7 @@ pub fn SplitDiffView(props: &AnyDiffViewProps) -> Html {
                     })
                     .collect::<Html>()
             }
-            </pre>
+            </div>
         </div>
     }
 }
@@ -227,20 +477,56 @@ fn ExpandIcon() -> Html {
 
 #[derive(Properties, PartialEq)]
 pub struct DiffLineGroupProps {
-    group: Vec<(ChangeTag, Vec<(Style, bytes::Bytes)>)>,
+    group: Vec<(ChangeTag, HighlightedLine)>,
     in_context: bool,
     group_start_index: (usize, usize, usize),
+    #[prop_or_default]
+    expand_all: bool,
+    #[prop_or_default]
+    style: DiffStyle,
 }
 
 #[derive(Properties, PartialEq)]
 pub struct DisplayGroupProps {
-    group: Vec<Vec<(Style, bytes::Bytes)>>,
+    group: Vec<HighlightedLine>,
     group_start_index: usize,
 }
 
 #[function_component]
 pub fn DiffLineGroup(props: &DiffLineGroupProps) -> Html {
-    let folded = use_state(|| !props.in_context);
+    // line of the permalink, if it is in this group
+    let permalink = use_permalink().filter(|(side, line)| {
+        let (start, tag) = match side {
+            ChangeTag::Delete => (props.group_start_index.1, ChangeTag::Insert),
+            _ => (props.group_start_index.2, ChangeTag::Delete),
+        };
+        let lines = props
+            .group
+            .iter()
+            .filter(|(other, _)| *other != tag)
+            .count();
+        (start + 1..=start + lines).contains(line)
+    });
+    let folded = use_state(|| !props.in_context && permalink.is_none());
+    {
+        let folded = folded.clone();
+        let in_context = props.in_context;
+        use_effect_with(props.expand_all, move |expand_all| {
+            folded.set(!in_context && !expand_all && permalink.is_none());
+        });
+    }
+    // unfold the group to show the line of the permalink
+    {
+        let folded = folded.clone();
+        use_effect_with((permalink, *folded), move |(permalink, is_folded)| {
+            if let Some(permalink) = *permalink {
+                match is_folded {
+                    true => folded.set(false),
+                    false => scroll_to_line(permalink),
+                }
+            }
+        });
+    }
     let onclick = {
         let folded = folded.clone();
         Callback::from(move |_| folded.set(!*folded))
@@ -277,12 +563,18 @@ pub fn DiffLineGroup(props: &DiffLineGroupProps) -> Html {
                 </button>
             </div>
         }
+    } else if props.style == DiffStyle::Split {
+        split_lines(
+            &props.group,
+            (start_index.1, start_index.2),
+            props.in_context,
+        )
     } else {
         let (mut left_idx, mut right_idx) = (start_index.1, start_index.2);
         html! {
             <>
             {
-                props.group.iter().map(|(tag, change)| {
+                props.group.iter().enumerate().map(|(index, (tag, change))| {
                     let (sign, class, left, right) = match tag {
                         ChangeTag::Delete => ("-", "deletion", Some(left_idx), None),
                         ChangeTag::Insert => ("+", "insertion", None, Some(right_idx)),
@@ -294,8 +586,14 @@ pub fn DiffLineGroup(props: &DiffLineGroupProps) -> Html {
                         ChangeTag::Equal => (left_idx + 1, right_idx + 1),
                     };
 
+                    // the first line of a group with changes starts a hunk
+                    let hunk = props.in_context && index == 0;
+                    let target = permalink.is_some_and(|(side, line)| match side {
+                        ChangeTag::Delete => left == Some(line),
+                        _ => right == Some(line),
+                    });
                     html! {
-                        <div class={classes!("line", class)}>
+                        <div class={classes!("line", class, hunk.then_some("hunk"), target.then_some("target"))}>
                             <a id={left.map(|i| format!("L{i}"))} class="line-number">
                                 if let Some(index) = left {
                                     {index}
@@ -312,7 +610,7 @@ pub fn DiffLineGroup(props: &DiffLineGroupProps) -> Html {
                                 }
                             </div>
                             <div class="code-line">
-                                <CodeLine stack={change.clone()} />
+                                <CodeLine line={change.clone()} />
                             </div>
                         </div>
                     }
@@ -323,22 +621,121 @@ pub fn DiffLineGroup(props: &DiffLineGroupProps) -> Html {
     }
 }
 
+/// Line on one side of a side by side diff, with its line number.
+pub type SplitLine<'a, T> = Option<(usize, ChangeTag, &'a T)>;
+
+/// Pair the lines of a group as rows of old and new lines, with their line numbers.
+///
+/// Runs of removed lines are paired with the runs of added lines which follow them.
+pub fn split_rows<T>(
+    group: &[(ChangeTag, T)],
+    (mut left, mut right): (usize, usize),
+) -> Vec<(SplitLine<'_, T>, SplitLine<'_, T>)> {
+    let mut rows = vec![];
+    let mut removed = vec![];
+    let mut added = vec![];
+    let flush = |rows: &mut Vec<_>, removed: &mut Vec<_>, added: &mut Vec<_>| {
+        let len = removed.len().max(added.len());
+        let mut removed = std::mem::take(removed).into_iter();
+        let mut added = std::mem::take(added).into_iter();
+        rows.extend((0..len).map(|_| (removed.next(), added.next())));
+    };
+    for (tag, line) in group {
+        match tag {
+            ChangeTag::Delete => {
+                if !added.is_empty() {
+                    flush(&mut rows, &mut removed, &mut added);
+                }
+                removed.push((left, *tag, line));
+                left += 1;
+            }
+            ChangeTag::Insert => {
+                added.push((right, *tag, line));
+                right += 1;
+            }
+            ChangeTag::Equal => {
+                flush(&mut rows, &mut removed, &mut added);
+                rows.push((Some((left, *tag, line)), Some((right, *tag, line))));
+                left += 1;
+                right += 1;
+            }
+        }
+    }
+    flush(&mut rows, &mut removed, &mut added);
+    rows
+}
+
+/// Render the lines of a group side by side.
+fn split_lines(
+    group: &[(ChangeTag, HighlightedLine)],
+    start: (usize, usize),
+    in_context: bool,
+) -> Html {
+    let side = |line: SplitLine<'_, HighlightedLine>, prefix: &str| match line {
+        Some((number, tag, stack)) => {
+            let class = match tag {
+                ChangeTag::Delete => "deletion",
+                ChangeTag::Insert => "insertion",
+                ChangeTag::Equal => "unchanged",
+            };
+            html! {
+                <>
+                    <a id={format!("{prefix}{number}")} class={classes!("line-number", class)}>
+                        {number}
+                    </a>
+                    <div class={classes!("code-line", class)}>
+                        <CodeLine line={stack.clone()} />
+                    </div>
+                </>
+            }
+        }
+        None => html! {
+            <>
+                <div class="line-number empty"></div>
+                <div class="code-line empty"></div>
+            </>
+        },
+    };
+    split_rows(group, start)
+        .into_iter()
+        .enumerate()
+        .map(|(index, (left, right))| {
+            // the first line of a group with changes starts a hunk
+            let hunk = in_context && index == 0;
+            html! {
+                <div class={classes!("line", hunk.then_some("hunk"))}>
+                    {side(left, "L")}
+                    {side(right, "R")}
+                </div>
+            }
+        })
+        .collect()
+}
+
 #[function_component]
 pub fn FileView(props: &DisplayGroupProps) -> Html {
+    // both versions of displayed files are the same, so either side refers to the same line
+    let permalink = use_permalink().map(|(_, line)| line).filter(|line| {
+        (props.group_start_index + 1..=props.group_start_index + props.group.len()).contains(line)
+    });
+    use_effect_with(permalink, |permalink| {
+        if let Some(line) = *permalink {
+            scroll_to_line((ChangeTag::Insert, line));
+        }
+    });
     props
         .group
         .iter()
         .enumerate()
         .map(|(index, change)| {
+            let line = index + 1 + props.group_start_index;
             html! {
-                <div class={classes!("line", "unchanged")}>
-                    <div class={classes!("line-number", "file-view")}>
-                        {
-                            format!("{}", index+1+ props.group_start_index)
-                        }
-                    </div>
+                <div class={classes!("line", "unchanged", (permalink == Some(line)).then_some("target"))}>
+                    <a id={format!("R{line}")} class={classes!("line-number", "file-view")}>
+                        {line}
+                    </a>
                     <div class="code-line">
-                        <CodeLine stack={change.clone()} />
+                        <CodeLine line={change.clone()} />
                     </div>
                 </div>
             }
@@ -348,20 +745,420 @@ pub fn FileView(props: &DisplayGroupProps) -> Html {
 
 #[derive(Properties, PartialEq)]
 pub struct CodeLineProps {
-    stack: Vec<(Style, Bytes)>,
+    pub line: HighlightedLine,
 }
 
+/// Render a highlighted line, its classes are colored by the stylesheet of the current theme.
 #[function_component]
 pub fn CodeLine(props: &CodeLineProps) -> Html {
-    props
-        .stack
-        .iter()
-        .map(|(style, text)| {
-            let style = syntect_style_to_css(style);
-            let contents = String::from_utf8_lossy(&text[..]);
-            html! {
-                <span style={style}>{contents}</span>
+    Html::from_html_unchecked(AttrValue::from(props.line.clone()))
+}
+
+/// Number of lines above which the diff of a file is rendered virtually.
+const VIRTUAL_THRESHOLD: usize = 2000;
+
+/// Height of a line in pixels when rendering virtually, needs to match the stylesheet.
+const ROW_HEIGHT: f64 = 24.0;
+
+/// Number of lines which are rendered above and below the viewport when rendering virtually.
+const OVERSCAN: usize = 50;
+
+/// Row of a virtually rendered diff.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum VirtualRow {
+    /// Line of the file, with its index in the changes and its old and new line number, and
+    /// whether it starts a hunk
+    Line {
+        index: usize,
+        tag: ChangeTag,
+        left: Option<usize>,
+        right: Option<usize>,
+        hunk: bool,
+    },
+    /// Folded group of unchanged lines, with the old and new line numbers of the first and last
+    /// line
+    Fold {
+        group: usize,
+        start: (usize, usize),
+        end: (usize, usize),
+    },
+}
+
+/// Lay out the groups of a diff as rows, folding groups which are out of context and were not
+/// expanded, unless all groups are expanded.
+///
+/// When displaying an unchanged file, every line is shown and numbered as the new line.
+fn virtual_rows(
+    groups: &[(ChunkInfo, bool)],
+    changes: &[(ChangeTag, Bytes)],
+    display: bool,
+    expanded: &BTreeSet<usize>,
+    expand_all: bool,
+) -> Vec<VirtualRow> {
+    let mut rows = vec![];
+    for (group, (range, in_context)) in groups.iter().enumerate() {
+        // go from 0-indexed to 1-indexed
+        let (mut left, mut right) = (range.left_start + 1, range.right_start + 1);
+        if !display && !in_context && !expand_all && !expanded.contains(&group) {
+            // use the fact that folded sections never contain changes
+            let last = range.range.len().saturating_sub(1);
+            rows.push(VirtualRow::Fold {
+                group,
+                start: (left, right),
+                end: (left + last, right + last),
+            });
+            continue;
+        }
+        for index in range.range.clone() {
+            let tag = changes[index].0;
+            if display {
+                rows.push(VirtualRow::Line {
+                    index,
+                    tag,
+                    left: None,
+                    right: Some(index + 1),
+                    hunk: false,
+                });
+                continue;
             }
-        })
-        .collect::<Html>()
+            let (row_left, row_right) = match tag {
+                ChangeTag::Delete => (Some(left), None),
+                ChangeTag::Insert => (None, Some(right)),
+                ChangeTag::Equal => (Some(left), Some(right)),
+            };
+            left += row_left.is_some() as usize;
+            right += row_right.is_some() as usize;
+            rows.push(VirtualRow::Line {
+                index,
+                tag,
+                left: row_left,
+                right: row_right,
+                hunk: *in_context && index == range.range.start,
+            });
+        }
+    }
+    rows
+}
+
+/// Line which the fragment of the URL refers to, such as `#L12` for line 12 of the old file or
+/// `#R12` for line 12 of the new file.
+fn permalink_line() -> Option<(ChangeTag, usize)> {
+    let hash = web_sys::window()?.location().hash().ok()?;
+    let hash = hash.strip_prefix('#')?;
+    if let Some(line) = hash.strip_prefix('L') {
+        return Some((ChangeTag::Delete, line.parse().ok()?));
+    }
+    Some((ChangeTag::Insert, hash.strip_prefix('R')?.parse().ok()?))
+}
+
+/// Line which the fragment of the URL refers to, updated whenever the fragment changes.
+#[hook]
+fn use_permalink() -> Option<(ChangeTag, usize)> {
+    let permalink = use_state_eq(permalink_line);
+    {
+        let permalink = permalink.clone();
+        use_effect_with((), move |_| {
+            let listener = Closure::<dyn Fn()>::new(move || permalink.set(permalink_line()));
+            let window = web_sys::window();
+            if let Some(window) = &window {
+                let _ = window.add_event_listener_with_callback(
+                    "hashchange",
+                    listener.as_ref().unchecked_ref(),
+                );
+            }
+            move || {
+                if let Some(window) = &window {
+                    let _ = window.remove_event_listener_with_callback(
+                        "hashchange",
+                        listener.as_ref().unchecked_ref(),
+                    );
+                }
+                drop(listener);
+            }
+        });
+    }
+    *permalink
+}
+
+/// Scroll the line which a permalink refers to into view, a third of the way down the viewport.
+fn scroll_to_line((side, line): (ChangeTag, usize)) {
+    let prefix = if side == ChangeTag::Delete { 'L' } else { 'R' };
+    let Some(window) = web_sys::window() else {
+        return;
+    };
+    let element = window
+        .document()
+        .and_then(|document| document.get_element_by_id(&format!("{prefix}{line}")));
+    if let Some(element) = element {
+        let viewport = window
+            .inner_height()
+            .ok()
+            .and_then(|height| height.as_f64())
+            .unwrap_or_default();
+        let top = element.get_bounding_client_rect().top();
+        window.scroll_by_with_x_and_y(0.0, top - viewport / 3.0);
+    }
+}
+
+/// Determines if a row shows the given line of the old (`Delete`) or new (`Insert`) file.
+fn row_shows_line(row: &VirtualRow, (side, line): (ChangeTag, usize)) -> bool {
+    match (row, side) {
+        (VirtualRow::Line { left, .. }, ChangeTag::Delete) => *left == Some(line),
+        (VirtualRow::Line { right, .. }, _) => *right == Some(line),
+        (VirtualRow::Fold { start, end, .. }, ChangeTag::Delete) => {
+            (start.0..=end.0).contains(&line)
+        }
+        (VirtualRow::Fold { start, end, .. }, _) => (start.1..=end.1).contains(&line),
+    }
+}
+
+#[derive(Properties, PartialEq)]
+struct VirtualDiffViewProps {
+    path: Utf8PathBuf,
+    file_diff: Rc<FileDiff>,
+    is_identical_version: bool,
+    /// Whether the file is unchanged, and is displayed without a diff
+    display: bool,
+    expand_all: bool,
+}
+
+/// Show the diff of a large file, only rendering and highlighting the lines close to the viewport.
+///
+/// Every row has the same height, lines are not wrapped. Large files are always shown unified.
+#[function_component]
+fn VirtualDiffView(props: &VirtualDiffViewProps) -> Html {
+    let node = use_node_ref();
+    let highlighter = use_memo(
+        (props.path.clone(), props.file_diff.clone()),
+        |(path, file_diff)| {
+            let syntax = infer_syntax_for_changes(path.as_str(), &file_diff.changes);
+            info!("Highlighting {} as {} on demand", syntax.name, path);
+            LazyHighlighter::new(syntax, file_diff.changes.clone())
+        },
+    );
+    let groups = use_memo(
+        (props.file_diff.clone(), props.is_identical_version),
+        |(file_diff, is_identical_version)| diff_groups(file_diff, *is_identical_version),
+    );
+    let expanded = use_state(BTreeSet::<usize>::new);
+    let rows = use_memo(
+        (
+            groups.clone(),
+            (*expanded).clone(),
+            props.display,
+            props.expand_all,
+        ),
+        |(groups, expanded, display, expand_all)| {
+            virtual_rows(
+                groups,
+                &props.file_diff.changes,
+                *display,
+                expanded,
+                *expand_all,
+            )
+        },
+    );
+    let window = use_state_eq(|| 0..OVERSCAN);
+
+    // update the rendered rows whenever the page is scrolled or resized
+    {
+        let node = node.clone();
+        let window = window.clone();
+        use_effect_with(rows.len(), move |rows| {
+            let rows = *rows;
+            let update = move || {
+                let Some(element) = node.cast::<Element>() else {
+                    return;
+                };
+                let viewport = web_sys::window()
+                    .and_then(|window| window.inner_height().ok())
+                    .and_then(|height| height.as_f64())
+                    .unwrap_or_default();
+                let top = -element.get_bounding_client_rect().top();
+                let first = (top / ROW_HEIGHT).floor().max(0.0) as usize;
+                let last = ((top + viewport) / ROW_HEIGHT).ceil().max(0.0) as usize;
+                let first = first.saturating_sub(OVERSCAN).min(rows);
+                window.set(first..(last + OVERSCAN).clamp(first, rows));
+            };
+            update();
+            let listener = Closure::<dyn Fn()>::new(update);
+            let target = web_sys::window();
+            for event in ["scroll", "resize"] {
+                if let Some(target) = &target {
+                    let _ = target
+                        .add_event_listener_with_callback(event, listener.as_ref().unchecked_ref());
+                }
+            }
+            move || {
+                for event in ["scroll", "resize"] {
+                    if let Some(target) = &target {
+                        let _ = target.remove_event_listener_with_callback(
+                            event,
+                            listener.as_ref().unchecked_ref(),
+                        );
+                    }
+                }
+                drop(listener);
+            }
+        });
+    }
+
+    // scroll to the line of a permalink, unfolding it if needed
+    let permalink = use_permalink();
+    let scroll_target = use_state(|| permalink);
+    {
+        let scroll_target = scroll_target.clone();
+        use_effect_with(permalink, move |permalink| scroll_target.set(*permalink));
+    }
+    {
+        let node = node.clone();
+        let expanded = expanded.clone();
+        let scroll_target = scroll_target.clone();
+        use_effect_with((rows.clone(), *scroll_target), move |(rows, target)| {
+            let Some(target) = *target else {
+                return;
+            };
+            match rows.iter().position(|row| row_shows_line(row, target)) {
+                Some(index) => {
+                    if let VirtualRow::Fold { group, .. } = rows[index] {
+                        let mut groups = (*expanded).clone();
+                        groups.insert(group);
+                        expanded.set(groups);
+                        return;
+                    }
+                    let window = web_sys::window();
+                    if let (Some(window), Some(element)) = (window, node.cast::<Element>()) {
+                        let top = element.get_bounding_client_rect().top()
+                            + window.scroll_y().unwrap_or_default();
+                        let viewport = window
+                            .inner_height()
+                            .ok()
+                            .and_then(|height| height.as_f64())
+                            .unwrap_or_default();
+                        window.scroll_to_with_x_and_y(
+                            0.0,
+                            top + index as f64 * ROW_HEIGHT - viewport / 3.0,
+                        );
+                    }
+                    scroll_target.set(None);
+                }
+                None => scroll_target.set(None),
+            }
+        });
+    }
+
+    let range = window.start.min(rows.len())..window.end.min(rows.len());
+    let visible = &rows[range.clone()];
+    let mut indices = visible.iter().filter_map(|row| match row {
+        VirtualRow::Line { index, .. } => Some(*index),
+        VirtualRow::Fold { .. } => None,
+    });
+    // lines are in order, so only the first and last visible line are needed
+    let first = indices.clone().next().unwrap_or_default();
+    let last = indices
+        .next_back()
+        .map(|index| index + 1)
+        .unwrap_or_default();
+    let highlighted = highlighter.lines(first..last);
+
+    let digits = props.file_diff.changes.len().to_string().len();
+    let columns = if props.display {
+        format!("calc({digits}ch + 1rem) 0 0 1fr")
+    } else {
+        format!("calc({digits}ch + 1rem) calc({digits}ch + 1rem) calc(1ch + 1rem) 1fr")
+    };
+
+    let rows_html = visible.iter().enumerate().map(|(offset, row)| {
+        let key = range.start + offset;
+        let target = permalink.is_some_and(|line| row_shows_line(row, line));
+        match *row {
+            VirtualRow::Line { index, right, .. } if props.display => html! {
+                <div {key} class={classes!("line", "unchanged", target.then_some("target"))}>
+                    <div class={classes!("line-number", "file-view")}>
+                        {right.unwrap_or_default()}
+                    </div>
+                    <div class="code-line">
+                        <CodeLine line={highlighted[index - first].1.clone()} />
+                    </div>
+                </div>
+            },
+            VirtualRow::Line {
+                index,
+                tag,
+                left,
+                right,
+                hunk,
+            } => {
+                let (sign, class) = match tag {
+                    ChangeTag::Delete => ("-", "deletion"),
+                    ChangeTag::Insert => ("+", "insertion"),
+                    ChangeTag::Equal => (" ", "unchanged"),
+                };
+                html! {
+                    <div
+                        {key}
+                        class={classes!("line", class, hunk.then_some("hunk"), target.then_some("target"))}
+                    >
+                        <a id={left.map(|i| format!("L{i}"))} class="line-number">
+                            if let Some(index) = left {
+                                {index}
+                            }
+                        </a>
+                        <a id={right.map(|i| format!("R{i}"))} class="line-number">
+                            if let Some(index) = right {
+                                {index}
+                            }
+                        </a>
+                        <div class="change-icon">{sign}</div>
+                        <div class="code-line">
+                            <CodeLine line={highlighted[index - first].1.clone()} />
+                        </div>
+                    </div>
+                }
+            }
+            VirtualRow::Fold { group, start, end } => {
+                let onclick = {
+                    let expanded = expanded.clone();
+                    Callback::from(move |_| {
+                        let mut groups = (*expanded).clone();
+                        groups.insert(group);
+                        expanded.set(groups);
+                    })
+                };
+                let info = if start.0 == start.1 {
+                    format!("Show lines {:?} to {:?}", start.0, end.0)
+                } else {
+                    format!("Show lines {start:?} to {end:?}")
+                };
+                html! {
+                    <div {key} class="expand">
+                        <button class={classes!("button")} onclick={onclick.clone()}>
+                            <ExpandIcon />
+                        </button>
+                        <button class={classes!("info")} {onclick}>{info}</button>
+                    </div>
+                }
+            }
+        }
+    });
+
+    html! {
+        <div class="overflow-x-scroll hl-code">
+            <div
+                class="virtual"
+                ref={node}
+                style={format!("height: {}px", rows.len() as f64 * ROW_HEIGHT)}
+            >
+                <div
+                    class="unified"
+                    style={format!(
+                        "transform: translateY({}px); grid-template-columns: {columns}",
+                        range.start as f64 * ROW_HEIGHT,
+                    )}
+                >
+                    { for rows_html }
+                </div>
+            </div>
+        </div>
+    }
 }
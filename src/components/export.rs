@@ -0,0 +1,77 @@
+use crate::{
+    data::VersionDiff,
+    export::{file_name, ExportFormat},
+};
+use camino::Utf8PathBuf;
+use gloo_file::{Blob, ObjectUrl};
+use std::{cell::RefCell, rc::Rc};
+use wasm_bindgen::JsCast;
+use web_sys::HtmlElement;
+use yew::prelude::*;
+
+/// Offer a file for download, by clicking a temporary link to it.
+///
+/// Returns the URL of the file, which needs to be kept alive until the download has started.
+fn download(name: &str, format: ExportFormat, content: &str) -> Option<ObjectUrl> {
+    let url = ObjectUrl::from(Blob::new_with_options(content, Some(format.mime_type())));
+    let document = web_sys::window()?.document()?;
+    let link: HtmlElement = document.create_element("a").ok()?.dyn_into().ok()?;
+    link.set_attribute("href", &url).ok()?;
+    link.set_attribute("download", name).ok()?;
+    link.click();
+    Some(url)
+}
+
+#[derive(Properties, PartialEq, Clone)]
+pub struct ExportMenuProps {
+    pub diff: Rc<VersionDiff>,
+    /// Selected file or directory, which can be exported on its own
+    #[prop_or_default]
+    pub path: Utf8PathBuf,
+}
+
+/// Menu to export the diff of all files, or of the selected file, in every export format.
+#[function_component]
+pub fn ExportMenu(props: &ExportMenuProps) -> Html {
+    // the URL of the last download, which is released on the next one
+    let url = use_mut_ref(|| None::<ObjectUrl>);
+
+    let mut scopes = vec![("All files".to_string(), Utf8PathBuf::new())];
+    if !props.path.as_str().is_empty() {
+        scopes.push((props.path.to_string(), props.path.clone()));
+    }
+
+    let buttons = |path: &Utf8PathBuf| {
+        ExportFormat::ALL.map(|format| {
+            let onclick = {
+                let diff = props.diff.clone();
+                let path = path.clone();
+                let url: Rc<RefCell<_>> = url.clone();
+                Callback::from(move |_| {
+                    let content = format.export(&diff, &path);
+                    *url.borrow_mut() =
+                        download(&file_name(&diff, &path, format), format, &content);
+                })
+            };
+            html! {
+                <button class="button" {onclick}>{format.name()}</button>
+            }
+        })
+    };
+
+    html! {
+        <details class="export">
+            <summary>{"Export"}</summary>
+            <div class="menu">
+                {
+                    for scopes.iter().map(|(title, path)| html! {
+                        <div class="scope">
+                            <span class="title">{title}</span>
+                            { for buttons(path) }
+                        </div>
+                    })
+                }
+            </div>
+        </details>
+    }
+}
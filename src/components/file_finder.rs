@@ -0,0 +1,323 @@
+use crate::{
+    components::{estimate_prefix, Context, ESTIMATE_TITLE},
+    data::{Changes, VersionDiff},
+    Route,
+};
+use camino::Utf8PathBuf;
+use std::rc::Rc;
+use web_sys::HtmlInputElement;
+use yew::prelude::*;
+use yew_router::prelude::*;
+
+/// Maximum number of files which are listed by the file finder.
+const MAX_RESULTS: usize = 50;
+
+/// Score of every matched character.
+const SCORE_MATCH: i32 = 16;
+/// Bonus for matching the first character of a path segment.
+const BONUS_SEGMENT: i32 = 10;
+/// Bonus for matching the first character of a word, such as after `_`, `-` or `.`, or at a
+/// camelCase boundary.
+const BONUS_WORD: i32 = 8;
+/// Bonus for matching a character directly after the previously matched one.
+const BONUS_CONSECUTIVE: i32 = 6;
+/// Bonus for matching a character of the file name rather than of its directories.
+const BONUS_FILE_NAME: i32 = 2;
+/// Penalty for skipping characters between two matched characters.
+const PENALTY_GAP_START: i32 = 3;
+/// Penalty for every skipped character between two matched characters.
+const PENALTY_GAP_EXTENSION: i32 = 1;
+
+/// Result of matching a query against a path.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FuzzyMatch {
+    /// Score of the match, better matches have a higher score
+    pub score: i32,
+    /// Indices of the characters of the path which matched the query
+    pub positions: Vec<usize>,
+}
+
+/// Lowercase a single character, keeping it if it lowercases to several characters.
+fn lowercase(c: char) -> char {
+    let mut lower = c.to_lowercase();
+    match (lower.next(), lower.next()) {
+        (Some(lower), None) => lower,
+        _ => c,
+    }
+}
+
+/// Bonus for matching the character at the given index of the path.
+fn boundary_bonus(path: &[char], index: usize) -> i32 {
+    let Some(previous) = index.checked_sub(1).map(|index| path[index]) else {
+        return BONUS_SEGMENT;
+    };
+    let current = path[index];
+    if previous == '/' {
+        BONUS_SEGMENT
+    } else if (!previous.is_alphanumeric() && current.is_alphanumeric())
+        || (previous.is_lowercase() && current.is_uppercase())
+    {
+        BONUS_WORD
+    } else {
+        0
+    }
+}
+
+/// Match the characters of the query against the path, in order and ignoring case.
+///
+/// Returns the best scoring match, preferring characters at the start of path segments and words
+/// (including `snake_case` and `camelCase` boundaries), consecutive characters and characters of
+/// the file name. Whitespace in the query is ignored.
+pub fn fuzzy_match(query: &str, path: &str) -> Option<FuzzyMatch> {
+    let query: Vec<char> = query
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .map(lowercase)
+        .collect();
+    let path: Vec<char> = path.chars().collect();
+    let lower: Vec<char> = path.iter().copied().map(lowercase).collect();
+    if query.len() > path.len() {
+        return None;
+    }
+    let file_name = path
+        .iter()
+        .rposition(|c| *c == '/')
+        .map_or(0, |index| index + 1);
+    let bonus: Vec<i32> = (0..path.len())
+        .map(|index| {
+            let file_name_bonus = if index >= file_name {
+                BONUS_FILE_NAME
+            } else {
+                0
+            };
+            SCORE_MATCH + boundary_bonus(&path, index) + file_name_bonus
+        })
+        .collect();
+
+    // best score of matching the query up to each character when matching it at each index of
+    // the path, along with the index of the previous matched character
+    let mut rows: Vec<Vec<Option<(i32, usize)>>> = Vec::with_capacity(query.len());
+    for (row, c) in query.iter().enumerate() {
+        let mut scores = vec![None; path.len()];
+        // best score of the previous row before the current index, offset by the gap penalty
+        let mut gap_best: Option<(i32, usize)> = None;
+        for index in 0..path.len() {
+            if row > 0 && index >= 2 {
+                if let Some((score, _)) = rows[row - 1][index - 2] {
+                    let offset = score + (index as i32 - 2) * PENALTY_GAP_EXTENSION;
+                    if gap_best.is_none_or(|(best, _)| offset > best) {
+                        gap_best = Some((offset, index - 2));
+                    }
+                }
+            }
+            if lower[index] != *c {
+                continue;
+            }
+            scores[index] = if row == 0 {
+                Some((bonus[index], 0))
+            } else {
+                let consecutive = index
+                    .checked_sub(1)
+                    .and_then(|previous| {
+                        rows[row - 1][previous].map(|(score, _)| (score, previous))
+                    })
+                    .map(|(score, previous)| (score + BONUS_CONSECUTIVE, previous));
+                let gap = gap_best.map(|(offset, previous)| {
+                    let skipped = offset - (index as i32 - 1) * PENALTY_GAP_EXTENSION;
+                    (skipped - PENALTY_GAP_START, previous)
+                });
+                consecutive
+                    .into_iter()
+                    .chain(gap)
+                    .max_by_key(|(score, _)| *score)
+                    .map(|(score, previous)| (score + bonus[index], previous))
+            };
+        }
+        rows.push(scores);
+    }
+
+    let Some(last) = rows.last() else {
+        return Some(FuzzyMatch {
+            score: 0,
+            positions: vec![],
+        });
+    };
+    let (mut index, score) = last
+        .iter()
+        .enumerate()
+        .filter_map(|(index, score)| score.map(|(score, _)| (index, score)))
+        .max_by_key(|(index, score)| (*score, std::cmp::Reverse(*index)))?;
+    let mut positions = vec![index];
+    for row in rows.iter().skip(1).rev() {
+        index = row[index].map(|(_, previous)| previous)?;
+        positions.push(index);
+    }
+    positions.reverse();
+    Some(FuzzyMatch { score, positions })
+}
+
+/// Rank paths by how well they match the query, returning the indices of the matching paths
+/// along with their matches, best match first.
+///
+/// Matches with the same score are ordered by the length of the path, and then by their order.
+pub fn fuzzy_rank<'a>(
+    query: &str,
+    paths: impl IntoIterator<Item = &'a str>,
+) -> Vec<(usize, FuzzyMatch)> {
+    let mut matches: Vec<_> = paths
+        .into_iter()
+        .enumerate()
+        .filter_map(|(index, path)| {
+            fuzzy_match(query, path).map(|found| (index, path.chars().count(), found))
+        })
+        .collect();
+    matches.sort_by_key(|(index, length, found)| (std::cmp::Reverse(found.score), *length, *index));
+    matches
+        .into_iter()
+        .map(|(index, _, found)| (index, found))
+        .collect()
+}
+
+/// Render a path, highlighting the characters which matched the query.
+fn highlight_path(path: &str, positions: &[usize]) -> Html {
+    let mut positions = positions.iter().copied().peekable();
+    path.chars()
+        .enumerate()
+        .map(|(index, c)| {
+            if positions.next_if_eq(&index).is_some() {
+                html! { <span class="match">{c}</span> }
+            } else {
+                html! { {c} }
+            }
+        })
+        .collect()
+}
+
+#[derive(Properties, PartialEq)]
+pub struct FileFinderProps {
+    pub diff: Rc<VersionDiff>,
+    /// Route to a file of the diff, defaults to the file route of the crate versions
+    #[prop_or_default]
+    pub file_route: Option<Callback<Utf8PathBuf, Route>>,
+    /// Called when a file is chosen instead of navigating to it
+    #[prop_or_default]
+    pub onselect: Option<Callback<Utf8PathBuf>>,
+    pub onclose: Callback<()>,
+}
+
+/// Overlay to find a file of either crate version by typing parts of its path.
+#[function_component]
+pub fn FileFinder(props: &FileFinderProps) -> Html {
+    let query = use_state(String::new);
+    let selected = use_state(|| 0_usize);
+    let input = use_node_ref();
+    let navigator = use_navigator().unwrap();
+    {
+        let input = input.clone();
+        use_effect_with((), move |_| {
+            if let Some(input) = input.cast::<HtmlInputElement>() {
+                let _ = input.focus();
+            }
+        });
+    }
+
+    let files = use_memo(props.diff.clone(), |diff| diff.tree_files(false));
+    let results: Vec<(Utf8PathBuf, Changes, FuzzyMatch)> =
+        fuzzy_rank(&query, files.iter().map(|(path, _)| path.as_str()))
+            .into_iter()
+            .take(MAX_RESULTS)
+            .map(|(index, found)| (files[index].0.clone(), files[index].1, found))
+            .collect();
+    let selected_index = (*selected).min(results.len().saturating_sub(1));
+
+    let open = {
+        let context = Context::new(
+            &props.diff,
+            props.file_route.clone(),
+            props.onselect.clone(),
+        );
+        let onclose = props.onclose.clone();
+        Callback::from(move |path: Utf8PathBuf| {
+            onclose.emit(());
+            context.open(&navigator, path);
+        })
+    };
+    let oninput = {
+        let query = query.clone();
+        let selected = selected.clone();
+        move |event: InputEvent| {
+            if let Some(input) = event.target_dyn_into::<HtmlInputElement>() {
+                query.set(input.value());
+                selected.set(0);
+            }
+        }
+    };
+    let onkeydown = {
+        let selected = selected.clone();
+        let paths: Vec<Utf8PathBuf> = results.iter().map(|(path, ..)| path.clone()).collect();
+        let open = open.clone();
+        let onclose = props.onclose.clone();
+        move |event: KeyboardEvent| match event.key().as_str() {
+            "ArrowDown" => {
+                event.prevent_default();
+                selected.set((selected_index + 1).min(paths.len().saturating_sub(1)));
+            }
+            "ArrowUp" => {
+                event.prevent_default();
+                selected.set(selected_index.saturating_sub(1));
+            }
+            "Enter" => {
+                if let Some(path) = paths.get(selected_index) {
+                    open.emit(path.clone());
+                }
+            }
+            "Escape" => onclose.emit(()),
+            _ => {}
+        }
+    };
+    let onclose = props.onclose.reform(|_| ());
+
+    html! {
+        <div class="overlay" onclick={onclose}>
+            <div class="dialog file-finder" onclick={|event: MouseEvent| event.stop_propagation()}>
+                <input
+                    ref={input}
+                    type="search"
+                    aria-label="Find file"
+                    placeholder="Find file..."
+                    value={(*query).clone()}
+                    {oninput}
+                    {onkeydown}
+                />
+                <ul class="results">
+                    {
+                        for results.iter().enumerate().map(|(index, (path, changes, found))| {
+                            let onclick = open.reform({
+                                let path = path.clone();
+                                move |_| path.clone()
+                            });
+                            html! {
+                                <li class={classes!((index == selected_index).then_some("active"))}>
+                                    <button {onclick}>
+                                        <span class="path">{highlight_path(path.as_str(), &found.positions)}</span>
+                                        <span class="tags" title={changes.estimate.then_some(ESTIMATE_TITLE)}>
+                                            if changes.added > 0 {
+                                                <span class="tag added">{"+"}{estimate_prefix(changes.estimate)}{changes.added}</span>
+                                            }
+                                            if changes.removed > 0 {
+                                                <span class="tag removed">{"-"}{estimate_prefix(changes.estimate)}{changes.removed}</span>
+                                            }
+                                        </span>
+                                    </button>
+                                </li>
+                            }
+                        })
+                    }
+                    if results.is_empty() {
+                        <li class="empty">{"No matching files."}</li>
+                    }
+                </ul>
+            </div>
+        </div>
+    }
+}
@@ -1,5 +1,5 @@
 use crate::{
-    components::SearchGlass,
+    components::{estimate_prefix, file_anchor, SearchGlass, ESTIMATE_TITLE},
     data::{Changes, Entry, Item, VersionDiff},
     Link, Route, VersionId,
 };
@@ -8,17 +8,40 @@ use implicit_clone::unsync::IString;
 use std::rc::Rc;
 use web_sys::HtmlInputElement;
 use yew::prelude::*;
+use yew_router::prelude::Navigator;
 
+/// How the files of a diff are opened, shared by the file tree and the file finder.
 #[derive(PartialEq, Clone, Debug)]
-struct Context {
+pub struct Context {
     old_krate: String,
     old_version: VersionId,
     new_krate: String,
     new_version: VersionId,
+    route: Option<Callback<Utf8PathBuf, Route>>,
+    select: Option<Callback<Utf8PathBuf>>,
 }
 
 impl Context {
-    fn file_route(&self, path: Utf8PathBuf) -> Route {
+    pub fn new(
+        diff: &VersionDiff,
+        route: Option<Callback<Utf8PathBuf, Route>>,
+        select: Option<Callback<Utf8PathBuf>>,
+    ) -> Self {
+        Self {
+            old_krate: diff.left.version.krate.clone(),
+            old_version: diff.left.version.version.clone().into(),
+            new_krate: diff.right.version.krate.clone(),
+            new_version: diff.right.version.version.clone().into(),
+            route,
+            select,
+        }
+    }
+
+    /// Route to the file at the given path.
+    pub fn file_route(&self, path: Utf8PathBuf) -> Route {
+        if let Some(route) = &self.route {
+            return route.emit(path);
+        }
         Route::File {
             old_krate: self.old_krate.clone(),
             old_version: self.old_version.clone(),
@@ -28,6 +51,14 @@ impl Context {
         }
         .simplify()
     }
+
+    /// Open the file at the given path, by selecting it or navigating to its route.
+    pub fn open(&self, navigator: &Navigator, path: Utf8PathBuf) {
+        match &self.select {
+            Some(select) => select.emit(path),
+            None => navigator.push(&self.file_route(path)),
+        }
+    }
 }
 
 #[derive(Clone, Copy, Debug, PartialEq, Default)]
@@ -87,10 +118,20 @@ impl SearchFilter {
     }
 }
 
+/// Id of the input which filters the file tree, so that it can be focused.
+pub const FILE_FILTER_ID: &str = "file-filter";
+
 #[derive(Properties, PartialEq, Clone)]
 pub struct FileTreeProps {
     pub diff: Rc<VersionDiff>,
     pub path: Utf8PathBuf,
+    /// Route to a file, defaults to [`Route::File`] for the versions of the diff.
+    #[prop_or_default]
+    pub file_route: Option<Callback<Utf8PathBuf, Route>>,
+    /// Called when a file is selected, makes the tree act as a table of contents which links to
+    /// the files on the current page instead of navigating to them.
+    #[prop_or_default]
+    pub onselect: Option<Callback<Utf8PathBuf>>,
 }
 
 #[derive(Properties, PartialEq, Clone)]
@@ -180,10 +221,14 @@ fn FileEntry(props: &FileEntryProps) -> Html {
         return html! { <></> };
     }
 
-    html! {
+    let estimate = props.entry.changes.estimate;
+    let contents = html! {
         <>
-        <Link to={route} classes={classes!("file-entry", current.then_some("active"))}>
-            <button class={classes!("toggle", (*expanded).then_some("active"))} onclick={toggle_expand}>
+            // in table of contents mode, the whole entry toggles directories
+            <button
+                class={classes!("toggle", (*expanded).then_some("active"))}
+                onclick={props.context.select.is_none().then(|| toggle_expand.clone())}
+            >
                 if props.entry.item.is_dir() {
                     <ExpandIcon is_expanded={*expanded} />
                 }
@@ -198,15 +243,43 @@ fn FileEntry(props: &FileEntryProps) -> Html {
             <div class="name">
                 {&props.entry.name}
             </div>
-            <div class="tags">
+            <div class="tags" title={estimate.then_some(ESTIMATE_TITLE)}>
                 if props.entry.changes.added > 0 {
-                    <span class="tag added">{"+"}{props.entry.changes.added}</span>
+                    <span class="tag added">{"+"}{estimate_prefix(estimate)}{props.entry.changes.added}</span>
                 }
                 if props.entry.changes.removed > 0 {
-                    <span class="tag removed">{"-"}{props.entry.changes.removed}</span>
+                    <span class="tag removed">{"-"}{estimate_prefix(estimate)}{props.entry.changes.removed}</span>
                 }
             </div>
-        </Link>
+        </>
+    };
+    let classes = classes!("file-entry", current.then_some("active"));
+    let link = match &props.context.select {
+        // directories are expanded instead of selected
+        Some(_) if props.entry.item.is_dir() => html! {
+            <a href="#" class={classes} onclick={toggle_expand}>{contents}</a>
+        },
+        Some(select) => {
+            let onclick = {
+                let select = select.clone();
+                let path = path.clone();
+                move |event: MouseEvent| {
+                    event.prevent_default();
+                    select.emit(path.clone());
+                }
+            };
+            html! {
+                <a href={format!("#{}", file_anchor(&path))} class={classes} {onclick}>{contents}</a>
+            }
+        }
+        None => html! {
+            <Link to={route} {classes}>{contents}</Link>
+        },
+    };
+
+    html! {
+        <>
+        {link}
         if props.entry.item.is_dir() && *expanded {
             <SubTree
                 entry={props.entry.clone()}
@@ -279,12 +352,11 @@ pub fn FileTree(props: &FileTreeProps) -> Html {
     let prefix = Rc::new(Utf8PathBuf::default());
     let active = Rc::new(props.path.clone());
 
-    let context = Rc::new(Context {
-        old_krate: props.diff.left.version.krate.clone(),
-        old_version: props.diff.left.version.version.clone().into(),
-        new_krate: props.diff.right.version.krate.clone(),
-        new_version: props.diff.right.version.version.clone().into(),
-    });
+    let context = Rc::new(Context::new(
+        &props.diff,
+        props.file_route.clone(),
+        props.onselect.clone(),
+    ));
 
     html! {
         <div class="file-tree">
@@ -349,7 +421,7 @@ fn FileSearch(props: &FileSearchProps) -> Html {
             <div class="absolute inset-y-0 start-0 flex items-center ps-3 pointer-events-none">
                 <SearchGlass />
             </div>
-            <input type="search" class="block w-full p-1 ps-10 text-gray-900 border border-gray-300 rounded-lg bg-gray-50 focus:ring-blue-500 focus:border-blue-500 dark:bg-gray-800 dark:border-gray-600 dark:placeholder-gray-400 dark:text-white dark:focus:ring-blue-500 dark:focus:border-blue-500" placeholder="Filter..." value={props.filter.to_string()} {oninput}  />
+            <input type="search" id={FILE_FILTER_ID} class="block w-full p-1 ps-10 text-gray-900 border border-gray-300 rounded-lg bg-gray-50 focus:ring-blue-500 focus:border-blue-500 dark:bg-gray-800 dark:border-gray-600 dark:placeholder-gray-400 dark:text-white dark:focus:ring-blue-500 dark:focus:border-blue-500" placeholder="Filter..." value={props.filter.to_string()} {oninput}  />
         </div>
     }
 }
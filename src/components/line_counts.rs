@@ -0,0 +1,135 @@
+use crate::{cache::CRATE_RESPONSE_CACHE, worker::diff_versions};
+use anyhow::{anyhow, Result};
+use camino::Utf8Path;
+use semver::Version;
+use std::{collections::BTreeMap, rc::Rc};
+use yew::{platform::spawn_local, prelude::*};
+
+/// Explanation shown for line counts which are estimated.
+pub(crate) const ESTIMATE_TITLE: &str =
+    "Estimated, files which are too large to be diffed are counted as changed entirely";
+
+/// Prefix of line counts which are estimated.
+pub(crate) fn estimate_prefix(estimate: bool) -> &'static str {
+    if estimate {
+        "~"
+    } else {
+        ""
+    }
+}
+
+/// Pair of versions of a crate: crate name, old version and new version.
+pub type VersionPair = (String, Version, Version);
+
+/// Lines added and removed between two versions of a crate.
+#[derive(Clone, PartialEq)]
+pub enum LineCount {
+    Counting,
+    /// Lines added and removed, and whether they are estimated because some files are too large
+    /// to be diffed
+    Done(usize, usize, bool),
+    Failed(String),
+}
+
+/// Line counts of pairs of crate versions.
+#[derive(Default, PartialEq)]
+pub struct LineCounts(BTreeMap<VersionPair, LineCount>);
+
+impl Reducible for LineCounts {
+    type Action = (VersionPair, LineCount);
+
+    fn reduce(self: Rc<Self>, (pair, count): Self::Action) -> Rc<Self> {
+        let mut counts = self.0.clone();
+        counts.insert(pair, count);
+        Rc::new(Self(counts))
+    }
+}
+
+impl LineCounts {
+    /// Line count of a pair of versions, if it was requested.
+    pub fn get(&self, pair: &VersionPair) -> Option<&LineCount> {
+        self.0.get(pair)
+    }
+
+    /// Total lines added and removed, and number of pairs counted so far.
+    pub fn total(&self) -> (usize, usize, usize) {
+        self.0
+            .values()
+            .fold((0, 0, 0), |(added, removed, pairs), count| match count {
+                LineCount::Done(more, less, _) => (added + more, removed + less, pairs + 1),
+                _ => (added, removed, pairs),
+            })
+    }
+}
+
+/// Count the lines added and removed between two versions of a crate.
+async fn diff_pair((krate, old, new): &VersionPair) -> Result<LineCount> {
+    let info = CRATE_RESPONSE_CACHE.fetch_cached(krate).await?;
+    let version = |version: &Version| {
+        info.version(version.clone().into())
+            .ok_or_else(|| anyhow!("version {version} of {krate} not found"))
+    };
+    let diff = diff_versions(version(old)?, version(new)?, |_| {}).await?;
+    let root = Utf8Path::new("");
+    let (added, removed) = diff.summary.get(root).copied().unwrap_or_default();
+    Ok(LineCount::Done(added, removed, diff.is_estimate(root)))
+}
+
+/// Count the lines added and removed between a pair of crate versions, by diffing them in the
+/// worker.
+pub async fn count_lines(pair: VersionPair, counts: UseReducerDispatcher<LineCounts>) {
+    counts.dispatch((pair.clone(), LineCount::Counting));
+    let count = diff_pair(&pair)
+        .await
+        .unwrap_or_else(|error| LineCount::Failed(error.to_string()));
+    counts.dispatch((pair, count));
+}
+
+/// Count the lines of several pairs of crate versions which were not counted yet, one at a time
+/// to avoid downloading every version at once.
+pub fn count_all_lines(pairs: Vec<VersionPair>, counts: &UseReducerHandle<LineCounts>) {
+    let missing: Vec<_> = pairs
+        .into_iter()
+        .filter(|pair| counts.get(pair).is_none())
+        .collect();
+    let dispatcher = counts.dispatcher();
+    spawn_local(async move {
+        for pair in missing {
+            count_lines(pair, dispatcher.clone()).await;
+        }
+    });
+}
+
+#[derive(Properties, PartialEq)]
+pub struct LineCountViewProps {
+    pub pair: VersionPair,
+    pub counts: UseReducerHandle<LineCounts>,
+}
+
+/// Show the line count of a pair of versions, or a button to count them.
+#[function_component]
+pub fn LineCountView(props: &LineCountViewProps) -> Html {
+    match props.counts.get(&props.pair) {
+        Some(LineCount::Counting) => html! {
+            <span class="muted">{"counting"}</span>
+        },
+        Some(LineCount::Done(added, removed, estimate)) => html! {
+            <span title={estimate.then_some(ESTIMATE_TITLE)}>
+                <span class="added">{format!("+{}{added}", estimate_prefix(*estimate))}</span>
+                {" "}
+                <span class="removed">{format!("-{}{removed}", estimate_prefix(*estimate))}</span>
+            </span>
+        },
+        Some(LineCount::Failed(error)) => html! {
+            <span class="removed" title={error.clone()}>{"failed"}</span>
+        },
+        None => {
+            let onclick = {
+                let pair = props.pair.clone();
+                let dispatcher = props.counts.dispatcher();
+                move |_| spawn_local(count_lines(pair.clone(), dispatcher.clone()))
+            };
+            html! { <button class="button" {onclick}>{"Count"}</button> }
+        }
+    }
+}
@@ -0,0 +1,17 @@
+use comrak::Options;
+
+/// Render markdown from a crate to HTML which is safe to display.
+///
+/// Crate contents are untrusted, so the rendered HTML is sanitized with `ammonia`, which removes
+/// scripts, event handlers and dangerous links while keeping formatting, such as the raw HTML
+/// which READMEs commonly use for centered logos or badges.
+pub fn render_markdown(markdown: &str) -> String {
+    let mut options = Options::default();
+    options.extension.autolink = true;
+    options.extension.strikethrough = true;
+    options.extension.table = true;
+    options.extension.tasklist = true;
+    options.render.unsafe_ = true;
+    let html = comrak::markdown_to_html(markdown, &options);
+    ammonia::clean(&html)
+}
@@ -1,4 +1,9 @@
-use crate::{components::Search, data::CrateResponse, *};
+use crate::{
+    components::Search,
+    data::CrateResponse,
+    theme::{set_theme_settings, use_theme_settings, ColorScheme, ThemeSettings},
+    *,
+};
 use implicit_clone::unsync::IString;
 use indexmap::IndexMap;
 use semver::Version;
@@ -60,6 +65,30 @@ pub fn NavbarItem(props: &NavbarHeadingProps) -> Html {
     }
 }
 
+/// Button which toggles between following the system color scheme, light and dark.
+#[function_component]
+pub fn ThemeToggle() -> Html {
+    let settings = use_theme_settings();
+    let scheme = settings.scheme;
+    let icon_id = match scheme {
+        ColorScheme::System => IconId::LucideSunMoon,
+        ColorScheme::Light => IconId::LucideSun,
+        ColorScheme::Dark => IconId::LucideMoon,
+    };
+    let onclick = move |_| {
+        set_theme_settings(ThemeSettings {
+            scheme: scheme.next(),
+            ..settings.clone()
+        })
+    };
+    let title = format!("Color scheme: {}", scheme.name());
+    html! {
+        <button class="theme-toggle" title={title.clone()} aria-label={title} {onclick}>
+            <YewIcon height={"1.25em"} {icon_id} />
+        </button>
+    }
+}
+
 #[function_component]
 pub fn SimpleNavbar() -> Html {
     html! {
@@ -76,6 +105,29 @@ pub fn SimpleNavbar() -> Html {
                         {"About"}
                     </Link>
                 </NavbarItem>
+                <NavbarItem>
+                    <Link to={Route::Upload}>
+                        {"Upload"}
+                    </Link>
+                </NavbarItem>
+                <NavbarItem>
+                    <Link to={Route::Patch}>
+                        {"Patch"}
+                    </Link>
+                </NavbarItem>
+                <NavbarItem>
+                    <Link to={Route::Lockfile}>
+                        {"Lockfile"}
+                    </Link>
+                </NavbarItem>
+                <NavbarItem>
+                    <Link to={Route::Settings}>
+                        {"Settings"}
+                    </Link>
+                </NavbarItem>
+                <NavbarItem>
+                    <ThemeToggle />
+                </NavbarItem>
             </NavbarGroup>
         </Navbar>
     }
@@ -268,6 +320,14 @@ pub fn ComplexNavbar(props: &ComplexNavbarProps) -> Html {
                         }
                     />
                 </NavbarItem>
+                <NavbarItem>
+                    <Link to={Route::Timeline { krate: dst_name.clone() }} classes="text-sm">
+                        {"History"}
+                    </Link>
+                </NavbarItem>
+                <NavbarItem>
+                    <ThemeToggle />
+                </NavbarItem>
                 </div>
             </div>
         </Navbar>
@@ -26,6 +26,9 @@ pub fn Error(props: &ErrorProps) -> Html {
 pub struct LoadingProps {
     pub title: String,
     pub status: String,
+    /// Fraction of the work which is done, if known.
+    #[prop_or_default]
+    pub progress: Option<f64>,
 }
 
 #[function_component]
@@ -38,6 +41,11 @@ pub fn Loading(props: &LoadingProps) -> Html {
             <div class="">
                 <h4 class="font-bold p-2">{ &props.title }</h4>
                 <div>{ &props.status }</div>
+                if let Some(progress) = props.progress {
+                    <div class="progress">
+                        <div class="progress-value" style={format!("width: {:.1}%", progress * 100.0)}></div>
+                    </div>
+                }
             </div>
         </div>
     }
@@ -0,0 +1,204 @@
+use wasm_bindgen::{prelude::*, JsCast};
+use web_sys::{Element, HtmlElement, KeyboardEvent};
+use yew::prelude::*;
+
+/// Distance from the top of the viewport in pixels at which hunks are scrolled to.
+const HUNK_MARGIN: f64 = 80.0;
+
+/// Keyboard shortcut of the diff view.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Shortcut {
+    NextHunk,
+    PreviousHunk,
+    NextFile,
+    PreviousFile,
+    FocusFilter,
+    FindFile,
+    SearchCode,
+    Unified,
+    Split,
+    ExpandAll,
+    PreviousVersions,
+    NextVersions,
+    Help,
+    Close,
+}
+
+impl Shortcut {
+    /// All shortcuts, in the order in which they are listed in the help.
+    pub const ALL: [Shortcut; 14] = [
+        Self::NextHunk,
+        Self::PreviousHunk,
+        Self::NextFile,
+        Self::PreviousFile,
+        Self::FocusFilter,
+        Self::FindFile,
+        Self::SearchCode,
+        Self::Unified,
+        Self::Split,
+        Self::ExpandAll,
+        Self::PreviousVersions,
+        Self::NextVersions,
+        Self::Help,
+        Self::Close,
+    ];
+
+    /// Key which triggers the shortcut, as reported by [`KeyboardEvent::key`].
+    pub fn key(&self) -> &'static str {
+        match self {
+            Self::NextHunk => "j",
+            Self::PreviousHunk => "k",
+            Self::NextFile => "n",
+            Self::PreviousFile => "p",
+            Self::FocusFilter => "/",
+            Self::FindFile => "t",
+            Self::SearchCode => "f",
+            Self::Unified => "u",
+            Self::Split => "s",
+            Self::ExpandAll => "e",
+            Self::PreviousVersions => "[",
+            Self::NextVersions => "]",
+            Self::Help => "?",
+            Self::Close => "Escape",
+        }
+    }
+
+    /// Look up the shortcut which is triggered by a key.
+    pub fn from_key(key: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|shortcut| shortcut.key() == key)
+    }
+
+    /// Description of the shortcut, as shown in the help.
+    pub fn description(&self) -> &'static str {
+        match self {
+            Self::NextHunk => "Next hunk",
+            Self::PreviousHunk => "Previous hunk",
+            Self::NextFile => "Next changed file",
+            Self::PreviousFile => "Previous changed file",
+            Self::FocusFilter => "Filter files",
+            Self::FindFile => "Find file",
+            Self::SearchCode => "Search code",
+            Self::Unified => "Unified diff",
+            Self::Split => "Split diff",
+            Self::ExpandAll => "Expand or fold all unchanged lines",
+            Self::PreviousVersions => "Compare the previous versions",
+            Self::NextVersions => "Compare the next versions",
+            Self::Help => "Show keyboard shortcuts",
+            Self::Close => "Close dialog",
+        }
+    }
+}
+
+/// Determines if an element takes text input, in which case keys are not shortcuts.
+fn is_editable(element: &Element) -> bool {
+    matches!(element.tag_name().as_str(), "INPUT" | "TEXTAREA" | "SELECT")
+        || element
+            .dyn_ref::<HtmlElement>()
+            .is_some_and(HtmlElement::is_content_editable)
+}
+
+/// Call the callback whenever the key of a shortcut is pressed, unless text is being entered.
+#[hook]
+pub fn use_shortcuts(callback: Callback<Shortcut>) {
+    // the listener is only registered once, so it calls the latest callback
+    let current = use_mut_ref(|| callback.clone());
+    *current.borrow_mut() = callback;
+    use_effect_with((), move |_| {
+        let listener = Closure::<dyn Fn(KeyboardEvent)>::new(move |event: KeyboardEvent| {
+            if event.ctrl_key() || event.meta_key() || event.alt_key() || event.default_prevented()
+            {
+                return;
+            }
+            let target = event.target().and_then(|target| target.dyn_into().ok());
+            if target.is_some_and(|target: Element| is_editable(&target)) {
+                return;
+            }
+            if let Some(shortcut) = Shortcut::from_key(&event.key()) {
+                event.prevent_default();
+                current.borrow().emit(shortcut);
+            }
+        });
+        let window = web_sys::window();
+        if let Some(window) = &window {
+            let _ = window
+                .add_event_listener_with_callback("keydown", listener.as_ref().unchecked_ref());
+        }
+        move || {
+            if let Some(window) = &window {
+                let _ = window.remove_event_listener_with_callback(
+                    "keydown",
+                    listener.as_ref().unchecked_ref(),
+                );
+            }
+            drop(listener);
+        }
+    });
+}
+
+/// Scroll to the next or previous hunk of the diffs on the page.
+pub fn scroll_to_hunk(forward: bool) {
+    let Some(window) = web_sys::window() else {
+        return;
+    };
+    let Some(hunks) = window.document().and_then(|document| {
+        document
+            .query_selector_all(".diff-view .line.hunk > :first-child")
+            .ok()
+    }) else {
+        return;
+    };
+    let mut tops = (0..hunks.length())
+        .filter_map(|index| hunks.item(index)?.dyn_into::<Element>().ok())
+        .map(|hunk| hunk.get_bounding_client_rect().top());
+    // hunks within a pixel of the margin are the current hunk
+    let top = if forward {
+        tops.find(|top| *top > HUNK_MARGIN + 1.0)
+    } else {
+        tops.rev().find(|top| *top < HUNK_MARGIN - 1.0)
+    };
+    if let Some(top) = top {
+        window.scroll_by_with_x_and_y(0.0, top - HUNK_MARGIN);
+    }
+}
+
+/// Focus the element with the given id.
+pub fn focus_element(id: &str) {
+    let element = web_sys::window()
+        .and_then(|window| window.document())
+        .and_then(|document| document.get_element_by_id(id))
+        .and_then(|element| element.dyn_into::<HtmlElement>().ok());
+    if let Some(element) = element {
+        let _ = element.focus();
+    }
+}
+
+#[derive(Properties, PartialEq)]
+pub struct ShortcutHelpProps {
+    pub onclose: Callback<()>,
+}
+
+/// Overlay which lists the keyboard shortcuts.
+#[function_component]
+pub fn ShortcutHelp(props: &ShortcutHelpProps) -> Html {
+    let onclick = props.onclose.reform(|_| ());
+    html! {
+        <div class="overlay" onclick={onclick.clone()}>
+            <div class="dialog shortcut-help" onclick={|event: MouseEvent| event.stop_propagation()}>
+                <div class="dialog-header">
+                    <span>{"Keyboard shortcuts"}</span>
+                    <button class="button" {onclick}>{"Close"}</button>
+                </div>
+                <table>
+                    {
+                        for Shortcut::ALL.iter().map(|shortcut| html! {
+                            <tr>
+                                <td><kbd>{shortcut.key()}</kbd></td>
+                                <td>{shortcut.description()}</td>
+                            </tr>
+                        })
+                    }
+                </table>
+            </div>
+        </div>
+    }
+}
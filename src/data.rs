@@ -1,24 +1,30 @@
-use crate::version::{VersionId, VersionNamed};
+use crate::version::{is_breaking, VersionDate, VersionId, VersionNamed};
 use anyhow::{anyhow, Result};
 use bytes::Bytes;
 use camino::{Utf8Component, Utf8Path, Utf8PathBuf};
-use flate2::bufread::GzDecoder;
+use flate2::{bufread::GzDecoder, write};
+use futures::StreamExt;
 use gloo_net::http::Request;
+use itertools::Itertools;
+use js_sys::Uint8Array;
 use log::*;
 use semver::Version;
 use serde::{Deserialize, Serialize};
 use sha2::{Digest, Sha256};
-use similar::{ChangeTag, TextDiff};
+use similar::{ChangeTag, DiffTag, TextDiff};
 use std::{
+    cell::RefCell,
     collections::{BTreeMap, BTreeSet},
-    io::Read,
-    ops::Range,
+    io::{Read, Write},
+    ops::{Bound, Range},
     rc::Rc,
-    sync::Arc,
+    sync::{Arc, Mutex},
 };
 use subslice_offset::SubsliceOffset;
 use tar::Archive;
 use url::Url;
+use wasm_bindgen::JsCast;
+use wasm_streams::ReadableStream;
 
 /// Crates.io response type for crate search
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
@@ -102,7 +108,10 @@ pub struct VersionInfo {
     #[serde(rename = "num")]
     pub version: Version,
     //pub id: u64,
-    //pub crate_size: Option<u64>,
+    pub crate_size: Option<u64>,
+    /// When this version was published, as an RFC 3339 timestamp
+    #[serde(default)]
+    pub created_at: Option<String>,
     //pub downloads: u64,
     //pub license: Option<String>,
 }
@@ -121,23 +130,113 @@ impl CrateResponse {
         }
     }
 
+    /// Resolve a version id to one of the versions of this crate.
+    ///
+    /// Exact versions are always resolved, even when yanked. Requirements resolve to the newest
+    /// matching version, preferring versions which are not yanked.
     pub fn version(&self, version: VersionId) -> Option<&VersionInfo> {
+        let releases = self.releases();
+        let latest = releases.first().copied();
         match version {
             VersionId::Exact(version) => self.versions.iter().find(|v| v.version == version),
-            VersionId::Named(VersionNamed::Latest) => self.versions.first(),
-            VersionId::Named(VersionNamed::Previous) => {
-                self.versions.get(1).or(self.versions.first())
-            }
-            VersionId::Requirement(req) => self
+            VersionId::Named(VersionNamed::Latest) => latest,
+            VersionId::Named(VersionNamed::Previous) => releases.get(1).copied().or(latest),
+            VersionId::Named(VersionNamed::LatestStable) => self
+                .versions
+                .iter()
+                .filter(|v| !v.yanked && v.version.pre.is_empty())
+                .max_by_key(|v| &v.version),
+            VersionId::Named(VersionNamed::LatestUnyanked) => self
                 .versions
                 .iter()
-                .filter(|v| req.matches(&v.version))
+                .filter(|v| !v.yanked)
                 .max_by_key(|v| &v.version),
+            VersionId::Named(VersionNamed::PreviousMajor) => {
+                let latest = &latest?.version;
+                releases
+                    .into_iter()
+                    .find(|v| v.version < *latest && is_breaking(&v.version, latest))
+            }
+            VersionId::Named(VersionNamed::PreviousMinor) => {
+                let latest = &latest?.version;
+                releases.into_iter().find(|v| {
+                    v.version < *latest
+                        && (v.version.major, v.version.minor) != (latest.major, latest.minor)
+                })
+            }
+            VersionId::Relative(named, offset) => {
+                let base = self.version(named.into())?;
+                match offset.checked_sub(1) {
+                    None => Some(base),
+                    Some(offset) => releases
+                        .into_iter()
+                        .filter(|v| v.version < base.version)
+                        .nth(offset),
+                }
+            }
+            VersionId::Date(date) => releases.into_iter().find(|v| {
+                v.published()
+                    .and_then(|published| published.parse::<VersionDate>().ok())
+                    .is_some_and(|published| published <= date)
+            }),
+            VersionId::Requirement(req) => {
+                let matching = self.versions.iter().filter(|v| req.matches(&v.version));
+                // prefer versions which are not yanked
+                matching
+                    .clone()
+                    .filter(|v| !v.yanked)
+                    .max_by_key(|v| &v.version)
+                    .or_else(|| matching.max_by_key(|v| &v.version))
+            }
         }
     }
+
+    /// Version which follows the given version if `forward` is set, or which precedes it
+    /// otherwise, skipping yanked versions.
+    pub fn adjacent_version(&self, version: &Version, forward: bool) -> Option<&VersionInfo> {
+        let versions = self.versions.iter().filter(|v| !v.yanked);
+        if forward {
+            versions
+                .filter(|v| v.version > *version)
+                .min_by_key(|v| &v.version)
+        } else {
+            versions
+                .filter(|v| v.version < *version)
+                .max_by_key(|v| &v.version)
+        }
+    }
+
+    /// Versions which named versions are resolved against, ordered from newest to oldest.
+    ///
+    /// These are the versions which are neither yanked nor pre-releases. If there are none, the
+    /// versions which are not yanked are used instead, and if every version is yanked, all of them.
+    fn releases(&self) -> Vec<&VersionInfo> {
+        let filters: [fn(&VersionInfo) -> bool; 3] = [
+            |v| !v.yanked && v.version.pre.is_empty(),
+            |v| !v.yanked,
+            |_| true,
+        ];
+        let mut releases = filters
+            .iter()
+            .map(|filter| {
+                self.versions
+                    .iter()
+                    .filter(|v| filter(v))
+                    .collect::<Vec<_>>()
+            })
+            .find(|releases| !releases.is_empty())
+            .unwrap_or_default();
+        releases.sort_by(|left, right| right.version.cmp(&left.version));
+        releases
+    }
 }
 
 impl VersionInfo {
+    /// Date this version was published, if known.
+    pub fn published(&self) -> Option<&str> {
+        self.created_at.as_deref()?.get(..10)
+    }
+
     /// Get download URL for this crate
     ///
     /// We purposefully construct a URL here and don't use the one returned in the response,
@@ -150,8 +249,25 @@ impl VersionInfo {
         Ok(url)
     }
 
-    /// Fetch a crate source for the given version.
-    pub async fn fetch(&self) -> Result<CrateSource> {
+    /// Fetch the crate archive for the given version.
+    ///
+    /// The archive is hashed and decompressed while it is downloaded, use
+    /// [`ArchiveDecoder::finish`] to verify and parse it. Reports the number of bytes received so
+    /// far and the size of the archive, if it is known.
+    pub async fn fetch_archive(
+        &self,
+        limits: ArchiveLimits,
+        mut progress: impl FnMut(u64, Option<u64>),
+    ) -> Result<ArchiveDecoder> {
+        if let Some(size) = self.crate_size {
+            if size > limits.max_compressed_size {
+                return Err(CrateSourceError::ArchiveTooLarge {
+                    limit: limits.max_compressed_size,
+                }
+                .into());
+            }
+        }
+
         info!(
             "Fetching crate source for {} v{} from network",
             self.krate, self.version
@@ -162,10 +278,27 @@ impl VersionInfo {
             return Err(anyhow!("Error response: {}", response.status()));
         }
 
-        let bytes: Bytes = response.binary().await?.into();
-        let source = CrateSource::new(self.clone(), &bytes[..])?;
+        let total = self.crate_size.or_else(|| {
+            response
+                .headers()
+                .get("content-length")
+                .and_then(|length| length.parse().ok())
+        });
+        let body = response
+            .body()
+            .ok_or_else(|| anyhow!("Missing response body"))?;
+        let mut body = ReadableStream::from_raw(body.unchecked_into()).into_stream();
+        let mut decoder = ArchiveDecoder::with_limits(limits);
+        progress(0, total);
+        while let Some(chunk) = body.next().await {
+            let chunk: Uint8Array = chunk
+                .map_err(|error| anyhow!("Error reading response: {error:?}"))?
+                .unchecked_into();
+            decoder.write(&chunk.to_vec())?;
+            progress(decoder.len(), total);
+        }
 
-        Ok(source)
+        Ok(decoder)
     }
 }
 
@@ -220,6 +353,8 @@ impl RepositoryInfo {
             krate: "".into(),
             yanked: false,
             version: "0.0.0".parse().unwrap(),
+            crate_size: None,
+            created_at: None,
         };
         let url = self
             .url()
@@ -231,10 +366,14 @@ impl RepositoryInfo {
 
         let bytes = response.binary().await?;
         let prefix = self.prefix();
-        Ok(CrateSource {
-            version,
-            files: CrateSource::parse_archive(&prefix, &bytes[..], false)?,
-        })
+        let files = CrateSource::parse_archive(
+            &prefix,
+            GzDecoder::new(&bytes[..]),
+            false,
+            &ArchiveLimits::current(),
+            &mut |_| {},
+        )?;
+        Ok(CrateSource::from_files(version, files))
     }
 }
 
@@ -243,10 +382,11 @@ type FileContents = BTreeMap<Utf8PathBuf, Bytes>;
 /// Crate source
 ///
 /// This is parsed from the gzipped tarball that crates.io serves for every crate.
-#[derive(Clone, Debug, PartialEq, Eq)]
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
 pub struct CrateSource {
     pub version: VersionInfo,
     pub files: FileContents,
+    pub warnings: Vec<CrateSourceWarning>,
 }
 
 #[derive(thiserror::Error, Debug)]
@@ -276,42 +416,160 @@ pub enum CrateSourceError {
     /// interface.
     #[error("encountered invalid prefix in path {path} (expected {prefix})")]
     InvalidPrefix { path: String, prefix: String },
+
+    /// Paths in the archive must be relative and must not contain `.` or `..` components. Such
+    /// paths could refer to files outside of the crate, and the user interface cannot display
+    /// them.
+    #[error("encountered invalid path {path}")]
+    InvalidPath { path: String },
+
+    /// Archives can contain multiple entries with the same path, where extracting the archive
+    /// results in the last one overwriting the others. Depending on which entry is shown, this
+    /// could hide content from the user, so we return an error.
+    #[error("encountered duplicate path {path}")]
+    DuplicatePath { path: String },
+
+    /// Archives can contain a file whose path is also the directory of another file. Extracting
+    /// such an archive fails or replaces one with the other, and the file tree cannot show both,
+    /// so we return an error.
+    #[error("path {path} conflicts with the file {file}")]
+    ConflictingPath { path: String, file: String },
+
+    /// Crates which are not published are identified by the path prefix of their archive, or
+    /// the `Cargo.toml` of their directory. If the crate name and version cannot be determined
+    /// from these, we return an error.
+    #[error("cannot determine crate name and version from {path}")]
+    UnknownCrate { path: String },
+
+    /// The compressed archive is larger than [`ArchiveLimits::max_compressed_size`].
+    #[error("archive exceeds the maximum size of {limit} bytes")]
+    ArchiveTooLarge { limit: u64 },
+
+    /// The contents of the archive are larger than [`ArchiveLimits::max_uncompressed_size`]
+    /// when decompressed. This protects against decompression bombs, which are small archives
+    /// that decompress to a huge amount of data.
+    #[error("archive contents exceed the maximum size of {limit} bytes")]
+    ContentsTooLarge { limit: u64 },
+
+    /// The archive contains more than [`ArchiveLimits::max_files`] files.
+    #[error("archive contains more than {limit} files")]
+    TooManyFiles { limit: usize },
+
+    /// A path in the archive is longer than [`ArchiveLimits::max_path_length`].
+    #[error("path {path} exceeds the maximum length of {limit} bytes")]
+    PathTooLong { path: String, limit: usize },
+}
+
+/// Suspicious, but valid, contents of a crate archive
+///
+/// These do not prevent the crate from being shown, but are pointed out to the user.
+#[derive(thiserror::Error, Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub enum CrateSourceWarning {
+    /// The crate source was uploaded by the user rather than downloaded from crates.io, so its
+    /// contents could not be verified.
+    #[error("was uploaded locally and is not verified against crates.io")]
+    Unverified,
+
+    /// Paths which only differ in case refer to the same file on case-insensitive file systems,
+    /// such as the defaults on Windows and macOS. Only one of the files would be visible there.
+    #[error("contains paths which only differ in case: {}", .paths.iter().join(", "))]
+    CaseCollision { paths: Vec<Utf8PathBuf> },
+}
+
+/// Limits applied when unpacking and diffing crate archives
+///
+/// Crate archives are untrusted input, these limits make sure that unpacking or diffing a
+/// pathological crate cannot exhaust the memory of or hang the browser tab.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ArchiveLimits {
+    /// Maximum size of the compressed archive, in bytes.
+    pub max_compressed_size: u64,
+    /// Maximum total size of the decompressed archive, in bytes.
+    pub max_uncompressed_size: u64,
+    /// Maximum number of files in the archive.
+    pub max_files: usize,
+    /// Maximum length of a path in the archive, in bytes.
+    pub max_path_length: usize,
+    /// Maximum size of a file which is diffed without asking, in bytes.
+    pub max_diff_size: u64,
+}
+
+impl ArchiveLimits {
+    /// Default limits.
+    ///
+    /// The size limits are generous compared to what crates.io accepts by default, 10 MiB
+    /// compressed and 512 MiB uncompressed, because some crates have been granted exceptions.
+    pub const DEFAULT: Self = Self {
+        max_compressed_size: 64 * 1024 * 1024,
+        max_uncompressed_size: 512 * 1024 * 1024,
+        max_files: 65536,
+        max_path_length: 1024,
+        max_diff_size: 2 * 1024 * 1024,
+    };
+
+    /// Limits which are currently used, as chosen on the settings page.
+    pub fn current() -> Self {
+        *CURRENT_ARCHIVE_LIMITS.lock().unwrap()
+    }
+
+    /// Change the limits which are used from now on.
+    pub fn set_current(limits: Self) {
+        *CURRENT_ARCHIVE_LIMITS.lock().unwrap() = limits;
+    }
+
+    /// Determines if a file with the given contents on either side is too large to be diffed.
+    pub fn is_too_large(&self, left: &[u8], right: &[u8]) -> bool {
+        left.len().max(right.len()) as u64 > self.max_diff_size
+    }
+}
+
+/// Limits returned by [`ArchiveLimits::current`].
+static CURRENT_ARCHIVE_LIMITS: Mutex<ArchiveLimits> = Mutex::new(ArchiveLimits::DEFAULT);
+
+impl Default for ArchiveLimits {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
 }
 
 impl CrateSource {
+    /// Contents of the file at the given path, empty if there is no such file.
+    pub fn contents(&self, path: &Utf8Path) -> &[u8] {
+        self.files
+            .get(path)
+            .map(|data| &data[..])
+            .unwrap_or_default()
+    }
+
     /// Create empty crate source for the given version.
     pub fn new(version: VersionInfo, data: &[u8]) -> Result<Self, CrateSourceError> {
-        // compute hash
-        let mut hasher = Sha256::new();
-        hasher.update(data);
-        let hash = hasher.finalize();
-
-        // make sure hash matches
-        if hash[..] != version.checksum[..] {
-            return Err(CrateSourceError::HashsumMismatch {
-                expected: version.checksum.clone(),
-                got: hash[..].to_vec(),
-            });
-        }
-
-        let prefix = format!("{}-{}/", version.krate, version.version);
-        let source = CrateSource {
-            version,
-            files: Self::parse_archive(&prefix, data, true)?,
-        };
+        Self::new_with_progress(version, data, ArchiveLimits::DEFAULT, |_| {})
+    }
 
-        Ok(source)
+    /// Create crate source for the given version with the given limits, reporting the number of
+    /// files unpacked so far.
+    pub fn new_with_progress(
+        version: VersionInfo,
+        data: &[u8],
+        limits: ArchiveLimits,
+        progress: impl FnMut(usize),
+    ) -> Result<Self, CrateSourceError> {
+        let mut decoder = ArchiveDecoder::with_limits(limits);
+        decoder.write(data)?;
+        decoder.finish(version, progress)
     }
 
-    /// Parse gzipped archive.
+    /// Parse decompressed tar archive.
     fn parse_archive(
         prefix: &str,
-        data: &[u8],
+        data: impl Read,
         error_outside_prefix: bool,
+        limits: &ArchiveLimits,
+        progress: &mut dyn FnMut(usize),
     ) -> Result<FileContents, CrateSourceError> {
-        let mut data = GzDecoder::new(data);
-        let mut archive = Archive::new(&mut data);
+        let mut archive = Archive::new(data);
         let mut files = FileContents::default();
+        let mut total_size = 0;
 
         // this is the path prefix we expect in the archive.
         for entry in archive.entries()? {
@@ -336,7 +594,7 @@ impl CrateSource {
                 None => continue,
             };
 
-            let path: Utf8PathBuf = path.into();
+            let path = Self::check_entry(&files, path, entry.size(), &mut total_size, limits)?;
 
             // read data
             let mut data = vec![];
@@ -345,11 +603,190 @@ impl CrateSource {
             debug!("Storing path {path} ({} bytes)", data.len());
             // store data
             files.insert(path, data.into());
+            progress(files.len());
         }
 
         Ok(files)
     }
 
+    /// Check an entry against the limits and the files so far, returning its path.
+    fn check_entry(
+        files: &FileContents,
+        path: &str,
+        size: u64,
+        total_size: &mut u64,
+        limits: &ArchiveLimits,
+    ) -> Result<Utf8PathBuf, CrateSourceError> {
+        let path = Self::check_path(files, path)?;
+        if path.as_str().len() > limits.max_path_length {
+            return Err(CrateSourceError::PathTooLong {
+                path: path.into_string(),
+                limit: limits.max_path_length,
+            });
+        }
+
+        if files.len() >= limits.max_files {
+            return Err(CrateSourceError::TooManyFiles {
+                limit: limits.max_files,
+            });
+        }
+
+        // check size before reading, the header could claim an arbitrary size
+        *total_size += size;
+        if *total_size > limits.max_uncompressed_size {
+            return Err(CrateSourceError::ContentsTooLarge {
+                limit: limits.max_uncompressed_size,
+            });
+        }
+
+        if files.contains_key(&path) {
+            return Err(CrateSourceError::DuplicatePath {
+                path: path.into_string(),
+            });
+        }
+
+        Ok(path)
+    }
+
+    /// Check that a path is plain and relative, and that it does not conflict with the files so
+    /// far, returning it.
+    fn check_path(files: &FileContents, path: &str) -> Result<Utf8PathBuf, CrateSourceError> {
+        // only plain relative paths are allowed, anything else could refer to a location
+        // outside of the crate or be displayed in a confusing way. the raw segments are checked,
+        // because parsing the path into components silently drops `.` and empty segments.
+        let valid = path
+            .split('/')
+            .all(|segment| !matches!(segment, "" | "." | ".."));
+        if !valid {
+            return Err(CrateSourceError::InvalidPath { path: path.into() });
+        }
+
+        // a file must not be a directory of another file. paths are ordered by their components,
+        // so files below this path directly follow it.
+        let path = Utf8Path::new(path);
+        let below = files
+            .range::<Utf8Path, _>((Bound::Excluded(path), Bound::Unbounded))
+            .next()
+            .filter(|(file, _)| file.starts_with(path));
+        let above = path
+            .ancestors()
+            .skip(1)
+            .find(|dir| files.contains_key(*dir));
+        if let Some(file) = below.map(|(file, _)| file.as_path()).or(above) {
+            return Err(CrateSourceError::ConflictingPath {
+                path: path.to_string(),
+                file: file.to_string(),
+            });
+        }
+
+        Ok(path.to_owned())
+    }
+
+    /// Create crate source from the files of a crate directory which is not published.
+    ///
+    /// Paths are relative to the crate root. The crate name and version are read from the
+    /// `Cargo.toml`, and the crate source is marked as unverified. The checksum is computed over
+    /// the paths and contents of the files, it only serves to tell different uploads apart.
+    pub fn from_directory_unverified(
+        entries: Vec<(String, Bytes)>,
+        limits: &ArchiveLimits,
+    ) -> Result<Self, CrateSourceError> {
+        let mut files = FileContents::default();
+        let mut total_size = 0;
+        let mut hasher = Sha256::new();
+        for (path, data) in entries {
+            let path =
+                Self::check_entry(&files, &path, data.len() as u64, &mut total_size, limits)?;
+            files.insert(path, data);
+        }
+        for (path, data) in &files {
+            hasher.update(path.as_str().as_bytes());
+            hasher.update([0]);
+            hasher.update((data.len() as u64).to_le_bytes());
+            hasher.update(data);
+        }
+
+        let unknown = || CrateSourceError::UnknownCrate {
+            path: "Cargo.toml".into(),
+        };
+        let manifest = files.get(Utf8Path::new("Cargo.toml")).ok_or_else(unknown)?;
+        let manifest: toml::Table = std::str::from_utf8(manifest)
+            .ok()
+            .and_then(|manifest| toml::from_str(manifest).ok())
+            .ok_or_else(unknown)?;
+        let package = manifest.get("package").and_then(toml::Value::as_table);
+        let krate = package
+            .and_then(|package| package.get("name")?.as_str())
+            .ok_or_else(unknown)?;
+        // versions inherited from a workspace cannot be resolved
+        let version = package
+            .and_then(|package| package.get("version")?.as_str()?.parse().ok())
+            .unwrap_or(Version::new(0, 0, 0));
+
+        let version = VersionInfo {
+            checksum: hasher.finalize().to_vec(),
+            krate: krate.into(),
+            dl_path: Default::default(),
+            yanked: false,
+            version,
+            crate_size: None,
+            created_at: None,
+        };
+        Ok(Self::from_files(version, files).unverified())
+    }
+
+    /// Create crate source from files which were not taken from a crate, such as the files
+    /// reconstructed from a patch.
+    ///
+    /// The paths are checked like the paths of an archive, and the crate source is marked as
+    /// unverified.
+    pub fn from_contents_unverified(
+        version: VersionInfo,
+        files: BTreeMap<Utf8PathBuf, Bytes>,
+    ) -> Result<Self, CrateSourceError> {
+        let mut checked = FileContents::default();
+        for (path, data) in files {
+            let path = Self::check_path(&checked, path.as_str())?;
+            checked.insert(path, data);
+        }
+        Ok(Self::from_files(version, checked).unverified())
+    }
+
+    /// Mark this crate source as unverified.
+    fn unverified(mut self) -> Self {
+        self.warnings.insert(0, CrateSourceWarning::Unverified);
+        self
+    }
+
+    /// Determines if this crate source was not verified against crates.io.
+    pub fn is_unverified(&self) -> bool {
+        self.warnings.contains(&CrateSourceWarning::Unverified)
+    }
+
+    /// Create crate source from unpacked files, checking them for suspicious paths.
+    fn from_files(version: VersionInfo, files: FileContents) -> Self {
+        let mut warnings = vec![];
+
+        // group paths by their lowercase form to find case collisions
+        let mut paths: BTreeMap<String, Vec<Utf8PathBuf>> = BTreeMap::new();
+        for path in files.keys() {
+            paths
+                .entry(path.as_str().to_lowercase())
+                .or_default()
+                .push(path.clone());
+        }
+        for paths in paths.into_values().filter(|paths| paths.len() > 1) {
+            warn!("Found paths which only differ in case: {paths:?}");
+            warnings.push(CrateSourceWarning::CaseCollision { paths });
+        }
+
+        Self {
+            version,
+            files,
+            warnings,
+        }
+    }
+
     /// Get [`CargoVcsInfo`] from the crate sources.
     pub fn cargo_vcs_info(&self) -> Result<CargoVcsInfo, CargoVcsInfoError> {
         let raw = self
@@ -361,6 +798,156 @@ impl CrateSource {
     }
 }
 
+/// Incremental decoder for crate archives
+///
+/// Crate archives are hashed and decompressed chunk by chunk as they arrive, so that little work
+/// is left to do once the download is finished. The archive is only unpacked after its hash is
+/// verified.
+pub struct ArchiveDecoder {
+    hasher: Sha256,
+    decoder: write::GzDecoder<Vec<u8>>,
+    data: Vec<u8>,
+    limits: ArchiveLimits,
+}
+
+impl Default for ArchiveDecoder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ArchiveDecoder {
+    /// How much compressed data to decompress at once before checking the decompressed size.
+    ///
+    /// Deflate compresses by at most a factor of about 1000, so this bounds how far the
+    /// decompressed data can overshoot the limit.
+    const DECOMPRESS_STEP: usize = 16 * 1024;
+
+    /// Create new decoder with the default limits
+    pub fn new() -> Self {
+        Self::with_limits(ArchiveLimits::DEFAULT)
+    }
+
+    /// Create new decoder with the given limits
+    pub fn with_limits(limits: ArchiveLimits) -> Self {
+        Self {
+            hasher: Sha256::new(),
+            decoder: write::GzDecoder::new(Vec::new()),
+            data: Vec::new(),
+            limits,
+        }
+    }
+
+    /// Hash and decompress the next chunk of the archive.
+    pub fn write(&mut self, chunk: &[u8]) -> Result<(), CrateSourceError> {
+        if self.len() + chunk.len() as u64 > self.limits.max_compressed_size {
+            return Err(CrateSourceError::ArchiveTooLarge {
+                limit: self.limits.max_compressed_size,
+            });
+        }
+
+        self.hasher.update(chunk);
+        self.data.extend_from_slice(chunk);
+        for step in chunk.chunks(Self::DECOMPRESS_STEP) {
+            self.decoder.write_all(step)?;
+            if self.decoder.get_ref().len() as u64 > self.limits.max_uncompressed_size {
+                return Err(CrateSourceError::ContentsTooLarge {
+                    limit: self.limits.max_uncompressed_size,
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// Number of bytes of the archive received so far.
+    pub fn len(&self) -> u64 {
+        self.data.len() as u64
+    }
+
+    /// Determines if no bytes of the archive have been received so far.
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    /// Raw archive, as received so far.
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    /// Verify the archive against the checksum of the given version and unpack it, reporting the
+    /// number of files unpacked so far.
+    pub fn finish(
+        self,
+        version: VersionInfo,
+        mut progress: impl FnMut(usize),
+    ) -> Result<CrateSource, CrateSourceError> {
+        // make sure hash matches
+        let hash = self.hasher.finalize();
+        if hash[..] != version.checksum[..] {
+            return Err(CrateSourceError::HashsumMismatch {
+                expected: version.checksum.clone(),
+                got: hash[..].to_vec(),
+            });
+        }
+
+        let limits = self.limits;
+        let data = self.decoder.finish()?;
+        let prefix = format!("{}-{}/", version.krate, version.version);
+        let files = CrateSource::parse_archive(&prefix, &data[..], true, &limits, &mut progress)?;
+        Ok(CrateSource::from_files(version, files))
+    }
+
+    /// Unpack the archive without verifying it, for crate archives uploaded by the user.
+    ///
+    /// The crate name and version are taken from the directory which the archive contains, and the
+    /// hash of the archive is used as its checksum. The crate source is marked as unverified.
+    pub fn finish_unverified(self) -> Result<CrateSource, CrateSourceError> {
+        let checksum = self.hasher.finalize().to_vec();
+        let limits = self.limits;
+        let data = self.decoder.finish()?;
+        let (krate, version) = Self::archive_name(&data)?;
+        let prefix = format!("{krate}-{version}/");
+        let files = CrateSource::parse_archive(&prefix, &data[..], true, &limits, &mut |_| {})?;
+        let version = VersionInfo {
+            checksum,
+            krate,
+            dl_path: Default::default(),
+            yanked: false,
+            version,
+            crate_size: Some(self.data.len() as u64),
+            created_at: None,
+        };
+        Ok(CrateSource::from_files(version, files).unverified())
+    }
+
+    /// Determine crate name and version from the directory of the first entry of an archive.
+    ///
+    /// Crate names may contain dashes, so the directory is split at the first dash which is
+    /// followed by a valid version.
+    fn archive_name(data: &[u8]) -> Result<(String, Version), CrateSourceError> {
+        let mut archive = Archive::new(data);
+        let entry = match archive.entries()?.next() {
+            Some(entry) => entry?,
+            None => {
+                return Err(CrateSourceError::UnknownCrate {
+                    path: Default::default(),
+                })
+            }
+        };
+        let bytes = entry.path_bytes();
+        let path = std::str::from_utf8(&bytes)?;
+        let directory = path.split('/').next().unwrap_or_default();
+        directory
+            .match_indices('-')
+            .find_map(|(index, _)| {
+                let version = directory[index + 1..].parse().ok()?;
+                Some((directory[..index].to_string(), version))
+            })
+            .filter(|(krate, _)| !krate.is_empty())
+            .ok_or_else(|| CrateSourceError::UnknownCrate { path: path.into() })
+    }
+}
+
 #[derive(thiserror::Error, Debug)]
 pub enum CargoVcsInfoError {
     #[error("missing .cargo_vcs_info.json")]
@@ -390,37 +977,201 @@ pub struct FileDiff {
     pub summary: Changes,
 }
 
-/// Precomputed diff data
+/// How many lines of context to show in a diff
+const CONTEXT_LINES: usize = 3;
+
+/// Count the lines in some data, in the same way that the line diff does.
+fn line_count(data: &[u8]) -> usize {
+    let newlines = data.iter().filter(|byte| **byte == b'\n').count();
+    match data.last() {
+        Some(b'\n') | None => newlines,
+        Some(_) => newlines + 1,
+    }
+}
+
+impl FileDiff {
+    /// Count lines added and removed between two versions of a file.
+    ///
+    /// Identical or one-sided files are not diffed at all. Files which differ in both versions
+    /// still run the full diff algorithm, only the changed lines and hunks are not collected,
+    /// which [`FileDiff::new`] does once the file is viewed.
+    pub fn changes(left: &[u8], right: &[u8]) -> Changes {
+        if left == right {
+            return Changes::default();
+        }
+
+        if left.is_empty() || right.is_empty() {
+            return Changes {
+                added: line_count(right) as u64,
+                removed: line_count(left) as u64,
+                ..Default::default()
+            };
+        }
+
+        let diff = TextDiff::from_lines(left, right);
+        diff.ops()
+            .iter()
+            .map(|op| match op.tag() {
+                DiffTag::Equal => Changes::default(),
+                DiffTag::Delete => Changes {
+                    removed: op.old_range().len() as u64,
+                    ..Default::default()
+                },
+                DiffTag::Insert => Changes {
+                    added: op.new_range().len() as u64,
+                    ..Default::default()
+                },
+                DiffTag::Replace => Changes {
+                    added: op.new_range().len() as u64,
+                    removed: op.old_range().len() as u64,
+                    ..Default::default()
+                },
+            })
+            .fold(Changes::default(), |sum, changes| sum + changes)
+    }
+
+    /// Compute the full diff between two versions of a file.
+    pub fn new(left: &Bytes, right: &Bytes) -> Self {
+        // generate text diff
+        let diff = TextDiff::from_lines(&left[..], &right[..]);
+
+        // collect changes
+        let changes: Vec<_> = diff
+            .iter_all_changes()
+            .map(|change| {
+                // soo... we do an awkward little dance here. out data is a Bytes struct, which we
+                // can cheaply get subslices from. the diff algorithm gets a &[u8] and every
+                // change gives us a &[u8]. now, we want to figure out what the offset of this
+                // &[u8] was from the original bytes, so that we can call .slice() on it to get a
+                // cheap reference-counted bytes rather than having to clone it. so we use the
+                // subslice_offset crate which lets us do exactly that.
+                let value = change.value();
+                let value = [left, right]
+                    .iter()
+                    .find_map(|b| {
+                        b[..]
+                            .subslice_offset(value)
+                            .map(|index| b.slice(index..index + value.len()))
+                    })
+                    .unwrap();
+                (change.tag(), value)
+            })
+            .collect();
+
+        let mut offsets = vec![];
+        let mut insertions = 0;
+        let mut deletions = 0;
+
+        for (index, (tag, _)) in changes.iter().enumerate() {
+            match tag {
+                ChangeTag::Equal => {}
+                ChangeTag::Delete => {
+                    // cnt for determining start idx of hunk, wanna start before this line, so do not count current line
+                    offsets.push((index, insertions, deletions));
+                    deletions += 1;
+                }
+                ChangeTag::Insert => {
+                    offsets.push((index, insertions, deletions));
+                    insertions += 1;
+                }
+            }
+        }
+
+        // compute ranges to show
+        let mut ranges = vec![];
+        let mut last_hunk = (0..0, 0, 0);
+
+        for (offset, ins, del) in offsets.iter() {
+            let hunk_start = offset.saturating_sub(CONTEXT_LINES);
+            let left_start = hunk_start.saturating_sub(*ins);
+            let right_start = hunk_start.saturating_sub(*del);
+
+            let hunk = (
+                hunk_start..*offset + CONTEXT_LINES + 1,
+                left_start,
+                right_start,
+            );
+            let overlaps_with_last_hunk = hunk.0.start.max(last_hunk.0.start)
+                <= hunk.0.end.min(last_hunk.0.end) + CONTEXT_LINES;
+            if overlaps_with_last_hunk {
+                last_hunk = (last_hunk.0.start..hunk.0.end, last_hunk.1, last_hunk.2);
+            } else {
+                if last_hunk.0.end != 0 {
+                    ranges.push(last_hunk.clone().into());
+                }
+                last_hunk = hunk;
+            }
+        }
+
+        // Push the last hunk we've computed if any
+        if last_hunk.0.end != 0 {
+            ranges.push(last_hunk.into())
+        }
+
+        FileDiff {
+            changes,
+            context_ranges: ranges,
+            summary: Changes {
+                added: insertions as u64,
+                removed: deletions as u64,
+                ..Default::default()
+            },
+        }
+    }
+}
+
+/// Diff data between two crate sources
+///
+/// Only the summary of changes (used to render the file tree) is computed up front. The full diff
+/// of a file is computed when it is first requested using [`VersionDiff::file`], and kept for
+/// subsequent requests.
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub struct VersionDiff {
     /// Left crate source that is diffed
     pub left: Arc<CrateSource>,
     /// Right crate source that is diffed
     pub right: Arc<CrateSource>,
-    /// Files in this version diff
-    pub files: BTreeMap<Utf8PathBuf, FileDiff>,
     /// Summaries of files and folders
     pub summary: BTreeMap<Utf8PathBuf, (usize, usize)>,
+    /// Files and folders whose summary is estimated, see [`VersionDiff::is_estimate`]
+    estimates: BTreeSet<Utf8PathBuf>,
 
     pub tree: Entry,
-}
 
-/// How many lines of context to show in a diff
-const CONTEXT_LINES: usize = 3;
+    /// Limits which the diff was computed with
+    pub limits: ArchiveLimits,
+
+    /// Files diffs which have been computed so far
+    files: RefCell<BTreeMap<Utf8PathBuf, Rc<FileDiff>>>,
+}
 
 impl VersionDiff {
-    /// Generate diff data
+    /// Generate diff summary
     pub fn new(left: Arc<CrateSource>, right: Arc<CrateSource>) -> Self {
+        let limits = ArchiveLimits::DEFAULT;
+        let changes = Self::changes(&left, &right, &limits, |_, _| {});
+        Self::from_changes(left, right, changes, limits)
+    }
+
+    /// Count lines added and removed for every file in either crate source.
+    ///
+    /// Every file which differs in both crate sources is diffed to count its lines exactly, see
+    /// [`FileDiff::changes`], so this costs about as much as diffing all changed files. Files
+    /// larger than [`ArchiveLimits::max_diff_size`] are not diffed, every line in them is counted
+    /// as changed instead, see [`VersionDiff::is_estimate`]. Reports the number of files
+    /// processed so far and the total number of files.
+    pub fn changes(
+        left: &CrateSource,
+        right: &CrateSource,
+        limits: &ArchiveLimits,
+        mut progress: impl FnMut(usize, usize),
+    ) -> BTreeMap<Utf8PathBuf, Changes> {
         info!(
             "Computing diff for {} version {} and {} version {}",
             left.version.krate, left.version.version, right.version.krate, right.version.version
         );
 
-        let mut entry = Entry::default();
-        let mut files = BTreeMap::new();
-        let mut summary: BTreeMap<Utf8PathBuf, (usize, usize)> = BTreeMap::new();
-
-        // intersection of file paths in both left and right crate sources
+        // union of file paths in both left and right crate sources
         let file_paths: BTreeSet<&Utf8Path> = left
             .files
             .keys()
@@ -428,125 +1179,148 @@ impl VersionDiff {
             .map(|s| s.as_path())
             .collect();
 
-        // compute diffs
-        for path in file_paths.into_iter() {
-            info!("Computing diff for {path}");
-
-            // lookup files, default to empty bytes
-            let left = left.files.get(path).cloned().unwrap_or_default();
-            let right = right.files.get(path).cloned().unwrap_or_default();
-
-            // generate text diff
-            let diff = TextDiff::from_lines(&left[..], &right[..]);
-
-            // collect changes
-            let changes: Vec<_> = diff
-                .iter_all_changes()
-                .map(|change| {
-                    // soo... we do an awkward little dance here. out data is a Bytes struct, which we
-                    // can cheaply get subslices from. the diff algorithm gets a &[u8] and every
-                    // change gives us a &[u8]. now, we want to figure out what the offset of this
-                    // &[u8] was from the original bytes, so that we can call .slice() on it to get a
-                    // cheap reference-counted bytes rather than having to clone it. so we use the
-                    // subslice_offset crate which lets us do exactly that.
-                    let value = change.value();
-                    let value = [&left, &right]
-                        .iter()
-                        .find_map(|b| {
-                            b[..]
-                                .subslice_offset(value)
-                                .map(|index| b.slice(index..index + value.len()))
-                        })
-                        .unwrap();
-                    (change.tag(), value)
-                })
-                .collect();
-
-            let mut offsets = vec![];
-            let mut insertions = 0;
-            let mut deletions = 0;
-
-            for (index, (tag, _)) in changes.iter().enumerate() {
-                match tag {
-                    ChangeTag::Equal => {}
-                    ChangeTag::Delete => {
-                        // cnt for determining start idx of hunk, wanna start before this line, so do not count current line
-                        offsets.push((index, insertions, deletions));
-                        deletions += 1;
-                    }
-                    ChangeTag::Insert => {
-                        offsets.push((index, insertions, deletions));
-                        insertions += 1;
-                    }
-                }
-            }
+        let total = file_paths.len();
+        let mut changes = BTreeMap::new();
+        for (index, path) in file_paths.into_iter().enumerate() {
+            debug!("Computing changes for {path}");
 
-            // compute ranges to show
-            let mut ranges = vec![];
-            let mut last_hunk = (0..0, 0, 0);
-
-            for (offset, ins, del) in offsets.iter() {
-                let hunk_start = offset.saturating_sub(CONTEXT_LINES);
-                let left_start = hunk_start.saturating_sub(*ins);
-                let right_start = hunk_start.saturating_sub(*del);
-
-                let hunk = (
-                    hunk_start..*offset + CONTEXT_LINES + 1,
-                    left_start,
-                    right_start,
-                );
-                let overlaps_with_last_hunk = hunk.0.start.max(last_hunk.0.start)
-                    <= hunk.0.end.min(last_hunk.0.end) + CONTEXT_LINES;
-                if overlaps_with_last_hunk {
-                    last_hunk = (last_hunk.0.start..hunk.0.end, last_hunk.1, last_hunk.2);
-                } else {
-                    if last_hunk.0.end != 0 {
-                        ranges.push(last_hunk.clone().into());
-                    }
-                    last_hunk = hunk;
+            let (left, right) = (left.contents(path), right.contents(path));
+            let file_changes = if limits.is_too_large(left, right) && left != right {
+                Changes {
+                    added: line_count(right) as u64,
+                    removed: line_count(left) as u64,
+                    estimate: true,
                 }
-            }
+            } else {
+                FileDiff::changes(left, right)
+            };
+            changes.insert(path.to_owned(), file_changes);
+            progress(index + 1, total);
+        }
 
-            // Push the last hunk we've computed if any
-            if last_hunk.0.end != 0 {
-                ranges.push(last_hunk.into())
-            }
+        changes
+    }
+
+    /// Generate diff summary from previously computed changes.
+    pub fn from_changes(
+        left: Arc<CrateSource>,
+        right: Arc<CrateSource>,
+        changes: BTreeMap<Utf8PathBuf, Changes>,
+        limits: ArchiveLimits,
+    ) -> Self {
+        let mut entry = Entry::default();
+        let mut summary: BTreeMap<Utf8PathBuf, (usize, usize)> = BTreeMap::new();
+        let mut estimates = BTreeSet::new();
 
+        for (path, changes) in changes {
             // compute additions
             for path in path.ancestors() {
                 let summary = summary.entry(path.into()).or_default();
-                summary.0 += insertions;
-                summary.1 += deletions;
+                summary.0 += changes.added as usize;
+                summary.1 += changes.removed as usize;
+                if changes.estimate {
+                    estimates.insert(path.to_owned());
+                }
             }
 
-            entry.insert(
-                path,
-                Changes {
-                    added: insertions as u64,
-                    removed: deletions as u64,
-                },
-            );
-
-            files.insert(
-                path.into(),
-                FileDiff {
-                    changes,
-                    context_ranges: ranges,
-                    summary: Changes {
-                        added: insertions as u64,
-                        removed: deletions as u64,
-                    },
-                },
-            );
+            entry.insert(&path, changes);
         }
 
         VersionDiff {
             left,
             right,
-            files,
             summary,
+            estimates,
             tree: entry,
+            limits,
+            files: Default::default(),
+        }
+    }
+
+    /// Paths of the files in the file tree, along with their changes, in the order of the tree.
+    ///
+    /// If `changed_only` is set, files which are identical in both crate sources are skipped.
+    pub fn tree_files(&self, changed_only: bool) -> Vec<(Utf8PathBuf, Changes)> {
+        fn collect(
+            entry: &Entry,
+            path: Utf8PathBuf,
+            changed_only: bool,
+            files: &mut Vec<(Utf8PathBuf, Changes)>,
+        ) {
+            match &entry.item {
+                Item::File if changed_only && entry.changes == Changes::default() => {}
+                Item::File => files.push((path, entry.changes)),
+                Item::Dir(entries) => {
+                    for entry in entries.values() {
+                        collect(entry, path.join(&entry.name), changed_only, files);
+                    }
+                }
+            }
+        }
+        let mut files = vec![];
+        collect(&self.tree, Utf8PathBuf::new(), changed_only, &mut files);
+        files
+    }
+
+    /// Changed file which follows the given path in the order of the file tree if `forward` is
+    /// set, or which precedes it otherwise.
+    ///
+    /// If the path is not a file, such as a directory or an empty path, this is the first or last
+    /// changed file.
+    pub fn adjacent_file(&self, path: &Utf8Path, forward: bool) -> Option<Utf8PathBuf> {
+        let mut files = self.tree_files(false);
+        if !forward {
+            files.reverse();
+        }
+        let start = files
+            .iter()
+            .position(|(file, _)| file == path)
+            .map_or(0, |index| index + 1);
+        files
+            .into_iter()
+            .skip(start)
+            .find(|(_, changes)| *changes != Changes::default())
+            .map(|(file, _)| file)
+    }
+
+    /// Determines if the file at the given path is too large to be diffed without asking.
+    ///
+    /// The diff of such a file can still be computed using [`VersionDiff::file`], but this may
+    /// take a long time.
+    pub fn is_too_large(&self, path: &Utf8Path) -> bool {
+        self.limits
+            .is_too_large(self.left.contents(path), self.right.contents(path))
+    }
+
+    /// Determines if the summary of the file or folder at the given path is an estimate.
+    ///
+    /// Changed files which are too large are not diffed in advance, every line of them is counted
+    /// as changed instead. This also applies to the folders containing them.
+    pub fn is_estimate(&self, path: &Utf8Path) -> bool {
+        self.estimates.contains(path)
+    }
+
+    /// Get the diff for the file at the given path.
+    ///
+    /// Returns `None` if the file exists in neither crate source.
+    pub fn file(&self, path: &Utf8Path) -> Option<Rc<FileDiff>> {
+        if let Some(diff) = self.files.borrow().get(path) {
+            return Some(diff.clone());
         }
+
+        let left = self.left.files.get(path);
+        let right = self.right.files.get(path);
+        if left.is_none() && right.is_none() {
+            return None;
+        }
+
+        info!("Computing diff for {path}");
+        let diff = Rc::new(FileDiff::new(
+            &left.cloned().unwrap_or_default(),
+            &right.cloned().unwrap_or_default(),
+        ));
+        self.files.borrow_mut().insert(path.into(), diff.clone());
+        Some(diff)
     }
 }
 
@@ -576,10 +1350,12 @@ impl ChunkInfo {
     }
 }
 
-#[derive(Default, Clone, Copy, PartialEq, Eq, Debug)]
+#[derive(Serialize, Deserialize, Default, Clone, Copy, PartialEq, Eq, Debug)]
 pub struct Changes {
     pub added: u64,
     pub removed: u64,
+    /// Whether every line is counted as changed, because the file is too large to be diffed
+    pub estimate: bool,
 }
 
 impl std::ops::Add for Changes {
@@ -587,6 +1363,7 @@ impl std::ops::Add for Changes {
     fn add(mut self, rhs: Self) -> Self {
         self.added += rhs.added;
         self.removed += rhs.removed;
+        self.estimate |= rhs.estimate;
         self
     }
 }
@@ -639,11 +1416,17 @@ impl Entry {
         }
     }
 
+    /// Insert a file with the given changes, creating its directories.
+    ///
+    /// A file can be replaced by a directory of the same name in another version, or the other
+    /// way around. The directory is then kept next to the file, under its name followed by a
+    /// slash.
     pub fn insert(&mut self, path: &Utf8Path, changes: Changes) {
         debug!("Inserting {path} with changes {changes:?}");
         let mut entry = self;
 
-        for component in path.components() {
+        let mut components = path.components().peekable();
+        while let Some(component) = components.next() {
             entry.changes += changes;
 
             let component = match component {
@@ -654,13 +1437,25 @@ impl Entry {
                 Utf8Component::Prefix(_) => unreachable!(),
             };
 
-            entry = Rc::make_mut(
-                match &mut entry.item {
-                    Item::File => unreachable!(),
-                    Item::Dir(entries) => entries,
+            let entries = match &mut entry.item {
+                Item::File => unreachable!(),
+                Item::Dir(entries) => entries,
+            };
+            let conflict = format!("{component}/");
+            let is_file = components.peek().is_none();
+            let key = match entries.get(component) {
+                Some(other) if other.item.is_dir() && is_file => {
+                    let other = entries.remove(component).unwrap();
+                    entries.insert(conflict, other);
+                    component.to_string()
                 }
-                .entry(component.to_string())
-                .or_insert_with(|| Rc::new(Entry::new(component.to_string()))),
+                Some(other) if !other.item.is_dir() && !is_file => conflict,
+                _ => component.to_string(),
+            };
+            entry = Rc::make_mut(
+                entries
+                    .entry(key)
+                    .or_insert_with(|| Rc::new(Entry::new(component.to_string()))),
             );
         }
 
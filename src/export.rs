@@ -0,0 +1,489 @@
+//! Export of diffs, as a unified patch, a static HTML report or a JSON document.
+//!
+//! Exports cover the changed files of a [`VersionDiff`] below some path, which is either the
+//! whole crate or a single file. Hunks use the same context as the diff view.
+
+use crate::{
+    data::{FileDiff, VersionDiff},
+    syntax::{
+        escape_html as escape, highlight_changes, infer_syntax_for_changes, theme_css,
+        DEFAULT_LIGHT_THEME,
+    },
+};
+use bytes::Bytes;
+use camino::{Utf8Path, Utf8PathBuf};
+use serde::Serialize;
+use similar::ChangeTag;
+use std::{fmt::Write, ops::Range};
+
+/// Format in which a diff can be exported.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ExportFormat {
+    /// Unified patch, as generated by `git diff`
+    Patch,
+    /// Self-contained HTML report with syntax highlighting
+    Html,
+    /// JSON document with statistics and hunks of every file
+    Json,
+}
+
+impl ExportFormat {
+    /// All export formats, in the order in which they are offered.
+    pub const ALL: [ExportFormat; 3] = [Self::Patch, Self::Html, Self::Json];
+
+    /// Name of the format, as shown to the user.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Patch => "Patch",
+            Self::Html => "HTML",
+            Self::Json => "JSON",
+        }
+    }
+
+    /// Extension of files in this format.
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Self::Patch => "patch",
+            Self::Html => "html",
+            Self::Json => "json",
+        }
+    }
+
+    /// MIME type of files in this format.
+    pub fn mime_type(&self) -> &'static str {
+        match self {
+            Self::Patch => "text/x-diff",
+            Self::Html => "text/html",
+            Self::Json => "application/json",
+        }
+    }
+
+    /// Export the changed files below `path` in this format.
+    pub fn export(&self, diff: &VersionDiff, path: &Utf8Path) -> String {
+        let files = changed_files(diff, path);
+        match self {
+            Self::Patch => patch(diff, &files),
+            Self::Html => html(diff, &files),
+            Self::Json => json(diff, &files),
+        }
+    }
+}
+
+/// Name of the file which an export of the changed files below `path` is saved as.
+pub fn file_name(diff: &VersionDiff, path: &Utf8Path, format: ExportFormat) -> String {
+    let (left, right) = (&diff.left.version, &diff.right.version);
+    let mut name = format!(
+        "{}-{}...{}-{}",
+        left.krate, left.version, right.krate, right.version
+    );
+    if let Some(file_name) = path.file_name() {
+        name.push('-');
+        name.push_str(file_name);
+    }
+    name.push('.');
+    name.push_str(format.extension());
+    name
+}
+
+/// Paths of the changed files below `path`, in the order of the file tree.
+pub fn changed_files(diff: &VersionDiff, path: &Utf8Path) -> Vec<Utf8PathBuf> {
+    diff.tree_files(true)
+        .into_iter()
+        .map(|(file, _)| file)
+        .filter(|file| file.starts_with(path))
+        .collect()
+}
+
+/// Status of a file in a diff.
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum FileStatus {
+    /// File only exists in the new version
+    Added,
+    /// File only exists in the old version
+    Removed,
+    /// File exists in both versions, with different contents
+    Modified,
+    /// File exists in both versions, with the same contents
+    Unchanged,
+}
+
+impl FileStatus {
+    /// Determine the status of the file at the given path.
+    pub fn of(diff: &VersionDiff, path: &Utf8Path) -> Self {
+        match (diff.left.files.get(path), diff.right.files.get(path)) {
+            (None, _) => Self::Added,
+            (_, None) => Self::Removed,
+            (Some(left), Some(right)) if left == right => Self::Unchanged,
+            _ => Self::Modified,
+        }
+    }
+}
+
+/// Determines if a file is binary, in which case its changes are not exported line by line.
+///
+/// Like `git`, files containing a null byte are considered binary, as well as files which are not
+/// valid UTF-8.
+pub(crate) fn is_binary(data: Option<&Bytes>) -> bool {
+    data.is_some_and(|data| data.contains(&0) || std::str::from_utf8(data).is_err())
+}
+
+/// Range of lines of a file diff which is shown, along with the line numbers it covers.
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct HunkRange {
+    /// Range of the changes of the file diff
+    range: Range<usize>,
+    old_start: usize,
+    old_lines: usize,
+    new_start: usize,
+    new_lines: usize,
+}
+
+impl HunkRange {
+    /// Header of the hunk in a unified diff.
+    ///
+    /// Like `git`, the number of lines is omitted if it is one.
+    fn header(&self) -> String {
+        let range = |start: usize, lines: usize| match lines {
+            1 => start.to_string(),
+            lines => format!("{start},{lines}"),
+        };
+        format!(
+            "@@ -{} +{} @@",
+            range(self.old_start, self.old_lines),
+            range(self.new_start, self.new_lines)
+        )
+    }
+}
+
+/// Compute the hunks of a file diff, from the ranges which the diff view shows.
+fn hunk_ranges(file_diff: &FileDiff) -> Vec<HunkRange> {
+    file_diff
+        .context_ranges
+        .iter()
+        .map(|chunk| {
+            let range = chunk.start()..chunk.end().min(file_diff.changes.len());
+            let changes = &file_diff.changes[range.clone()];
+            let old_lines = changes
+                .iter()
+                .filter(|(tag, _)| *tag != ChangeTag::Insert)
+                .count();
+            let new_lines = changes
+                .iter()
+                .filter(|(tag, _)| *tag != ChangeTag::Delete)
+                .count();
+            // empty ranges refer to the line before them
+            HunkRange {
+                range,
+                old_start: chunk.left_start + usize::from(old_lines > 0),
+                old_lines,
+                new_start: chunk.right_start + usize::from(new_lines > 0),
+                new_lines,
+            }
+        })
+        .collect()
+}
+
+/// Prefix of a line of the given kind in a unified diff.
+fn line_prefix(tag: ChangeTag) -> char {
+    match tag {
+        ChangeTag::Equal => ' ',
+        ChangeTag::Delete => '-',
+        ChangeTag::Insert => '+',
+    }
+}
+
+/// Line of a change without its line ending.
+fn line_text(line: &Bytes) -> std::borrow::Cow<'_, str> {
+    let line = line.strip_suffix(b"\n").unwrap_or(line);
+    let line = line.strip_suffix(b"\r").unwrap_or(line);
+    String::from_utf8_lossy(line)
+}
+
+/// Generate a unified patch of the given files, in the format of `git diff`.
+///
+/// Paths are relative to the crate root, so the patch can be applied to the old version of the
+/// crate using `git apply`.
+pub fn patch(diff: &VersionDiff, files: &[Utf8PathBuf]) -> String {
+    let mut output = String::new();
+    for path in files {
+        file_patch(diff, path, &mut output);
+    }
+    output
+}
+
+/// Append the unified patch of a single file to the output.
+///
+/// Files which are too large to be diffed are left out, with a line noting that in place of
+/// their diff, which `git apply` ignores like the text before the first diff.
+fn file_patch(diff: &VersionDiff, path: &Utf8Path, output: &mut String) {
+    let status = FileStatus::of(diff, path);
+    if status == FileStatus::Unchanged {
+        return;
+    }
+    if diff.is_too_large(path) {
+        let _ = writeln!(
+            output,
+            "File {path} is too large to diff, its changes are left out."
+        );
+        return;
+    }
+    let old = match status {
+        FileStatus::Added => "/dev/null".to_string(),
+        _ => format!("a/{path}"),
+    };
+    let new = match status {
+        FileStatus::Removed => "/dev/null".to_string(),
+        _ => format!("b/{path}"),
+    };
+
+    let _ = writeln!(output, "diff --git a/{path} b/{path}");
+    match status {
+        FileStatus::Added => output.push_str("new file mode 100644\n"),
+        FileStatus::Removed => output.push_str("deleted file mode 100644\n"),
+        _ => {}
+    }
+    if is_binary(diff.left.files.get(path)) || is_binary(diff.right.files.get(path)) {
+        let _ = writeln!(output, "Binary files {old} and {new} differ");
+        return;
+    }
+    let _ = writeln!(output, "--- {old}");
+    let _ = writeln!(output, "+++ {new}");
+
+    let Some(file_diff) = diff.file(path) else {
+        return;
+    };
+    for hunk in hunk_ranges(&file_diff) {
+        let _ = writeln!(output, "{}", hunk.header());
+        for (tag, line) in &file_diff.changes[hunk.range] {
+            output.push(line_prefix(*tag));
+            output.push_str(&String::from_utf8_lossy(line));
+            if !line.ends_with(b"\n") {
+                output.push_str("\n\\ No newline at end of file\n");
+            }
+        }
+    }
+}
+
+/// Crate version which a report refers to.
+#[derive(Serialize, Clone, Debug, PartialEq, Eq)]
+pub struct ReportVersion {
+    #[serde(rename = "crate")]
+    pub krate: String,
+    pub version: String,
+}
+
+/// Hunk of a file in a report.
+#[derive(Serialize, Clone, Debug, PartialEq, Eq)]
+pub struct ReportHunk {
+    pub old_start: usize,
+    pub old_lines: usize,
+    pub new_start: usize,
+    pub new_lines: usize,
+    /// Lines of the hunk, prefixed with `+`, `-` or a space like in a unified diff, without
+    /// line endings
+    pub lines: Vec<String>,
+}
+
+/// Changed file in a report.
+#[derive(Serialize, Clone, Debug, PartialEq, Eq)]
+pub struct ReportFile {
+    pub path: Utf8PathBuf,
+    pub status: FileStatus,
+    pub added: u64,
+    pub removed: u64,
+    /// Whether the file is binary, in which case it has no hunks
+    pub binary: bool,
+    /// Whether the file is too large to be diffed, in which case it has no hunks and every line
+    /// of it is counted as changed
+    pub too_large: bool,
+    pub hunks: Vec<ReportHunk>,
+}
+
+/// Report of the changes between two crate versions, which is exported as JSON.
+#[derive(Serialize, Clone, Debug, PartialEq, Eq)]
+pub struct Report {
+    pub old: ReportVersion,
+    pub new: ReportVersion,
+    pub added: u64,
+    pub removed: u64,
+    pub files: Vec<ReportFile>,
+}
+
+impl Report {
+    /// Build the report of the given files.
+    pub fn new(diff: &VersionDiff, files: &[Utf8PathBuf]) -> Self {
+        let version = |source: &crate::data::CrateSource| ReportVersion {
+            krate: source.version.krate.clone(),
+            version: source.version.version.to_string(),
+        };
+        let files: Vec<ReportFile> = files
+            .iter()
+            .filter_map(|path| {
+                let (left, right) = (diff.left.files.get(path), diff.right.files.get(path));
+                if left.is_none() && right.is_none() {
+                    return None;
+                }
+                let binary = is_binary(left) || is_binary(right);
+                let too_large = !binary && diff.is_too_large(path);
+                let (added, removed) = diff.summary.get(path).copied().unwrap_or_default();
+                // binary files and files which are too large are not diffed
+                let file_diff = (!binary && !too_large).then(|| diff.file(path)).flatten();
+                let hunks = match file_diff {
+                    None => vec![],
+                    Some(file_diff) => hunk_ranges(&file_diff)
+                        .into_iter()
+                        .map(|hunk| ReportHunk {
+                            old_start: hunk.old_start,
+                            old_lines: hunk.old_lines,
+                            new_start: hunk.new_start,
+                            new_lines: hunk.new_lines,
+                            lines: file_diff.changes[hunk.range]
+                                .iter()
+                                .map(|(tag, line)| {
+                                    format!("{}{}", line_prefix(*tag), line_text(line))
+                                })
+                                .collect(),
+                        })
+                        .collect(),
+                };
+                Some(ReportFile {
+                    path: path.clone(),
+                    status: FileStatus::of(diff, path),
+                    added: added as u64,
+                    removed: removed as u64,
+                    binary,
+                    too_large,
+                    hunks,
+                })
+            })
+            .collect();
+        Report {
+            old: version(&diff.left),
+            new: version(&diff.right),
+            added: files.iter().map(|file| file.added).sum(),
+            removed: files.iter().map(|file| file.removed).sum(),
+            files,
+        }
+    }
+}
+
+/// Generate a JSON document with the statistics, statuses and hunks of the given files.
+pub fn json(diff: &VersionDiff, files: &[Utf8PathBuf]) -> String {
+    serde_json::to_string_pretty(&Report::new(diff, files)).unwrap_or_default()
+}
+
+/// Stylesheet of the HTML report.
+const HTML_STYLE: &str = "\
+body { font-family: sans-serif; margin: 2rem; color: #1f2937; }
+a { color: #2563eb; }
+table { border-collapse: collapse; }
+.files td { padding: 0.125rem 0.5rem; }
+.added { color: #16a34a; }
+.removed { color: #dc2626; }
+section { margin-top: 2rem; border: 1px solid #e5e7eb; border-radius: 0.375rem; overflow-x: auto; }
+section h2 { margin: 0; padding: 0.5rem 1rem; font-size: 1rem; font-family: monospace; background: #f9fafb; border-bottom: 1px solid #e5e7eb; }
+section p { padding: 0 1rem; color: #6b7280; font-style: italic; }
+.diff { width: 100%; font-family: monospace; }
+.diff td { padding: 0 0.5rem; white-space: pre-wrap; vertical-align: top; }
+.diff .line-number { text-align: right; color: #6b7280; background: #f9fafb; user-select: none; }
+.diff .hunk td { color: #6b7280; background: #eff6ff; }
+.diff .deletion td { background: #fee2e2; }
+.diff .insertion td { background: #dcfce7; }
+";
+
+/// Generate a self-contained HTML report of the given files, with syntax highlighting.
+pub fn html(diff: &VersionDiff, files: &[Utf8PathBuf]) -> String {
+    let report = Report::new(diff, files);
+    let title = format!(
+        "Diff of {} {} and {} {}",
+        report.old.krate, report.old.version, report.new.krate, report.new.version
+    );
+
+    let mut output = String::new();
+    let _ = writeln!(output, "<!DOCTYPE html>");
+    let _ = writeln!(output, "<html>\n<head>\n<meta charset=\"utf-8\">");
+    let _ = writeln!(output, "<title>{}</title>", escape(&title));
+    let theme = theme_css(DEFAULT_LIGHT_THEME).unwrap_or_default();
+    let _ = writeln!(
+        output,
+        "<style>\n{HTML_STYLE}{theme}</style>\n</head>\n<body>"
+    );
+    let _ = writeln!(output, "<h1>{}</h1>", escape(&title));
+    let _ = writeln!(
+        output,
+        "<p>{} files changed, <span class=\"added\">+{}</span> <span class=\"removed\">-{}</span></p>",
+        report.files.len(),
+        report.added,
+        report.removed
+    );
+
+    output.push_str("<table class=\"files\">\n");
+    for file in &report.files {
+        let _ = writeln!(
+            output,
+            "<tr><td><a href=\"#file-{path}\">{path}</a></td><td>{status:?}</td>\
+             <td class=\"added\">+{added}</td><td class=\"removed\">-{removed}</td></tr>",
+            path = escape(file.path.as_str()),
+            status = file.status,
+            added = file.added,
+            removed = file.removed,
+        );
+    }
+    output.push_str("</table>\n");
+
+    for file in &report.files {
+        let path = escape(file.path.as_str());
+        let _ = writeln!(output, "<section id=\"file-{path}\">\n<h2>{path}</h2>");
+        if file.binary {
+            output.push_str("<p>Binary file changed.</p>\n");
+        } else if file.too_large {
+            output.push_str("<p>File too large to diff, its changes are left out.</p>\n");
+        } else if let Some(file_diff) = diff.file(&file.path) {
+            html_file_diff(&file.path, &file_diff, &mut output);
+        }
+        output.push_str("</section>\n");
+    }
+
+    output.push_str("</body>\n</html>\n");
+    output
+}
+
+/// Append the highlighted hunks of a file diff to the HTML report.
+fn html_file_diff(path: &Utf8Path, file_diff: &FileDiff, output: &mut String) {
+    // the whole file is highlighted, as highlighting depends on the preceding lines
+    let syntax = infer_syntax_for_changes(path.as_str(), &file_diff.changes);
+    let highlighted = highlight_changes(syntax, &file_diff.changes);
+
+    output.push_str("<table class=\"diff\">\n");
+    for hunk in hunk_ranges(file_diff) {
+        let _ = writeln!(
+            output,
+            "<tr class=\"hunk\"><td colspan=\"4\">{}</td></tr>",
+            hunk.header()
+        );
+        let (mut left, mut right) = (hunk.old_start, hunk.new_start);
+        for (tag, line) in &highlighted[hunk.range] {
+            let (class, left_number, right_number) = match tag {
+                ChangeTag::Equal => ("unchanged", Some(left), Some(right)),
+                ChangeTag::Delete => ("deletion", Some(left), None),
+                ChangeTag::Insert => ("insertion", None, Some(right)),
+            };
+            left += usize::from(left_number.is_some());
+            right += usize::from(right_number.is_some());
+            let number = |number: Option<usize>| number.map(|n| n.to_string()).unwrap_or_default();
+            let _ = write!(
+                output,
+                "<tr class=\"{class}\"><td class=\"line-number\">{}</td>\
+                 <td class=\"line-number\">{}</td><td>{}</td><td class=\"code-line\">",
+                number(left_number),
+                number(right_number),
+                line_prefix(*tag),
+            );
+            // the line ending is dropped, as the cell already ends the line
+            output.push_str(&line.replace("\r\n", "").replace('\n', ""));
+            output.push_str("</td></tr>\n");
+        }
+    }
+    output.push_str("</table>\n");
+}
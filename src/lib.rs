@@ -5,14 +5,22 @@
 //! it, running a diff algorithm over the files and rendering the diff. Support syntax highlighting
 //! provided by the `syntect` crate.
 
+mod blame;
 mod cache;
+mod changelog;
+mod code_search;
 pub mod components;
 mod data;
-mod syntax;
+mod export;
+mod lockfile;
+mod patch;
+pub mod syntax;
 #[cfg(test)]
 mod tests;
+mod theme;
 mod version;
 pub mod views;
+pub mod worker;
 
 use crate::{
     version::{VersionId, VersionNamed},
@@ -32,7 +40,8 @@ pub type Link<R = Route> = yew_router::components::Link<R>;
 ///
 /// The default route for `/:name` is to render a crate. Therefore, when adding new routes, one
 /// must be careful to not alias an existing crate name. For example, adding a route with the path
-/// `/serde` would mask the crate view for the `serde` crate.
+/// `/serde` would mask the crate view for the `serde` crate. New views therefore go below `/_/`,
+/// as crate names must start with a letter.
 #[derive(Clone, Routable, PartialEq)]
 pub enum Route {
     /// Home view, shows search bar and summary.
@@ -43,6 +52,65 @@ pub enum Route {
     #[at("/about")]
     About,
 
+    /// Settings view, allows choosing the theme and inspecting and clearing the persistent cache.
+    #[at("/_/settings")]
+    Settings,
+
+    /// Upload view, allows comparing a local crate against its published versions.
+    #[at("/_/upload")]
+    Upload,
+
+    /// Diff view between a published version and an uploaded crate, will redirect to default
+    /// file.
+    #[at("/_/upload/:id/:version")]
+    UploadDiff { id: usize, version: VersionId },
+
+    /// File diff view between a published version and an uploaded crate.
+    #[at("/_/upload/:id/:version/*path")]
+    UploadFile {
+        id: usize,
+        version: VersionId,
+        path: Utf8PathBuf,
+    },
+
+    /// Patch view, allows viewing a unified diff.
+    #[at("/_/patch")]
+    Patch,
+
+    /// Diff view of an imported patch, will redirect to the first changed file.
+    #[at("/_/patch/:id")]
+    PatchDiff { id: usize },
+
+    /// File diff view of an imported patch.
+    #[at("/_/patch/:id/*path")]
+    PatchFile { id: usize, path: Utf8PathBuf },
+
+    /// Lockfile view, compares the packages of two `Cargo.lock` files.
+    #[at("/_/lockfile")]
+    Lockfile,
+
+    /// Timeline view, lists all versions of a crate.
+    #[at("/_/timeline/:krate")]
+    Timeline { krate: String },
+
+    /// Blame view, annotates the lines of a file with the versions which introduced them.
+    #[at("/_/blame/:krate/:old_version/:new_version/*path")]
+    Blame {
+        krate: String,
+        old_version: VersionId,
+        new_version: VersionId,
+        path: Utf8PathBuf,
+    },
+
+    /// All files view, renders the diffs of all changed files between `old` and `new` versions.
+    #[at("/_/all/:old_krate/:old_version/:new_krate/:new_version")]
+    AllFiles {
+        old_krate: String,
+        old_version: VersionId,
+        new_krate: String,
+        new_version: VersionId,
+    },
+
     /// Search view, shows search results.
     #[at("/search/:query")]
     Search { query: String },
@@ -116,6 +184,25 @@ impl Route {
         match route {
             Route::Home => html! { <Home /> },
             Route::About => html! { <About /> },
+            Route::Settings => html! { <Settings /> },
+            Route::Upload => html! { <Upload /> },
+            Route::Lockfile => html! { <Lockfile /> },
+            Route::Patch => html! { <Patch /> },
+            Route::PatchDiff { id } => html! { <PatchDiff {id} /> },
+            Route::PatchFile { id, path } => html! { <PatchDiff {id} {path} /> },
+            Route::Timeline { krate } => html! { <Timeline {krate} /> },
+            Route::Blame {
+                krate,
+                old_version,
+                new_version,
+                path,
+            } => html! {
+                <Blame {krate} old={old_version} new={new_version} {path} />
+            },
+            Route::UploadDiff { id, version } => html! { <UploadDiff {id} {version} /> },
+            Route::UploadFile { id, version, path } => html! {
+                <UploadDiff {id} {version} {path} />
+            },
             Route::Browse { krate, version } => html! {
                 <Diff
                     src_name={krate.clone()}
@@ -180,6 +267,14 @@ impl Route {
             } => html! {
                 <Diff src_name={old_krate} dst_name={new_krate} old={old_version} new={new_version} {path} />
             },
+            Route::AllFiles {
+                old_krate,
+                old_version,
+                new_krate,
+                new_version,
+            } => html! {
+                <Diff src_name={old_krate} dst_name={new_krate} old={old_version} new={new_version} all_files=true />
+            },
             Route::NotFound => html! { <NotFound /> },
             Route::Search { query } => html! { <Search search={query} /> },
             Route::RepoFile {
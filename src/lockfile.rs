@@ -0,0 +1,187 @@
+//! Comparison of `Cargo.lock` files, to review dependency updates.
+//!
+//! Lockfiles are either parsed in full, or reconstructed from a unified diff of a lockfile. In
+//! the latter case, only the packages which appear in the hunks of the diff are known, which is
+//! sufficient to determine the changes.
+
+use crate::version::is_breaking;
+use anyhow::Result;
+use semver::Version;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+
+/// Sources of packages which are published on crates.io.
+const CRATES_IO_SOURCES: &[&str] = &[
+    "registry+https://github.com/rust-lang/crates.io-index",
+    "sparse+https://index.crates.io/",
+];
+
+/// Package locked in a `Cargo.lock` file.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize)]
+pub struct LockPackage {
+    /// Name of the package
+    pub name: String,
+    /// Locked version of the package
+    pub version: Version,
+    /// Source of the package, missing for path dependencies and workspace members
+    #[serde(default)]
+    pub source: Option<String>,
+}
+
+impl LockPackage {
+    /// Determines if this package is published on crates.io.
+    pub fn is_registry(&self) -> bool {
+        self.source
+            .as_deref()
+            .is_some_and(|source| CRATES_IO_SOURCES.contains(&source))
+    }
+}
+
+#[derive(Deserialize)]
+struct Lockfile {
+    #[serde(default)]
+    package: Vec<LockPackage>,
+}
+
+/// Parse the packages of a `Cargo.lock` file.
+pub fn parse(text: &str) -> Result<Vec<LockPackage>> {
+    let lockfile: Lockfile = toml::from_str(text)?;
+    Ok(lockfile.package)
+}
+
+/// Value of a `key = "value"` line, if the line assigns `key`.
+fn string_value<'a>(line: &'a str, key: &str) -> Option<&'a str> {
+    let value = line.strip_prefix(key)?.trim_start().strip_prefix('=')?;
+    value.trim().strip_prefix('"')?.strip_suffix('"')
+}
+
+/// Collects packages from the lines of a partial lockfile.
+#[derive(Default)]
+struct PackageScanner {
+    packages: Vec<LockPackage>,
+    name: Option<String>,
+    version: Option<Version>,
+    source: Option<String>,
+}
+
+impl PackageScanner {
+    /// Finish the current package, keeping it if both its name and version are known.
+    fn finish(&mut self) {
+        let (name, version, source) = (self.name.take(), self.version.take(), self.source.take());
+        if let (Some(name), Some(version)) = (name, version) {
+            self.packages.push(LockPackage {
+                name,
+                version,
+                source,
+            });
+        }
+    }
+
+    fn line(&mut self, line: &str) {
+        if line.trim() == "[[package]]" {
+            self.finish();
+        } else if let Some(name) = string_value(line, "name") {
+            self.name = Some(name.into());
+        } else if let Some(version) = string_value(line, "version") {
+            self.version = version.parse().ok();
+        } else if let Some(source) = string_value(line, "source") {
+            self.source = Some(source.into());
+        }
+    }
+}
+
+/// Parse the packages before and after a unified diff of a `Cargo.lock` file.
+///
+/// Only packages whose name and version are both part of a hunk are returned, which is the case
+/// for all changed packages when the diff has the default amount of context.
+pub fn parse_diff(diff: &str) -> (Vec<LockPackage>, Vec<LockPackage>) {
+    let mut old = PackageScanner::default();
+    let mut new = PackageScanner::default();
+    for line in diff.lines() {
+        if line.starts_with("@@") {
+            // packages do not continue across hunks
+            old.finish();
+            new.finish();
+            continue;
+        }
+        if ["---", "+++", "diff ", "index ", "\\"]
+            .iter()
+            .any(|prefix| line.starts_with(prefix))
+        {
+            continue;
+        }
+        match line.split_at_checked(1) {
+            Some(("-", line)) => old.line(line),
+            Some(("+", line)) => new.line(line),
+            Some((" ", line)) => {
+                old.line(line);
+                new.line(line);
+            }
+            _ => {}
+        }
+    }
+    old.finish();
+    new.finish();
+    (old.packages, new.packages)
+}
+
+/// Change of a package between two lockfiles.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LockChange {
+    /// Name of the package
+    pub name: String,
+    /// Version in the old lockfile, `None` if the package was added
+    pub old: Option<Version>,
+    /// Version in the new lockfile, `None` if the package was removed
+    pub new: Option<Version>,
+    /// Whether the package is published on crates.io, so its versions can be compared
+    pub registry: bool,
+}
+
+/// Compare the packages of two lockfiles.
+///
+/// Lockfiles may contain several versions of the same package. Removed versions are paired with
+/// semver-compatible added versions first, the remaining ones are paired in ascending order.
+/// Changes are sorted by package name.
+pub fn changes(old: &[LockPackage], new: &[LockPackage]) -> Vec<LockChange> {
+    let mut packages: BTreeMap<&str, (Vec<&LockPackage>, Vec<&LockPackage>)> = BTreeMap::new();
+    for package in old {
+        if !new.contains(package) {
+            packages.entry(&package.name).or_default().0.push(package);
+        }
+    }
+    for package in new {
+        if !old.contains(package) {
+            packages.entry(&package.name).or_default().1.push(package);
+        }
+    }
+
+    let mut changes = vec![];
+    for (name, (mut removed, mut added)) in packages {
+        removed.sort_by(|left, right| left.version.cmp(&right.version));
+        added.sort_by(|left, right| left.version.cmp(&right.version));
+        let change = |old: Option<&LockPackage>, new: Option<&LockPackage>| LockChange {
+            name: name.into(),
+            old: old.map(|package| package.version.clone()),
+            new: new.map(|package| package.version.clone()),
+            registry: old.into_iter().chain(new).all(LockPackage::is_registry),
+        };
+
+        let mut leftover = vec![];
+        for package in removed {
+            match added
+                .iter()
+                .position(|other| !is_breaking(&package.version, &other.version))
+            {
+                Some(index) => changes.push(change(Some(package), Some(added.remove(index)))),
+                None => leftover.push(package),
+            }
+        }
+        let mut added = added.into_iter();
+        for package in leftover {
+            changes.push(change(Some(package), added.next()));
+        }
+        changes.extend(added.map(|package| change(None, Some(package))));
+    }
+    changes
+}
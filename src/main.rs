@@ -14,5 +14,6 @@ fn init_logging() {
 
 fn main() {
     init_logging();
+    diff_rs::views::restore_settings();
     yew::Renderer::<diff_rs::App>::new().render();
 }
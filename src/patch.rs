@@ -0,0 +1,333 @@
+//! Parsing of unified diffs, as generated by `git diff` or `diff -u`, to view them like a diff of
+//! two crate versions.
+//!
+//! A patch only contains the lines around the changes. The files of both sides are reconstructed
+//! from the hunks, and the lines between hunks are filled with blank lines. This keeps the line
+//! numbers of the patch, and the filler lines are folded away since they are unchanged.
+
+use crate::data::{CrateSource, VersionDiff, VersionInfo};
+use anyhow::{anyhow, bail, Context, Result};
+use bytes::Bytes;
+use camino::{Utf8Path, Utf8PathBuf};
+use std::{collections::BTreeMap, sync::Arc};
+
+/// Prefix of lines which mark that the preceding line has no line ending, such as
+/// `\ No newline at end of file`.
+const NO_NEWLINE_PREFIX: &str = "\\ ";
+
+/// Line of a hunk.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum PatchLine {
+    /// Line which is in both versions
+    Context(String),
+    /// Line which is only in the old version
+    Removed(String),
+    /// Line which is only in the new version
+    Added(String),
+}
+
+/// Hunk of a patched file.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct PatchHunk {
+    /// First line of the hunk in the old version, starting at 1
+    pub old_start: usize,
+    pub old_lines: usize,
+    /// First line of the hunk in the new version, starting at 1
+    pub new_start: usize,
+    pub new_lines: usize,
+    pub lines: Vec<PatchLine>,
+    /// Whether the last line of the old version in this hunk has no line ending
+    pub old_no_newline: bool,
+    /// Whether the last line of the new version in this hunk has no line ending
+    pub new_no_newline: bool,
+}
+
+impl PatchHunk {
+    /// Mark the last line of the hunk as having no line ending.
+    fn mark_no_newline(&mut self) {
+        match self.lines.last() {
+            Some(PatchLine::Removed(_)) => self.old_no_newline = true,
+            Some(PatchLine::Added(_)) => self.new_no_newline = true,
+            Some(PatchLine::Context(_)) => {
+                self.old_no_newline = true;
+                self.new_no_newline = true;
+            }
+            None => {}
+        }
+    }
+}
+
+/// File changed by a patch.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct PatchFile {
+    /// Path of the old version, `None` if the file is added
+    pub old_path: Option<Utf8PathBuf>,
+    /// Path of the new version, `None` if the file is removed
+    pub new_path: Option<Utf8PathBuf>,
+    /// Whether the file is binary, in which case there are no hunks
+    pub binary: bool,
+    pub hunks: Vec<PatchHunk>,
+}
+
+impl PatchFile {
+    /// Path of the file, which is the new path unless the file was removed.
+    pub fn path(&self) -> Option<&Utf8Path> {
+        self.new_path.as_deref().or(self.old_path.as_deref())
+    }
+}
+
+/// Parse a path in the header of a patched file.
+///
+/// Returns `None` for `/dev/null`, which is used for added and removed files. Prefixes like `a/`
+/// and `b/` as well as timestamps as generated by `diff -u` are removed, and so are leading `./`
+/// as in `diff -u ./old.rs ./new.rs`.
+fn parse_path(path: &str, prefix: &str) -> Option<Utf8PathBuf> {
+    let path = path.split('\t').next().unwrap_or(path).trim_end();
+    let path = path.trim_matches('"').trim_start_matches("./");
+    if path == "/dev/null" {
+        return None;
+    }
+    Some(path.strip_prefix(prefix).unwrap_or(path).into())
+}
+
+/// Remove the directories of both versions from the paths of a plain unified diff.
+///
+/// `diff -ru old/ new/` prefixes paths with the compared directories. If the paths only differ in
+/// their first component, it is removed like `patch -p1` would.
+fn strip_directories(file: &mut PatchFile) {
+    let (Some(old), Some(new)) = (&file.old_path, &file.new_path) else {
+        return;
+    };
+    let (Some((old_dir, old_rest)), Some((new_dir, new_rest))) =
+        (old.as_str().split_once('/'), new.as_str().split_once('/'))
+    else {
+        return;
+    };
+    if old_dir != new_dir && old_rest == new_rest && !old_rest.is_empty() {
+        let path = Utf8PathBuf::from(old_rest);
+        file.old_path = Some(path.clone());
+        file.new_path = Some(path);
+    }
+}
+
+/// Parse a range in a hunk header, such as `12,3` or `12`.
+fn parse_range(range: &str) -> Result<(usize, usize)> {
+    let (start, lines) = range.split_once(',').unwrap_or((range, "1"));
+    Ok((start.parse()?, lines.parse()?))
+}
+
+/// Parse a hunk header, such as `@@ -1,3 +1,4 @@ fn main() {`.
+fn parse_hunk_header(line: &str) -> Result<PatchHunk> {
+    let ranges = line
+        .strip_prefix("@@ -")
+        .and_then(|line| line.split(" @@").next())
+        .ok_or_else(|| anyhow!("invalid hunk header {line:?}"))?;
+    let (old, new) = ranges
+        .split_once(" +")
+        .ok_or_else(|| anyhow!("invalid hunk header {line:?}"))?;
+    let (old_start, old_lines) = parse_range(old).with_context(|| format!("in {line:?}"))?;
+    let (new_start, new_lines) = parse_range(new).with_context(|| format!("in {line:?}"))?;
+    Ok(PatchHunk {
+        old_start,
+        old_lines,
+        new_start,
+        new_lines,
+        ..Default::default()
+    })
+}
+
+/// Parse a unified diff into the files it changes.
+///
+/// Lines outside of files, such as the commit message of a patch generated by
+/// `git format-patch`, are ignored.
+pub fn parse(text: &str) -> Result<Vec<PatchFile>> {
+    let mut files: Vec<PatchFile> = vec![];
+    // remaining lines of the old and new version in the current hunk
+    let mut remaining: (usize, usize) = (0, 0);
+    // whether the header of a file started by a `diff` line is being parsed
+    let mut in_header = false;
+    // whether the current file has no `diff --git` line, as in plain unified diffs
+    let mut plain = false;
+    for (number, line) in text.lines().enumerate() {
+        let error = || format!("line {}", number + 1);
+        let line = line.strip_suffix('\r').unwrap_or(line);
+
+        if remaining != (0, 0) {
+            let Some(hunk) = files.last_mut().and_then(|file| file.hunks.last_mut()) else {
+                bail!("hunk without a file at {}", error());
+            };
+            // some tools strip the space of empty context lines
+            let (prefix, content) = line.split_at_checked(1).unwrap_or((" ", ""));
+            let content = content.to_string();
+            match prefix {
+                " " => {
+                    remaining = (remaining.0.saturating_sub(1), remaining.1.saturating_sub(1));
+                    hunk.lines.push(PatchLine::Context(content));
+                }
+                "-" if remaining.0 > 0 => {
+                    remaining.0 -= 1;
+                    hunk.lines.push(PatchLine::Removed(content));
+                }
+                "+" if remaining.1 > 0 => {
+                    remaining.1 -= 1;
+                    hunk.lines.push(PatchLine::Added(content));
+                }
+                "\\" => hunk.mark_no_newline(),
+                _ => bail!("unexpected line in hunk at {}: {line:?}", error()),
+            }
+            continue;
+        }
+
+        if line.starts_with(NO_NEWLINE_PREFIX) {
+            // marks the last line of the preceding hunk
+            if let Some(hunk) = files.last_mut().and_then(|file| file.hunks.last_mut()) {
+                hunk.mark_no_newline();
+            }
+        } else if let Some(paths) = line.strip_prefix("diff --git ") {
+            let (old, new) = paths.split_once(" b/").unwrap_or((paths, ""));
+            in_header = true;
+            plain = false;
+            files.push(PatchFile {
+                old_path: parse_path(old, "a/"),
+                new_path: (!new.is_empty()).then(|| new.into()),
+                ..Default::default()
+            });
+        } else if let Some(path) = line.strip_prefix("--- ") {
+            // plain unified diffs have no `diff` line before the file header
+            if !in_header {
+                files.push(PatchFile::default());
+                plain = true;
+            }
+            in_header = false;
+            if let Some(file) = files.last_mut() {
+                file.old_path = parse_path(path, "a/");
+            }
+        } else if let Some(path) = line.strip_prefix("+++ ") {
+            let Some(file) = files.last_mut() else {
+                bail!("file header without old path at {}", error());
+            };
+            file.new_path = parse_path(path, "b/");
+            if plain {
+                strip_directories(file);
+            }
+        } else if let Some(path) = line.strip_prefix("rename from ") {
+            if let Some(file) = files.last_mut() {
+                file.old_path = Some(path.into());
+            }
+        } else if let Some(path) = line.strip_prefix("rename to ") {
+            if let Some(file) = files.last_mut() {
+                file.new_path = Some(path.into());
+            }
+        } else if line.starts_with("new file mode ") {
+            if let Some(file) = files.last_mut() {
+                file.old_path = None;
+            }
+        } else if line.starts_with("deleted file mode ") {
+            if let Some(file) = files.last_mut() {
+                file.new_path = None;
+            }
+        } else if line.starts_with("Binary files ") || line == "GIT binary patch" {
+            if let Some(file) = files.last_mut() {
+                file.binary = true;
+            }
+        } else if line.starts_with("@@ ") {
+            let Some(file) = files.last_mut() else {
+                bail!("hunk without a file at {}", error());
+            };
+            let hunk = parse_hunk_header(line).with_context(error)?;
+            in_header = false;
+            remaining = (hunk.old_lines, hunk.new_lines);
+            file.hunks.push(hunk);
+        }
+    }
+
+    if remaining != (0, 0) {
+        bail!("patch ends in the middle of a hunk");
+    }
+    if files.is_empty() {
+        bail!("patch does not change any files");
+    }
+    Ok(files)
+}
+
+/// Reconstruct the old and new version of a file from its hunks.
+///
+/// Lines which are not part of any hunk are filled with blank lines.
+fn reconstruct(file: &PatchFile) -> (String, String) {
+    let (mut old, mut new) = (String::new(), String::new());
+    let (mut old_line, mut new_line) = (1, 1);
+    for hunk in &file.hunks {
+        // empty ranges refer to the line before them
+        while old_line < hunk.old_start + usize::from(hunk.old_lines == 0) {
+            old.push('\n');
+            old_line += 1;
+        }
+        while new_line < hunk.new_start + usize::from(hunk.new_lines == 0) {
+            new.push('\n');
+            new_line += 1;
+        }
+        for line in &hunk.lines {
+            let (in_old, in_new, content) = match line {
+                PatchLine::Context(content) => (true, true, content),
+                PatchLine::Removed(content) => (true, false, content),
+                PatchLine::Added(content) => (false, true, content),
+            };
+            if in_old {
+                old.push_str(content);
+                old.push('\n');
+                old_line += 1;
+            }
+            if in_new {
+                new.push_str(content);
+                new.push('\n');
+                new_line += 1;
+            }
+        }
+        if hunk.old_no_newline {
+            old.pop();
+        }
+        if hunk.new_no_newline {
+            new.pop();
+        }
+    }
+    (old, new)
+}
+
+/// Create a diff from the files of a patch.
+///
+/// Both sides are shown as versions of a crate named `patch`. Binary files cannot be
+/// reconstructed, they are left out. Paths are checked like the paths of a crate archive, so an
+/// error is returned for paths such as `../lib.rs`, or a file which is also a directory.
+pub fn version_diff(files: &[PatchFile]) -> Result<VersionDiff> {
+    let mut old_files = BTreeMap::new();
+    let mut new_files = BTreeMap::new();
+    for file in files.iter().filter(|file| !file.binary) {
+        let (old, new) = reconstruct(file);
+        if let Some(path) = &file.old_path {
+            old_files.insert(path.clone(), Bytes::from(old));
+        }
+        if let Some(path) = &file.new_path {
+            new_files.insert(path.clone(), Bytes::from(new));
+        }
+    }
+
+    let version = |version: &str| VersionInfo {
+        checksum: vec![],
+        krate: "patch".into(),
+        dl_path: Default::default(),
+        yanked: false,
+        version: version.parse().unwrap(),
+        crate_size: None,
+        created_at: None,
+    };
+    Ok(VersionDiff::new(
+        Arc::new(CrateSource::from_contents_unverified(
+            version("0.0.0-old"),
+            old_files,
+        )?),
+        Arc::new(CrateSource::from_contents_unverified(
+            version("0.0.0-new"),
+            new_files,
+        )?),
+    ))
+}
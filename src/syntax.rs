@@ -1,101 +1,327 @@
+//! Syntax highlighting of files and changes, using syntect.
+//!
+//! Syntaxes and themes are loaded from dumps which are created by the build script, and code is
+//! highlighted into HTML with classes, which are colored by the stylesheet of a theme.
+
+use bytes::Bytes;
+use regex::Regex;
 use similar::ChangeTag;
-use subslice_offset::SubsliceOffset;
+use std::{cell::RefCell, fmt::Write, ops::Range, rc::Rc};
 use syntect::{
-    easy::HighlightLines,
-    highlighting::{Color, FontStyle, Style, Theme, ThemeSet},
-    parsing::{SyntaxReference, SyntaxSet},
+    dumps::from_uncompressed_data,
+    highlighting::{Theme, ThemeSet},
+    html::{css_for_theme_with_class_style, line_tokens_to_classed_spans, ClassStyle},
+    parsing::{ParseState, Scope, ScopeStack, SyntaxReference, SyntaxSet},
 };
 
+/// Dump of the syntax set, containing the default syntaxes of syntect and those in `syntaxes/`,
+/// compiled by the build script.
+const SYNTAX_DUMP: &[u8] = include_bytes!(concat!(env!("OUT_DIR"), "/syntaxes.packdump"));
+
+/// Dump of the theme set, containing the themes of syntect which can be chosen.
+const THEME_DUMP: &[u8] = include_bytes!(concat!(env!("OUT_DIR"), "/themes.themedump"));
+
 lazy_static::lazy_static! {
-    /// The default syntect syntax set, used for parsing language definitions.
-    static ref SYNTAX_SET: SyntaxSet = SyntaxSet::load_defaults_newlines();
-    /// The default syntect theme set, currently only one theme is ever used.
-    static ref THEME_SET: ThemeSet = ThemeSet::load_defaults();
-    /// Theme definition from the default syntect theme set.
-    static ref THEME: &'static Theme = &THEME_SET.themes["InspiredGitHub"];
+    /// The syntect syntax set, used for parsing language definitions.
+    static ref SYNTAX_SET: SyntaxSet = load_syntax_set();
+    /// The syntect theme set, any of its themes can be used for highlighting.
+    static ref THEME_SET: ThemeSet = load_theme_set();
+    /// Vim modeline which sets the file type, such as `vim: set ft=rust:`.
+    static ref VIM_MODELINE: Regex =
+        Regex::new(r"(?:^|\s)(?:vi|vim|ex):.*?\b(?:ft|filetype|syn|syntax)=([\w+#.-]+)").unwrap();
+    /// Emacs modeline which sets the mode, such as `-*- mode: rust -*-` or `-*- rust -*-`.
+    static ref EMACS_MODELINE: Regex =
+        Regex::new(r"-\*-\s*(?:.*?\bmode:\s*)?([\w+#.-]+)\s*(?:;[^\n]*?)?-\*-").unwrap();
+}
+
+/// Load the syntax set from the dump which is embedded into the application.
+///
+/// The dump is not compressed, so this only needs to deserialize it. The regular expressions of
+/// the syntaxes are compiled when they are first used.
+pub fn load_syntax_set() -> SyntaxSet {
+    from_uncompressed_data(SYNTAX_DUMP).expect("syntax set dump should be valid")
+}
+
+/// Load the theme set from the dump which is embedded into the application.
+pub fn load_theme_set() -> ThemeSet {
+    from_uncompressed_data(THEME_DUMP).expect("theme set dump should be valid")
+}
+
+/// Prefix of the classes of highlighted code, to avoid collisions with other classes.
+const CLASS_PREFIX: &str = "hl-";
+
+/// Style of the classes of highlighted code, such as `hl-keyword hl-control`.
+const CLASS_STYLE: ClassStyle = ClassStyle::SpacedPrefixed {
+    prefix: CLASS_PREFIX,
+};
+
+/// Theme which is used for light backgrounds unless another one is chosen.
+pub const DEFAULT_LIGHT_THEME: &str = "InspiredGitHub";
+
+/// Theme which is used for dark backgrounds unless another one is chosen.
+pub const DEFAULT_DARK_THEME: &str = "base16-ocean.dark";
+
+/// Names of the themes which can be used for highlighting, in alphabetical order.
+pub fn theme_names() -> impl Iterator<Item = &'static str> {
+    THEME_SET.themes.keys().map(String::as_str)
+}
+
+/// Generate the stylesheet which colors highlighted code with the theme of the given name.
+///
+/// Code is highlighted with classes rather than colors, so that the theme can be changed without
+/// highlighting it again. Returns `None` if there is no theme with the name.
+pub fn theme_css(name: &str) -> Option<String> {
+    let theme: &Theme = THEME_SET.themes.get(name)?;
+    let mut css = css_for_theme_with_class_style(theme, CLASS_STYLE).ok()?;
+    // unhighlighted text, such as removed lines, uses the foreground color of the theme
+    if let Some(color) = theme.settings.foreground {
+        let _ = writeln!(
+            css,
+            ".code-line {{ color: #{:02x}{:02x}{:02x}; }}",
+            color.r, color.g, color.b
+        );
+    }
+    Some(css)
+}
+
+/// Escape text for use in HTML.
+pub fn escape_html(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Number of lines at the start and at the end of a file which are searched for modelines.
+const MODELINE_LINES: usize = 5;
+
+/// Get the syntax named by an editor modeline in the first or last lines of a file.
+///
+/// Both Vim modelines such as `vim: set ft=toml:` and Emacs modelines such as
+/// `-*- mode: python -*-` are supported. The name is looked up as an extension and then as the
+/// name of a syntax.
+fn modeline_syntax<'a>(
+    lines: impl DoubleEndedIterator<Item = &'a str> + Clone,
+) -> Option<&'static SyntaxReference> {
+    lines
+        .clone()
+        .take(MODELINE_LINES)
+        .chain(lines.rev().take(MODELINE_LINES))
+        .find_map(|line| {
+            let name = VIM_MODELINE
+                .captures(line)
+                .or_else(|| EMACS_MODELINE.captures(line))?
+                .get(1)?
+                .as_str();
+            SYNTAX_SET.find_syntax_by_token(name)
+        })
+}
+
+/// Get the syntax named by the interpreter of a shebang, such as `#!/usr/bin/env python3`.
+///
+/// Inner attributes of Rust files, such as `#![no_std]`, are not shebangs.
+fn shebang_syntax(first_line: Option<&str>) -> Option<&'static SyntaxReference> {
+    first_line
+        .filter(|line| line.starts_with("#!") && !line.starts_with("#!["))
+        .and_then(|line| SYNTAX_SET.find_syntax_by_first_line(line))
 }
 
 /// Get the `SyntaxReference` from `SYNTAX_SET` to use for syntax highlighting
 /// the given file.
 ///
-/// It will be based first on the file's name, then the file's extension, and
-/// finally based on the first line of the file.
-pub fn infer_syntax_for_file(path: &str, first_line: Option<&str>) -> &'static SyntaxReference {
-    // Determine which syntax should be used for this file. It will be based
-    // first on the file's name, then the file's extension, then the first line.
+/// A syntax set by a modeline in the lines of the file takes precedence. Otherwise it will be
+/// based first on the file's name, then on a shebang, then the file's extension, and finally on
+/// the first line of the file.
+pub fn infer_syntax_for_file<'a, I>(path: &str, lines: I) -> &'static SyntaxReference
+where
+    I: IntoIterator<Item = &'a str>,
+    I::IntoIter: DoubleEndedIterator + Clone,
+{
+    let lines = lines.into_iter();
+    let first_line = lines.clone().next();
     let (_, file_name) = path.rsplit_once('/').unwrap_or(("", path));
     let (_, extension) = file_name.rsplit_once('.').unwrap_or(("", file_name));
-    SYNTAX_SET
-        .find_syntax_by_extension(file_name)
+    modeline_syntax(lines)
+        .or_else(|| SYNTAX_SET.find_syntax_by_extension(file_name))
+        .or_else(|| shebang_syntax(first_line))
         .or_else(|| SYNTAX_SET.find_syntax_by_extension(extension))
         .or_else(|| first_line.and_then(|line| SYNTAX_SET.find_syntax_by_first_line(line)))
         .unwrap_or_else(|| SYNTAX_SET.find_syntax_plain_text())
 }
 
-/// Highlight a single line as a bytes slice, avoiding extra copies.
-fn highlight_bytes_line(
-    highlight_lines: &mut HighlightLines<'_>,
+/// Get the `SyntaxReference` to use for syntax highlighting the changes of a file.
+///
+/// The lines of the file are taken from the new version of the file, if there is one.
+pub fn infer_syntax_for_changes(
+    path: &str,
+    changes: &[(ChangeTag, Bytes)],
+) -> &'static SyntaxReference {
+    let new = changes.iter().any(|(tag, _)| *tag != ChangeTag::Delete);
+    infer_syntax_for_file(
+        path,
+        changes
+            .iter()
+            .filter(move |(tag, _)| !new || *tag != ChangeTag::Delete)
+            .map(|(_, line)| std::str::from_utf8(line).unwrap_or_default()),
+    )
+}
+
+/// Opening tag of the span for a scope, with a class for every atom of the scope.
+fn scope_span(scope: Scope) -> String {
+    let classes: Vec<_> = scope
+        .build_string()
+        .split('.')
+        .map(|atom| format!("{CLASS_PREFIX}{atom}"))
+        .collect();
+    format!("<span class=\"{}\">", classes.join(" "))
+}
+
+/// Highlight a single line, continuing from the state of the previous line.
+///
+/// Spans which are still open at the start of the line are opened again, and spans which are
+/// open at the end of the line are closed, so that every line can be rendered on its own.
+fn highlight_line(
+    syntax_set: &SyntaxSet,
+    parse_state: &mut ParseState,
+    scope_stack: &mut ScopeStack,
     tag: ChangeTag,
-    bytes: &bytes::Bytes,
-) -> Option<Vec<(Style, bytes::Bytes)>> {
+    bytes: &Bytes,
+) -> Option<HighlightedLine> {
     // Don't highlight removal lines, as it could confuse the parser.
     if tag == ChangeTag::Delete {
         return None;
     }
 
     let line = std::str::from_utf8(&bytes[..]).ok()?;
-    let styles = highlight_lines.highlight_line(line, &SYNTAX_SET).ok()?;
-
-    // Map each chunk back to the bytes slice to avoid unnecessary copies.
-    Some(
-        styles
-            .into_iter()
-            .map(|(style, chunk)| {
-                let start = line[..].subslice_offset(chunk).unwrap();
-                (style, bytes.slice(start..start + chunk.len()))
-            })
-            .collect::<Vec<_>>(),
-    )
+    let ops = parse_state.parse_line(line, syntax_set).ok()?;
+    let mut html: String = scope_stack
+        .as_slice()
+        .iter()
+        .copied()
+        .map(scope_span)
+        .collect();
+    let (spans, _) = line_tokens_to_classed_spans(line, &ops, CLASS_STYLE, scope_stack).ok()?;
+    html.push_str(&spans);
+    html.push_str(&"</span>".repeat(scope_stack.as_slice().len()));
+    Some(html.into())
+}
+
+/// Highlight a single line, escaping it instead if it cannot be highlighted.
+fn highlight_or_escape(
+    syntax_set: &SyntaxSet,
+    parse_state: &mut ParseState,
+    scope_stack: &mut ScopeStack,
+    tag: ChangeTag,
+    bytes: &Bytes,
+) -> HighlightedLine {
+    highlight_line(syntax_set, parse_state, scope_stack, tag, bytes)
+        .unwrap_or_else(|| escape_html(&String::from_utf8_lossy(bytes)).into())
+}
+
+/// Line of a change as HTML, with spans whose classes are the scopes of the highlighted text.
+///
+/// The classes are colored by the stylesheet of a theme, see [`theme_css`].
+pub type HighlightedLine = Rc<str>;
+
+/// Syntax highlighter which highlights the lines of a list of changes on demand.
+///
+/// Highlighting needs to process lines in order, so requesting a line highlights all lines before
+/// it first. Highlighted lines are kept, so every line is highlighted at most once. This allows
+/// only highlighting the lines which are visible, for files which are too large to highlight up
+/// front.
+pub struct LazyHighlighter {
+    changes: Vec<(ChangeTag, Bytes)>,
+    state: RefCell<HighlighterState>,
+}
+
+struct HighlighterState {
+    parse_state: ParseState,
+    scope_stack: ScopeStack,
+    highlighted: Vec<HighlightedLine>,
+}
+
+impl LazyHighlighter {
+    /// Create a highlighter for the changes, which will use the given syntax.
+    pub fn new(syntax: &'static SyntaxReference, changes: Vec<(ChangeTag, Bytes)>) -> Self {
+        Self {
+            changes,
+            state: RefCell::new(HighlighterState {
+                parse_state: ParseState::new(syntax),
+                scope_stack: ScopeStack::new(),
+                highlighted: vec![],
+            }),
+        }
+    }
+
+    /// Number of lines which were highlighted so far.
+    #[cfg(test)]
+    pub fn highlighted(&self) -> usize {
+        self.state.borrow().highlighted.len()
+    }
+
+    /// Get the highlighted lines in the range, highlighting lines up to the end of it if needed.
+    pub fn lines(&self, range: Range<usize>) -> Vec<(ChangeTag, HighlightedLine)> {
+        let end = range.end.min(self.changes.len());
+        let mut state = self.state.borrow_mut();
+        let HighlighterState {
+            parse_state,
+            scope_stack,
+            highlighted,
+        } = &mut *state;
+        for (tag, bytes) in &self.changes[highlighted.len().min(end)..end] {
+            highlighted.push(highlight_or_escape(
+                &SYNTAX_SET,
+                parse_state,
+                scope_stack,
+                *tag,
+                bytes,
+            ));
+        }
+
+        let start = range.start.min(end);
+        self.changes[start..end]
+            .iter()
+            .zip(&highlighted[start..end])
+            .map(|((tag, _), line)| (*tag, line.clone()))
+            .collect()
+    }
 }
 
 /// Apply syntax highlighting to a list of changes using the listed syntax.
+///
+/// This highlights every line up front, use [`LazyHighlighter`] to only highlight lines as they
+/// are needed.
 pub fn highlight_changes(
     syntax: &'static SyntaxReference,
-    changes: &[(ChangeTag, bytes::Bytes)],
-) -> Vec<(ChangeTag, Vec<(Style, bytes::Bytes)>)> {
-    let default_style = Style {
-        foreground: THEME.settings.foreground.unwrap_or(Color::BLACK),
-        background: THEME.settings.background.unwrap_or(Color::WHITE),
-        font_style: FontStyle::empty(),
-    };
-
-    let mut highlight_lines = HighlightLines::new(syntax, &THEME);
+    changes: &[(ChangeTag, Bytes)],
+) -> Vec<(ChangeTag, HighlightedLine)> {
+    highlight_changes_with(&SYNTAX_SET, syntax, changes)
+}
+
+/// Apply syntax highlighting to a list of changes using a syntax of the given syntax set.
+///
+/// This allows highlighting with a freshly loaded syntax set, whose regular expressions are not
+/// compiled yet, to measure how long it takes to highlight the first file.
+pub fn highlight_changes_with(
+    syntax_set: &SyntaxSet,
+    syntax: &SyntaxReference,
+    changes: &[(ChangeTag, Bytes)],
+) -> Vec<(ChangeTag, HighlightedLine)> {
+    let mut parse_state = ParseState::new(syntax);
+    let mut scope_stack = ScopeStack::new();
     changes
         .iter()
         .map(|(tag, bytes)| {
-            let styled = highlight_bytes_line(&mut highlight_lines, *tag, bytes)
-                .unwrap_or_else(|| vec![(default_style, bytes.clone())]);
-            (*tag, styled)
+            let line =
+                highlight_or_escape(syntax_set, &mut parse_state, &mut scope_stack, *tag, bytes);
+            (*tag, line)
         })
         .collect()
 }
-
-/// Convert the given syntect style to inline `style` attribute formatting.
-///
-/// Does not apply background colors.
-pub fn syntect_style_to_css(style: &Style) -> String {
-    let mut css = format!(
-        "color:#{:02x}{:02x}{:02x};",
-        style.foreground.r, style.foreground.g, style.foreground.b
-    );
-    if style.font_style.contains(FontStyle::UNDERLINE) {
-        css.push_str("text-decoration:underline;");
-    }
-    if style.font_style.contains(FontStyle::BOLD) {
-        css.push_str("font-weight:bold;");
-    }
-    if style.font_style.contains(FontStyle::BOLD) {
-        css.push_str("font-style:italic;");
-    }
-    css
-}
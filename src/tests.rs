@@ -1,7 +1,23 @@
-use crate::data::*;
+use crate::{
+    blame::blame,
+    changelog::Changelog,
+    code_search::{self, SearchOptions, SearchSide},
+    components::{fuzzy_match, fuzzy_rank, render_markdown, split_rows, Shortcut},
+    data::*,
+    export::{self, FileStatus, Report},
+    lockfile::{self, LockChange},
+    patch::{self, PatchLine},
+    syntax::{
+        escape_html, highlight_changes, infer_syntax_for_file, theme_css, theme_names,
+        LazyHighlighter, DEFAULT_DARK_THEME, DEFAULT_LIGHT_THEME,
+    },
+    theme::{ColorScheme, ThemeSettings},
+    Route,
+};
 use anyhow::Result;
 use serde_json::from_reader;
-use std::fs::File;
+use similar::ChangeTag;
+use std::{fs::File, ops::Range};
 
 fn parse_canned_response(name: &str) -> Result<CrateResponse> {
     let response = File::open(format!("data/{name}.json"))?;
@@ -59,3 +75,1726 @@ fn can_parse_crate_source_log_0_4_17() {
     let version = log.version("0.4.17".parse().unwrap()).unwrap();
     let _ = parse_canned_source(version).unwrap();
 }
+
+fn parse_canned_diff(krate: &str, old: &str, new: &str) -> VersionDiff {
+    let response = parse_canned_response(krate).unwrap();
+    let old = response.version(old.parse().unwrap()).unwrap();
+    let new = response.version(new.parse().unwrap()).unwrap();
+    VersionDiff::new(
+        parse_canned_source(old).unwrap().into(),
+        parse_canned_source(new).unwrap().into(),
+    )
+}
+
+#[test]
+fn version_diff_summary_matches_file_diffs() {
+    let diff = parse_canned_diff("log", "0.4.15", "0.4.16");
+    let paths: Vec<_> = diff
+        .left
+        .files
+        .keys()
+        .chain(diff.right.files.keys())
+        .cloned()
+        .collect();
+    for path in paths {
+        let file = diff.file(&path).unwrap();
+        let summary = diff.summary[&path];
+        assert_eq!(
+            (file.summary.added as usize, file.summary.removed as usize),
+            summary,
+            "summary mismatch for {path}"
+        );
+    }
+}
+
+#[test]
+fn version_diff_tree_files_lists_changed_files() {
+    let diff = parse_canned_diff("log", "0.4.15", "0.4.16");
+    let changed = diff.tree_files(true);
+    assert!(!changed.is_empty());
+    for (path, changes) in &changed {
+        assert_ne!(*changes, Changes::default(), "{path} is unchanged");
+        assert_eq!(
+            diff.summary[path],
+            (changes.added as usize, changes.removed as usize)
+        );
+    }
+
+    // unchanged files are included when asked for
+    let all = diff.tree_files(false);
+    let files: std::collections::BTreeSet<_> = diff
+        .left
+        .files
+        .keys()
+        .chain(diff.right.files.keys())
+        .collect();
+    assert_eq!(all.len(), files.len());
+    assert!(changed.iter().all(|file| all.contains(file)));
+}
+
+#[test]
+fn version_diff_adjacent_file_steps_through_changed_files() {
+    let diff = parse_canned_diff("log", "0.4.15", "0.4.16");
+    let changed: Vec<_> = diff
+        .tree_files(true)
+        .into_iter()
+        .map(|(path, _)| path)
+        .collect();
+    assert!(changed.len() > 1);
+
+    // paths which are not files start at either end
+    assert_eq!(
+        diff.adjacent_file("".into(), true).as_ref(),
+        changed.first()
+    );
+    assert_eq!(
+        diff.adjacent_file("".into(), false).as_ref(),
+        changed.last()
+    );
+
+    for pair in changed.windows(2) {
+        assert_eq!(diff.adjacent_file(&pair[0], true).as_ref(), Some(&pair[1]));
+        assert_eq!(diff.adjacent_file(&pair[1], false).as_ref(), Some(&pair[0]));
+    }
+    assert_eq!(diff.adjacent_file(changed.last().unwrap(), true), None);
+    assert_eq!(diff.adjacent_file(changed.first().unwrap(), false), None);
+}
+
+#[test]
+fn version_diff_computes_files_lazily() {
+    let diff = parse_canned_diff("log", "0.4.16", "0.4.17");
+    assert!(diff.file("does/not/exist.rs".into()).is_none());
+    let first = diff.file("src/lib.rs".into()).unwrap();
+    let second = diff.file("src/lib.rs".into()).unwrap();
+    assert!(std::rc::Rc::ptr_eq(&first, &second));
+}
+
+#[test]
+fn file_diff_changes_counts_lines() {
+    assert_eq!(FileDiff::changes(b"a\nb\n", b"a\nb\n"), Changes::default());
+    assert_eq!(
+        FileDiff::changes(b"", b"a\nb"),
+        Changes {
+            added: 2,
+            removed: 0,
+            estimate: false
+        }
+    );
+    assert_eq!(
+        FileDiff::changes(b"a\nb\nc\n", b""),
+        Changes {
+            added: 0,
+            removed: 3,
+            estimate: false
+        }
+    );
+    assert_eq!(
+        FileDiff::changes(b"a\nb\nc\n", b"a\nx\nc\nd\n"),
+        Changes {
+            added: 2,
+            removed: 1,
+            estimate: false
+        }
+    );
+}
+
+#[test]
+fn diff_result_survives_worker_codec() {
+    use crate::worker::{DiffResponse, DiffResult};
+
+    let response = parse_canned_response("log").unwrap();
+    let old = parse_canned_source(response.version("0.4.16".parse().unwrap()).unwrap()).unwrap();
+    let new = parse_canned_source(response.version("0.4.17".parse().unwrap()).unwrap()).unwrap();
+    let limits = ArchiveLimits::DEFAULT;
+    let changes = VersionDiff::changes(&old, &new, &limits, |_, _| {});
+    let result = DiffResult {
+        old: Some(old),
+        new: None,
+        changes,
+        limits,
+    };
+
+    let encoded = bincode::serialize(&DiffResponse::Done(Ok(Box::new(result.clone())))).unwrap();
+    let DiffResponse::Done(Ok(decoded)) = bincode::deserialize(&encoded).unwrap() else {
+        panic!("decoded wrong response");
+    };
+    assert_eq!(decoded.old, result.old);
+    assert_eq!(decoded.new, result.new);
+    assert_eq!(decoded.changes, result.changes);
+}
+
+#[test]
+fn version_diff_reports_progress() {
+    let diff = parse_canned_diff("log", "0.4.15", "0.4.16");
+    let mut reports = vec![];
+    let changes = VersionDiff::changes(&diff.left, &diff.right, &diff.limits, |files, total| {
+        reports.push((files, total))
+    });
+    let total = changes.len();
+    assert_eq!(reports.last(), Some(&(total, total)));
+    assert_eq!(
+        VersionDiff::from_changes(diff.left.clone(), diff.right.clone(), changes, diff.limits)
+            .summary,
+        diff.summary
+    );
+}
+
+#[test]
+fn archive_decoder_decodes_chunks() {
+    let response = parse_canned_response("log").unwrap();
+    let version = response.version("0.4.17".parse().unwrap()).unwrap();
+    let data = std::fs::read("data/log-0.4.17.crate").unwrap();
+    let mut decoder = ArchiveDecoder::new();
+    for chunk in data.chunks(1000) {
+        decoder.write(chunk).unwrap();
+    }
+    assert_eq!(decoder.len(), data.len() as u64);
+    assert_eq!(version.crate_size, Some(data.len() as u64));
+
+    let mut unpacked = 0;
+    let source = decoder
+        .finish(version.clone(), |files| unpacked = files)
+        .unwrap();
+    assert_eq!(unpacked, source.files.len());
+    assert_eq!(source, parse_canned_source(version).unwrap());
+}
+
+#[test]
+fn archive_decoder_verifies_checksum() {
+    let response = parse_canned_response("log").unwrap();
+    let mut version = response.version("0.4.17".parse().unwrap()).unwrap().clone();
+    version.checksum[0] ^= 0xff;
+    let data = std::fs::read("data/log-0.4.17.crate").unwrap();
+    let mut decoder = ArchiveDecoder::new();
+    decoder.write(&data).unwrap();
+    assert!(matches!(
+        decoder.finish(version, |_| {}),
+        Err(CrateSourceError::HashsumMismatch { .. })
+    ));
+}
+
+fn decode_canned_with_limits(limits: ArchiveLimits) -> Result<CrateSource, CrateSourceError> {
+    let response = parse_canned_response("log").unwrap();
+    let version = response.version("0.4.17".parse().unwrap()).unwrap();
+    let data = std::fs::read("data/log-0.4.17.crate").unwrap();
+    let mut decoder = ArchiveDecoder::with_limits(limits);
+    decoder.write(&data)?;
+    decoder.finish(version.clone(), |_| {})
+}
+
+#[test]
+fn archive_limits_reject_large_archive() {
+    let result = decode_canned_with_limits(ArchiveLimits {
+        max_compressed_size: 1024,
+        ..ArchiveLimits::DEFAULT
+    });
+    assert!(matches!(
+        result,
+        Err(CrateSourceError::ArchiveTooLarge { limit: 1024 })
+    ));
+}
+
+#[test]
+fn archive_limits_reject_large_contents() {
+    let result = decode_canned_with_limits(ArchiveLimits {
+        max_uncompressed_size: 64 * 1024,
+        ..ArchiveLimits::DEFAULT
+    });
+    assert!(matches!(
+        result,
+        Err(CrateSourceError::ContentsTooLarge { .. })
+    ));
+}
+
+#[test]
+fn archive_limits_reject_many_files() {
+    let result = decode_canned_with_limits(ArchiveLimits {
+        max_files: 3,
+        ..ArchiveLimits::DEFAULT
+    });
+    assert!(matches!(
+        result,
+        Err(CrateSourceError::TooManyFiles { limit: 3 })
+    ));
+}
+
+#[test]
+fn archive_limits_reject_long_paths() {
+    let result = decode_canned_with_limits(ArchiveLimits {
+        max_path_length: 8,
+        ..ArchiveLimits::DEFAULT
+    });
+    assert!(matches!(
+        result,
+        Err(CrateSourceError::PathTooLong { limit: 8, .. })
+    ));
+}
+
+#[test]
+fn version_diff_skips_large_files() {
+    let diff = parse_canned_diff("log", "0.4.16", "0.4.17");
+    let limits = ArchiveLimits {
+        max_diff_size: 1024,
+        ..ArchiveLimits::DEFAULT
+    };
+    let changes = VersionDiff::changes(&diff.left, &diff.right, &limits, |_, _| {});
+    let limited = VersionDiff::from_changes(diff.left.clone(), diff.right.clone(), changes, limits);
+
+    let path = camino::Utf8Path::new("src/lib.rs");
+    assert!(!diff.is_too_large(path));
+    assert!(limited.is_too_large(path));
+
+    // every line of large files is counted as changed
+    let lines = |source: &CrateSource| {
+        let data = &source.files[path];
+        data.iter().filter(|byte| **byte == b'\n').count() + usize::from(!data.ends_with(b"\n"))
+    };
+    assert_ne!(diff.summary[path], limited.summary[path]);
+    assert_eq!(
+        limited.summary[path],
+        (lines(&diff.right), lines(&diff.left))
+    );
+
+    // the counts of large files and the folders containing them are marked as estimates
+    assert!(!diff.is_estimate(path));
+    for path in ["src/lib.rs", "src", ""] {
+        assert!(limited.is_estimate(path.into()), "{path}");
+    }
+    for (path, changes) in limited.tree_files(false) {
+        let estimate = limited.is_too_large(&path) && changes != Changes::default();
+        assert_eq!(limited.is_estimate(&path), estimate, "{path}");
+        assert_eq!(changes.estimate, estimate, "{path}");
+    }
+    assert!(limited.tree.changes.estimate);
+
+    // the file can still be diffed when asked
+    assert_eq!(limited.file(path), diff.file(path));
+}
+
+/// Build a crate archive with the given entries, bypassing the path checks of [`tar::Builder`].
+fn crafted_archive(entries: &[(&str, &[u8])]) -> (VersionInfo, Vec<u8>) {
+    use flate2::{write::GzEncoder, Compression};
+    use sha2::{Digest, Sha256};
+
+    let mut builder = tar::Builder::new(GzEncoder::new(vec![], Compression::default()));
+    for (path, data) in entries {
+        let mut header = tar::Header::new_gnu();
+        let name = &mut header.as_gnu_mut().unwrap().name;
+        name[..path.len()].copy_from_slice(path.as_bytes());
+        header.set_size(data.len() as u64);
+        header.set_entry_type(tar::EntryType::Regular);
+        header.set_cksum();
+        builder.append(&header, *data).unwrap();
+    }
+    let data = builder.into_inner().unwrap().finish().unwrap();
+
+    let version = VersionInfo {
+        checksum: Sha256::digest(&data).to_vec(),
+        dl_path: "/path".into(),
+        krate: "crafted".into(),
+        version: "0.1.0".parse().unwrap(),
+        yanked: false,
+        crate_size: Some(data.len() as u64),
+        created_at: None,
+    };
+    (version, data)
+}
+
+fn parse_crafted_archive(entries: &[(&str, &[u8])]) -> Result<CrateSource, CrateSourceError> {
+    let (version, data) = crafted_archive(entries);
+    CrateSource::new(version, &data)
+}
+
+#[test]
+fn crafted_archive_is_parsed() {
+    let source = parse_crafted_archive(&[
+        ("crafted-0.1.0/Cargo.toml", b"[package]"),
+        ("crafted-0.1.0/src/lib.rs", b"fn main() {}"),
+    ])
+    .unwrap();
+    assert_eq!(source.files.len(), 2);
+    assert!(source.warnings.is_empty());
+}
+
+#[test]
+fn crafted_archive_rejects_duplicate_paths() {
+    let result = parse_crafted_archive(&[
+        ("crafted-0.1.0/src/lib.rs", b"fn visible() {}"),
+        ("crafted-0.1.0/src/lib.rs", b"fn hidden() {}"),
+    ]);
+    assert!(matches!(
+        result,
+        Err(CrateSourceError::DuplicatePath { path }) if path == "src/lib.rs"
+    ));
+}
+
+#[test]
+fn crafted_archive_rejects_parent_components() {
+    let result = parse_crafted_archive(&[("crafted-0.1.0/../outside.rs", b"")]);
+    assert!(matches!(
+        result,
+        Err(CrateSourceError::InvalidPath { path }) if path == "../outside.rs"
+    ));
+}
+
+#[test]
+fn crafted_archive_rejects_absolute_paths() {
+    let result = parse_crafted_archive(&[("crafted-0.1.0//etc/passwd", b"")]);
+    assert!(matches!(
+        result,
+        Err(CrateSourceError::InvalidPath { path }) if path == "/etc/passwd"
+    ));
+}
+
+#[test]
+fn crafted_archive_rejects_current_dir_components() {
+    let result = parse_crafted_archive(&[("crafted-0.1.0/./src/lib.rs", b"")]);
+    assert!(matches!(result, Err(CrateSourceError::InvalidPath { .. })));
+}
+
+#[test]
+fn crafted_archive_rejects_mid_path_components() {
+    for path in [
+        "crafted-0.1.0/src/./lib.rs",
+        "crafted-0.1.0/src//lib.rs",
+        "crafted-0.1.0/src/../lib.rs",
+        "crafted-0.1.0/src/",
+    ] {
+        let result = parse_crafted_archive(&[(path, b"")]);
+        assert!(
+            matches!(result, Err(CrateSourceError::InvalidPath { .. })),
+            "{path}"
+        );
+    }
+}
+
+#[test]
+fn crafted_archive_rejects_file_and_directory_of_same_path() {
+    for paths in [
+        ["crafted-0.1.0/src/foo", "crafted-0.1.0/src/foo/bar.rs"],
+        ["crafted-0.1.0/src/foo/bar.rs", "crafted-0.1.0/src/foo"],
+    ] {
+        let result = parse_crafted_archive(&[(paths[0], b""), (paths[1], b"")]);
+        assert!(
+            matches!(result, Err(CrateSourceError::ConflictingPath { .. })),
+            "{paths:?}"
+        );
+    }
+
+    // paths which only share a prefix of their names do not conflict
+    parse_crafted_archive(&[
+        ("crafted-0.1.0/src/foo", b""),
+        ("crafted-0.1.0/src/foo.rs", b""),
+        ("crafted-0.1.0/src/foo-bar/lib.rs", b""),
+    ])
+    .unwrap();
+}
+
+#[test]
+fn version_diff_handles_file_replaced_by_directory() {
+    let file = parse_crafted_archive(&[("crafted-0.1.0/src/foo", b"file\n")]).unwrap();
+    let dir = parse_crafted_archive(&[("crafted-0.1.0/src/foo/bar.rs", b"dir\n")]).unwrap();
+    for (left, right) in [(&file, &dir), (&dir, &file)] {
+        let diff = VersionDiff::new(left.clone().into(), right.clone().into());
+        let paths: Vec<_> = diff
+            .tree_files(true)
+            .into_iter()
+            .map(|(path, _)| path.into_string())
+            .collect();
+        assert_eq!(paths, ["src/foo", "src/foo/bar.rs"]);
+        assert_eq!(diff.summary[camino::Utf8Path::new("src")], (1, 1));
+    }
+}
+
+#[test]
+fn crafted_archive_warns_about_case_collisions() {
+    let source = parse_crafted_archive(&[
+        ("crafted-0.1.0/src/Foo.rs", b"fn upper() {}"),
+        ("crafted-0.1.0/src/foo.rs", b"fn lower() {}"),
+        ("crafted-0.1.0/src/lib.rs", b""),
+    ])
+    .unwrap();
+    assert_eq!(source.files.len(), 3);
+    assert_eq!(
+        source.warnings,
+        vec![CrateSourceWarning::CaseCollision {
+            paths: vec!["src/Foo.rs".into(), "src/foo.rs".into()]
+        }]
+    );
+}
+
+fn decode_unverified(entries: &[(&str, &[u8])]) -> Result<CrateSource, CrateSourceError> {
+    let (_, data) = crafted_archive(entries);
+    let mut decoder = ArchiveDecoder::new();
+    decoder.write(&data)?;
+    decoder.finish_unverified()
+}
+
+#[test]
+fn unverified_archive_name_is_parsed() {
+    let source = decode_unverified(&[
+        ("my-crate-1.2.3-rc.1/Cargo.toml", b"[package]"),
+        ("my-crate-1.2.3-rc.1/src/lib.rs", b""),
+    ])
+    .unwrap();
+    assert_eq!(source.version.krate, "my-crate");
+    assert_eq!(source.version.version, "1.2.3-rc.1".parse().unwrap());
+    assert_eq!(source.files.len(), 2);
+    assert!(source.is_unverified());
+}
+
+#[test]
+fn unverified_archive_without_version_is_rejected() {
+    let result = decode_unverified(&[("crafted/Cargo.toml", b"[package]")]);
+    assert!(matches!(result, Err(CrateSourceError::UnknownCrate { .. })));
+}
+
+#[test]
+fn unverified_archive_rejects_other_prefixes() {
+    let result = decode_unverified(&[
+        ("crafted-0.1.0/Cargo.toml", b"[package]"),
+        ("other-0.1.0/src/lib.rs", b""),
+    ]);
+    assert!(matches!(
+        result,
+        Err(CrateSourceError::InvalidPrefix { .. })
+    ));
+}
+
+#[test]
+fn unverified_directory_reads_manifest() {
+    let manifest = "[package]\nname = \"crafted\"\nversion = \"0.2.0\"\n";
+    let source = CrateSource::from_directory_unverified(
+        vec![
+            ("Cargo.toml".into(), manifest.into()),
+            ("src/lib.rs".into(), "fn main() {}".into()),
+        ],
+        &ArchiveLimits::DEFAULT,
+    )
+    .unwrap();
+    assert_eq!(source.version.krate, "crafted");
+    assert_eq!(source.version.version, "0.2.0".parse().unwrap());
+    assert_eq!(source.warnings, vec![CrateSourceWarning::Unverified]);
+}
+
+#[test]
+fn unverified_directory_checksum_depends_on_contents() {
+    let manifest = "[package]\nname = \"crafted\"\nversion.workspace = true\n";
+    let source = |lib: &'static str| {
+        CrateSource::from_directory_unverified(
+            vec![
+                ("Cargo.toml".into(), manifest.into()),
+                ("src/lib.rs".into(), lib.into()),
+            ],
+            &ArchiveLimits::DEFAULT,
+        )
+        .unwrap()
+    };
+    let (first, second) = (source("fn first() {}"), source("fn second() {}"));
+    assert_eq!(first.version.version, semver::Version::new(0, 0, 0));
+    assert_ne!(first.version.checksum, second.version.checksum);
+}
+
+#[test]
+fn unverified_directory_requires_manifest() {
+    let result = CrateSource::from_directory_unverified(
+        vec![("src/lib.rs".into(), "fn main() {}".into())],
+        &ArchiveLimits::DEFAULT,
+    );
+    assert!(matches!(result, Err(CrateSourceError::UnknownCrate { .. })));
+}
+
+#[test]
+fn changelog_of_canned_crate_is_extracted() {
+    let diff = parse_canned_diff("log", "0.4.15", "0.4.17");
+    let (path, changelog) = Changelog::from_source(&diff.right).unwrap();
+    assert_eq!(path, "CHANGELOG.md");
+    let versions: Vec<_> = changelog
+        .between(&diff.left.version.version, &diff.right.version.version)
+        .iter()
+        .map(|entry| entry.version.to_string())
+        .collect();
+    assert_eq!(versions, ["0.4.17", "0.4.16"]);
+}
+
+#[test]
+fn changelog_entries_include_subsections() {
+    let changelog = Changelog::parse(
+        "# Changelog\n\n## Unreleased\n\n- next\n\n## [1.1.0] - 2024-02-01\n\n### Added\n\n- feature\n\n## v1.0.0\n\n- initial\n\n[1.1.0]: https://example.com/1.1.0\n",
+    );
+    assert_eq!(changelog.entries.len(), 2);
+    assert_eq!(
+        changelog.entries[0].markdown,
+        "## [1.1.0] - 2024-02-01\n\n### Added\n\n- feature\n\n"
+    );
+    assert_eq!(changelog.entries[1].version, semver::Version::new(1, 0, 0));
+    assert_eq!(changelog.links, ["[1.1.0]: https://example.com/1.1.0"]);
+}
+
+#[test]
+fn changelog_ignores_headings_in_code_blocks() {
+    let changelog = Changelog::parse("## 0.2\n\n```sh\n# 0.1.0\n```\n\n## 0.1.0\n");
+    let versions: Vec<_> = changelog
+        .entries
+        .iter()
+        .map(|entry| entry.version.to_string())
+        .collect();
+    assert_eq!(versions, ["0.2.0", "0.1.0"]);
+}
+
+#[test]
+fn rendered_markdown_is_sanitized() {
+    let html = render_markdown(
+        "# Title\n\n<script>alert(1)</script>\n\n<img src=\"logo.png\" onerror=\"alert(1)\">\n\n[link](javascript:alert(1))\n",
+    );
+    assert!(html.contains("<h1>Title</h1>"));
+    assert!(html.contains("logo.png"));
+    assert!(!html.contains("script"));
+    assert!(!html.contains("onerror"));
+    assert!(!html.contains("javascript:"));
+}
+
+#[test]
+fn version_info_has_publish_date() {
+    let log = parse_canned_response("log").unwrap();
+    let version = log.version("0.4.17".parse().unwrap()).unwrap();
+    assert_eq!(version.published(), Some("2022-05-02"));
+}
+
+#[test]
+fn blame_annotates_lines_with_introducing_version() {
+    let version = |version: &str| version.parse::<semver::Version>().unwrap();
+    let lines = blame(&[
+        (version("0.1.0"), Some("a\nb\n".into())),
+        (version("0.2.0"), Some("a\nc\nb\n".into())),
+        (version("0.3.0"), Some("a\nc\nd\n".into())),
+    ]);
+    let annotated: Vec<_> = lines
+        .iter()
+        .map(|line| {
+            (
+                std::str::from_utf8(&line.text).unwrap(),
+                line.version.to_string(),
+            )
+        })
+        .collect();
+    assert_eq!(
+        annotated,
+        [
+            ("a\n", "0.1.0".into()),
+            ("c\n", "0.2.0".into()),
+            ("d\n", "0.3.0".into())
+        ]
+    );
+}
+
+#[test]
+fn blame_restarts_when_file_is_removed() {
+    let version = |version: &str| version.parse::<semver::Version>().unwrap();
+    let lines = blame(&[
+        (version("0.1.0"), Some("a\n".into())),
+        (version("0.2.0"), None),
+        (version("0.3.0"), Some("a\n".into())),
+    ]);
+    assert_eq!(lines.len(), 1);
+    assert_eq!(lines[0].version, version("0.3.0"));
+}
+
+#[test]
+fn blame_of_canned_crate_spans_versions() {
+    let log = parse_canned_response("log").unwrap();
+    let contents: Vec<_> = ["0.4.15", "0.4.16", "0.4.17"]
+        .iter()
+        .map(|version| {
+            let info = log.version(version.parse().unwrap()).unwrap();
+            let source = parse_canned_source(info).unwrap();
+            let file = source
+                .files
+                .get(camino::Utf8Path::new("Cargo.toml"))
+                .cloned();
+            (info.version.clone(), file)
+        })
+        .collect();
+    let lines = blame(&contents);
+    assert_eq!(
+        lines.len(),
+        std::str::from_utf8(contents[2].1.as_ref().unwrap())
+            .unwrap()
+            .lines()
+            .count()
+    );
+    assert!(lines.iter().any(|line| line.version == contents[0].0));
+    assert!(lines.iter().any(|line| line.version == contents[2].0));
+}
+
+const LOCKFILE_OLD: &str = r#"
+version = 3
+
+[[package]]
+name = "app"
+version = "0.1.0"
+dependencies = [
+ "log",
+ "syn 1.0.109",
+ "syn 2.0.10",
+]
+
+[[package]]
+name = "log"
+version = "0.4.15"
+source = "registry+https://github.com/rust-lang/crates.io-index"
+
+[[package]]
+name = "syn"
+version = "1.0.109"
+source = "registry+https://github.com/rust-lang/crates.io-index"
+
+[[package]]
+name = "syn"
+version = "2.0.10"
+source = "registry+https://github.com/rust-lang/crates.io-index"
+
+[[package]]
+name = "winapi"
+version = "0.3.9"
+source = "registry+https://github.com/rust-lang/crates.io-index"
+"#;
+
+const LOCKFILE_NEW: &str = r#"
+version = 3
+
+[[package]]
+name = "app"
+version = "0.2.0"
+dependencies = [
+ "log",
+ "syn 1.0.109",
+ "syn 2.0.40",
+]
+
+[[package]]
+name = "log"
+version = "0.4.17"
+source = "registry+https://github.com/rust-lang/crates.io-index"
+
+[[package]]
+name = "syn"
+version = "1.0.109"
+source = "registry+https://github.com/rust-lang/crates.io-index"
+
+[[package]]
+name = "syn"
+version = "2.0.40"
+source = "registry+https://github.com/rust-lang/crates.io-index"
+
+[[package]]
+name = "windows-sys"
+version = "0.52.0"
+source = "sparse+https://index.crates.io/"
+"#;
+
+fn lock_change(name: &str, old: Option<&str>, new: Option<&str>, registry: bool) -> LockChange {
+    LockChange {
+        name: name.into(),
+        old: old.map(|version| version.parse().unwrap()),
+        new: new.map(|version| version.parse().unwrap()),
+        registry,
+    }
+}
+
+#[test]
+fn lockfile_changes_are_detected() {
+    let old = lockfile::parse(LOCKFILE_OLD).unwrap();
+    let new = lockfile::parse(LOCKFILE_NEW).unwrap();
+    assert_eq!(old.len(), 5);
+    assert!(!old[0].is_registry());
+    assert!(old[1].is_registry());
+    assert!(new[4].is_registry());
+    assert_eq!(
+        lockfile::changes(&old, &new),
+        [
+            lock_change("app", Some("0.1.0"), Some("0.2.0"), false),
+            lock_change("log", Some("0.4.15"), Some("0.4.17"), true),
+            lock_change("syn", Some("2.0.10"), Some("2.0.40"), true),
+            lock_change("winapi", Some("0.3.9"), None, true),
+            lock_change("windows-sys", None, Some("0.52.0"), true),
+        ]
+    );
+}
+
+#[test]
+fn lockfile_changes_pair_compatible_versions() {
+    let package = |version: &str| lockfile::LockPackage {
+        name: "rand".into(),
+        version: version.parse().unwrap(),
+        source: Some("registry+https://github.com/rust-lang/crates.io-index".into()),
+    };
+    let old = [package("0.7.3"), package("0.8.4")];
+    let new = [package("0.8.5"), package("0.9.0")];
+    assert_eq!(
+        lockfile::changes(&old, &new),
+        [
+            lock_change("rand", Some("0.8.4"), Some("0.8.5"), true),
+            lock_change("rand", Some("0.7.3"), Some("0.9.0"), true),
+        ]
+    );
+}
+
+#[test]
+fn lockfile_invalid_is_rejected() {
+    assert!(lockfile::parse("[[package]]\nname = 1").is_err());
+}
+
+#[test]
+fn lockfile_diff_is_parsed() {
+    let diff = r#"diff --git a/Cargo.lock b/Cargo.lock
+index 1234567..89abcde 100644
+--- a/Cargo.lock
++++ b/Cargo.lock
+@@ -10,9 +10,9 @@ dependencies = [
+ 
+ [[package]]
+ name = "log"
+-version = "0.4.15"
++version = "0.4.17"
+ source = "registry+https://github.com/rust-lang/crates.io-index"
+-checksum = "aaaa"
++checksum = "bbbb"
+ 
+ [[package]]
+ name = "syn"
+@@ -40,8 +40,3 @@ source = "registry+https://github.com/rust-lang/crates.io-index"
+ checksum = "cccc"
+-
+-[[package]]
+-name = "winapi"
+-version = "0.3.9"
+-source = "registry+https://github.com/rust-lang/crates.io-index"
+"#;
+    let (old, new) = lockfile::parse_diff(diff);
+    assert_eq!(old.len(), 2);
+    assert_eq!(new.len(), 1);
+    assert_eq!(
+        lockfile::changes(&old, &new),
+        [
+            lock_change("log", Some("0.4.15"), Some("0.4.17"), true),
+            lock_change("winapi", Some("0.3.9"), None, true),
+        ]
+    );
+}
+
+fn rust_changes() -> Vec<(ChangeTag, bytes::Bytes)> {
+    let source = "/// Doc comment\nfn main() {\n    let x = \"string\";\n}\n";
+    source
+        .split_inclusive('\n')
+        .enumerate()
+        .map(|(i, line)| {
+            let tag = [ChangeTag::Equal, ChangeTag::Delete, ChangeTag::Insert][i % 3];
+            (tag, bytes::Bytes::copy_from_slice(line.as_bytes()))
+        })
+        .collect()
+}
+
+#[test]
+fn lazy_highlighter_matches_full_highlighting() {
+    let syntax = infer_syntax_for_file("main.rs", []);
+    let changes = rust_changes();
+    let full = highlight_changes(syntax, &changes);
+    let highlighter = LazyHighlighter::new(syntax, changes.clone());
+    assert_eq!(highlighter.lines(2..4), full[2..4]);
+    assert_eq!(highlighter.lines(0..2), full[0..2]);
+    assert_eq!(highlighter.lines(0..100), full);
+}
+
+#[test]
+fn lazy_highlighter_highlights_on_demand() {
+    let syntax = infer_syntax_for_file("main.rs", []);
+    let highlighter = LazyHighlighter::new(syntax, rust_changes());
+    assert_eq!(highlighter.highlighted(), 0);
+    assert_eq!(highlighter.lines(1..2).len(), 1);
+    assert_eq!(highlighter.highlighted(), 2);
+    highlighter.lines(0..1);
+    assert_eq!(highlighter.highlighted(), 2);
+    assert!(highlighter.lines(10..20).is_empty());
+    assert_eq!(highlighter.highlighted(), 4);
+}
+
+#[test]
+fn highlighted_lines_are_self_contained() {
+    let syntax = infer_syntax_for_file("main.rs", []);
+    let changes: Vec<_> = ["/* block\n", "comment */\n", "fn main() {}\n", "x < y\n"]
+        .into_iter()
+        .enumerate()
+        .map(|(i, line)| {
+            let tag = [
+                ChangeTag::Insert,
+                ChangeTag::Equal,
+                ChangeTag::Equal,
+                ChangeTag::Delete,
+            ][i];
+            (tag, bytes::Bytes::from_static(line.as_bytes()))
+        })
+        .collect();
+    let lines = highlight_changes(syntax, &changes);
+    for (_, line) in &lines {
+        assert_eq!(
+            line.matches("<span").count(),
+            line.matches("</span>").count()
+        );
+    }
+    // the comment spans are opened again on the second line
+    assert!(lines[1].1.starts_with("<span class=\"hl-source hl-rust\">"));
+    assert!(lines[1].1.contains("hl-comment hl-block"));
+    assert!(lines[2].1.contains("hl-storage hl-type hl-function"));
+    // removed lines are not highlighted, only escaped
+    assert_eq!(&*lines[3].1, "x &lt; y\n");
+}
+
+#[test]
+fn syntax_inferred_from_file_names() {
+    let cases = [
+        ("Cargo.toml", "TOML"),
+        ("Cargo.lock", "TOML"),
+        ("src/lib.rs", "Rust"),
+        ("proto/api.proto", "Protocol Buffers"),
+        ("shaders/blit.wgsl", "WGSL"),
+        ("src/grammar.pest", "Pest"),
+        ("src/parser.lalrpop", "LALRPOP"),
+        ("Dockerfile", "Dockerfile"),
+        ("Containerfile", "Dockerfile"),
+        ("Justfile", "Just"),
+        ("justfile", "Just"),
+        ("flake.nix", "Nix"),
+        ("kernels/add.cu", "CUDA C++"),
+        ("README.md", "Markdown"),
+        ("docs/intro.mdx", "MDX"),
+        ("LICENSE", "Plain Text"),
+    ];
+    for (path, name) in cases {
+        assert_eq!(infer_syntax_for_file(path, []).name, name, "{path}");
+    }
+}
+
+#[test]
+fn syntax_overridden_by_modeline_and_shebang() {
+    let infer = |path, contents: &str| infer_syntax_for_file(path, contents.lines()).name.clone();
+    assert_eq!(
+        infer("bin/release", "#!/usr/bin/env python3\nprint()"),
+        "Python"
+    );
+    assert_eq!(
+        infer("run.txt", "#!/bin/bash\necho"),
+        "Bourne Again Shell (bash)"
+    );
+    assert_eq!(infer("src/lib.rs", "#![no_std]\nfn main() {}"), "Rust");
+    assert_eq!(
+        infer("config", "[section]\nkey = 1\n# vim: set ft=toml:"),
+        "TOML"
+    );
+    assert_eq!(
+        infer("build.rs", "# -*- mode: python -*-\nprint()"),
+        "Python"
+    );
+    assert_eq!(
+        infer("Dockerfile.dev", "# syntax=docker/dockerfile:1\nFROM rust"),
+        "Dockerfile"
+    );
+}
+
+#[test]
+fn extended_syntaxes_highlight() {
+    let cases = [
+        (
+            "Cargo.toml",
+            "[package]\nname = \"diff-rs\" # name\nversion = { workspace = true }\n",
+            "hl-entity hl-name hl-section",
+        ),
+        (
+            "api.proto",
+            "syntax = \"proto3\";\nmessage Request {\n  repeated string names = 1;\n}\n",
+            "hl-entity hl-name hl-type",
+        ),
+        (
+            "blit.wgsl",
+            "@vertex\nfn main(@builtin(vertex_index) index: u32) -> vec4f {}\n",
+            "hl-entity hl-name hl-function",
+        ),
+        (
+            "grammar.pest",
+            "ident = @{ ASCII_ALPHA ~ (\"_\" | ASCII_DIGIT)* }\n",
+            "hl-entity hl-name hl-function",
+        ),
+        (
+            "parser.lalrpop",
+            "grammar;\npub Num: i32 = <s:r\"[0-9]+\"> => { i32::from_str(s).unwrap() };\n",
+            "hl-string hl-regexp",
+        ),
+        (
+            "Dockerfile",
+            "FROM rust:1 AS build\nRUN cargo build --release && \\\n    cp $HOME/x /\n",
+            "hl-keyword hl-other",
+        ),
+        (
+            "justfile",
+            "# Build\nbuild target=\"release\":\n    cargo build --{{target}}\n",
+            "hl-entity hl-name hl-function",
+        ),
+        (
+            "flake.nix",
+            "{ pkgs, ... }:\nlet name = \"x-${pkgs.system}\"; in ''\n  ${name}\n''\n",
+            "hl-meta hl-interpolation",
+        ),
+        (
+            "add.cu",
+            "__global__ void add(int *x) {\n  x[threadIdx.x] += 1;\n}\n",
+            "hl-storage hl-modifier hl-cuda",
+        ),
+        (
+            "intro.mdx",
+            "import { Chart } from './chart.js'\n\n# Title\n",
+            "hl-source hl-js",
+        ),
+    ];
+    for (path, source, class) in cases {
+        let changes: Vec<_> = source
+            .split_inclusive('\n')
+            .map(|line| {
+                (
+                    ChangeTag::Insert,
+                    bytes::Bytes::copy_from_slice(line.as_bytes()),
+                )
+            })
+            .collect();
+        let lines = highlight_changes(infer_syntax_for_file(path, source.lines()), &changes);
+        let html: String = lines.iter().map(|(_, line)| &**line).collect();
+        assert!(html.contains(class), "{path}: {html}");
+        assert_eq!(
+            html.matches("<span").count(),
+            html.matches("</span>").count(),
+            "{path}"
+        );
+    }
+}
+
+#[test]
+fn theme_css_exists_for_every_theme() {
+    assert!(theme_names().any(|name| name == DEFAULT_LIGHT_THEME));
+    assert!(theme_names().any(|name| name == DEFAULT_DARK_THEME));
+    for name in theme_names() {
+        let css = theme_css(name).unwrap();
+        // the background of the theme is applied to the code containers
+        assert!(css.contains(".hl-code {"), "{name}");
+    }
+    assert_eq!(theme_css("missing"), None);
+    assert_eq!(
+        escape_html("<a href=\"&\">"),
+        "&lt;a href=&quot;&amp;&quot;&gt;"
+    );
+}
+
+#[test]
+fn color_scheme_settings() {
+    for scheme in ColorScheme::ALL {
+        assert_eq!(ColorScheme::from_key(scheme.key()), Some(scheme));
+    }
+    assert_eq!(ColorScheme::from_key("sepia"), None);
+    assert_eq!(
+        ColorScheme::System.next().next().next(),
+        ColorScheme::System
+    );
+    assert!(ColorScheme::System.is_dark(true));
+    assert!(!ColorScheme::Light.is_dark(true));
+    assert!(ColorScheme::Dark.is_dark(false));
+    let settings = ThemeSettings::default();
+    assert_eq!(settings.syntax_theme(false), DEFAULT_LIGHT_THEME);
+    assert_eq!(settings.syntax_theme(true), DEFAULT_DARK_THEME);
+}
+
+fn crafted_diff(old: &[(&str, &[u8])], new: &[(&str, &[u8])]) -> VersionDiff {
+    VersionDiff::new(
+        parse_crafted_archive(old).unwrap().into(),
+        parse_crafted_archive(new).unwrap().into(),
+    )
+}
+
+#[test]
+fn export_patch_matches_git() {
+    let diff = crafted_diff(
+        &[
+            ("crafted-0.1.0/Cargo.toml", b"[package]\n"),
+            ("crafted-0.1.0/src/lib.rs", b"a\nb\nc\n"),
+            ("crafted-0.1.0/src/old.rs", b"old\n"),
+        ],
+        &[
+            ("crafted-0.1.0/Cargo.toml", b"[package]\n"),
+            ("crafted-0.1.0/src/lib.rs", b"a\nB\nc"),
+            ("crafted-0.1.0/README.md", b"hello\n"),
+        ],
+    );
+    let files = export::changed_files(&diff, "".into());
+    assert_eq!(files.len(), 3);
+    assert!(!files.contains(&"Cargo.toml".into()));
+    assert_eq!(export::changed_files(&diff, "src".into()).len(), 2);
+
+    assert_eq!(
+        export::patch(&diff, &["src/lib.rs".into()]),
+        "diff --git a/src/lib.rs b/src/lib.rs\n\
+         --- a/src/lib.rs\n\
+         +++ b/src/lib.rs\n\
+         @@ -1,3 +1,3 @@\n \
+         a\n\
+         -b\n\
+         -c\n\
+         +B\n\
+         +c\n\
+         \\ No newline at end of file\n"
+    );
+    assert_eq!(
+        export::patch(&diff, &["README.md".into(), "src/old.rs".into()]),
+        "diff --git a/README.md b/README.md\n\
+         new file mode 100644\n\
+         --- /dev/null\n\
+         +++ b/README.md\n\
+         @@ -0,0 +1 @@\n\
+         +hello\n\
+         diff --git a/src/old.rs b/src/old.rs\n\
+         deleted file mode 100644\n\
+         --- a/src/old.rs\n\
+         +++ /dev/null\n\
+         @@ -1 +0,0 @@\n\
+         -old\n"
+    );
+}
+
+#[test]
+fn export_patch_skips_binary_files() {
+    let diff = crafted_diff(
+        &[("crafted-0.1.0/image.png", b"\x89PNG\0\x01")],
+        &[("crafted-0.1.0/image.png", b"\x89PNG\0\x02")],
+    );
+    assert_eq!(
+        export::patch(&diff, &["image.png".into()]),
+        "diff --git a/image.png b/image.png\n\
+         Binary files a/image.png and b/image.png differ\n"
+    );
+    let report = Report::new(&diff, &["image.png".into()]);
+    assert!(report.files[0].binary);
+    assert!(report.files[0].hunks.is_empty());
+}
+
+#[test]
+fn export_leaves_out_files_too_large_to_diff() {
+    let diff = crafted_diff(
+        &[
+            ("crafted-0.1.0/src/large.rs", b"a\nb\nc\nd\n"),
+            ("crafted-0.1.0/src/lib.rs", b"a\n"),
+        ],
+        &[
+            ("crafted-0.1.0/src/large.rs", b"a\nB\nc\nd\ne\n"),
+            ("crafted-0.1.0/src/lib.rs", b"b\n"),
+        ],
+    );
+    let diff = with_max_diff_size(&diff, 4);
+    let files = ["src/large.rs".into(), "src/lib.rs".into()];
+    assert_eq!(
+        export::patch(&diff, &files),
+        "File src/large.rs is too large to diff, its changes are left out.\n\
+         diff --git a/src/lib.rs b/src/lib.rs\n\
+         --- a/src/lib.rs\n\
+         +++ b/src/lib.rs\n\
+         @@ -1 +1 @@\n\
+         -a\n\
+         +b\n"
+    );
+
+    let report = Report::new(&diff, &files);
+    assert!(report.files[0].too_large);
+    assert!(report.files[0].hunks.is_empty());
+    assert_eq!((report.files[0].added, report.files[0].removed), (5, 4));
+    assert!(!report.files[1].too_large);
+    assert_eq!(report.files[1].hunks.len(), 1);
+
+    let html = export::html(&diff, &files);
+    assert!(html.contains("File too large to diff"));
+}
+
+#[test]
+fn export_report_hunks_reconstruct_new_version() {
+    let diff = parse_canned_diff("log", "0.4.15", "0.4.16");
+    let files = export::changed_files(&diff, "".into());
+    let report = Report::new(&diff, &files);
+    assert_eq!(report.files.len(), files.len());
+    assert_eq!(report.old.version, "0.4.15");
+    assert_eq!(
+        report.added,
+        report.files.iter().map(|file| file.added).sum::<u64>()
+    );
+
+    for file in report.files.iter().filter(|file| !file.binary) {
+        let text = |source: &CrateSource| {
+            source
+                .files
+                .get(&file.path)
+                .map(|data| String::from_utf8(data.to_vec()).unwrap())
+                .unwrap_or_default()
+        };
+        let (old, new) = (text(&diff.left), text(&diff.right));
+        let old: Vec<&str> = old.lines().collect();
+
+        // apply the hunks to the old version
+        let mut lines = vec![];
+        let mut cursor = 0;
+        for hunk in &file.hunks {
+            let start = hunk.old_start - usize::from(hunk.old_lines > 0);
+            lines.extend_from_slice(&old[cursor..start]);
+            cursor = start;
+            for line in &hunk.lines {
+                let (prefix, line) = line.split_at(1);
+                match prefix {
+                    " " => {
+                        assert_eq!(old[cursor], line, "context mismatch in {}", file.path);
+                        lines.push(line);
+                        cursor += 1;
+                    }
+                    "-" => cursor += 1,
+                    _ => lines.push(line),
+                }
+            }
+        }
+        lines.extend_from_slice(&old[cursor..]);
+        assert_eq!(lines, new.lines().collect::<Vec<_>>(), "{}", file.path);
+        if file.status == FileStatus::Added {
+            assert_eq!(file.removed, 0);
+        }
+    }
+
+    let json: serde_json::Value = serde_json::from_str(&export::json(&diff, &files)).unwrap();
+    assert_eq!(json["files"].as_array().unwrap().len(), files.len());
+    assert_eq!(json["old"]["crate"], "log");
+}
+
+#[test]
+fn export_html_is_escaped() {
+    let diff = crafted_diff(
+        &[("crafted-0.1.0/src/lib.rs", b"fn main() {}\n")],
+        &[(
+            "crafted-0.1.0/src/lib.rs",
+            b"fn main() { let x = \"<script>\"; }\n",
+        )],
+    );
+    let html = export::html(&diff, &export::changed_files(&diff, "".into()));
+    assert!(html.starts_with("<!DOCTYPE html>"));
+    assert!(html.contains("id=\"file-src/lib.rs\""));
+    assert!(html.contains("&lt;script&gt;"));
+    assert!(!html.contains("<script>"));
+}
+
+#[test]
+fn patch_parses_git_diff() {
+    let text = "\
+From 1234 Mon Sep 17 00:00:00 2001
+Subject: [PATCH] Example
+
+diff --git a/src/lib.rs b/src/lib.rs
+index 1111111..2222222 100644
+--- a/src/lib.rs
++++ b/src/lib.rs
+@@ -2,3 +2,3 @@ fn main() {
+ a
+-b
++B
+
+@@ -10 +10,2 @@
+ c
++d
+\\ No newline at end of file
+diff --git a/README.md b/README.md
+new file mode 100644
+--- /dev/null
++++ b/README.md
+@@ -0,0 +1 @@
++hello
+diff --git a/old.rs b/new.rs
+similarity index 100%
+rename from old.rs
+rename to new.rs
+diff --git a/image.png b/image.png
+Binary files a/image.png and b/image.png differ
+";
+    let files = patch::parse(text).unwrap();
+    assert_eq!(files.len(), 4);
+
+    let lib = &files[0];
+    assert_eq!(lib.old_path, Some("src/lib.rs".into()));
+    assert_eq!(lib.new_path, Some("src/lib.rs".into()));
+    assert_eq!(lib.hunks.len(), 2);
+    assert_eq!(
+        lib.hunks[0].lines,
+        [
+            PatchLine::Context("a".into()),
+            PatchLine::Removed("b".into()),
+            PatchLine::Added("B".into()),
+            PatchLine::Context("".into()),
+        ]
+    );
+    assert!(lib.hunks[1].new_no_newline);
+    assert!(!lib.hunks[1].old_no_newline);
+
+    assert_eq!(files[1].old_path, None);
+    assert_eq!(files[1].new_path, Some("README.md".into()));
+    assert_eq!(files[2].old_path, Some("old.rs".into()));
+    assert_eq!(files[2].new_path, Some("new.rs".into()));
+    assert!(files[2].hunks.is_empty());
+    assert!(files[3].binary);
+
+    // lines between hunks are blank, and keep their line numbers
+    let diff = patch::version_diff(&files).unwrap();
+    assert_eq!(
+        &diff.left.files[camino::Utf8Path::new("src/lib.rs")][..],
+        b"\na\nb\n\n\n\n\n\n\nc\n"
+    );
+    assert_eq!(
+        &diff.right.files[camino::Utf8Path::new("src/lib.rs")][..],
+        b"\na\nB\n\n\n\n\n\n\nc\nd"
+    );
+    assert_eq!(diff.summary[camino::Utf8Path::new("src/lib.rs")], (2, 1));
+    assert_eq!(diff.summary[camino::Utf8Path::new("README.md")], (1, 0));
+    assert!(!diff
+        .right
+        .files
+        .contains_key(camino::Utf8Path::new("image.png")));
+    assert!(diff.right.is_unverified());
+}
+
+#[test]
+fn patch_parses_plain_unified_diff() {
+    let text = "\
+diff -ru old/src/lib.rs new/src/lib.rs
+--- old/src/lib.rs\t2024-01-01 00:00:00.000000000 +0000
++++ new/src/lib.rs\t2024-01-02 00:00:00.000000000 +0000
+@@ -1 +1 @@
+--- a
++-- b
+--- old/Cargo.toml\t2024-01-01 00:00:00.000000000 +0000
++++ new/Cargo.toml\t2024-01-02 00:00:00.000000000 +0000
+@@ -1,2 +1,2 @@
+ [package]
+-name = \"a\"
++name = \"b\"
+";
+    let files = patch::parse(text).unwrap();
+    assert_eq!(files.len(), 2);
+    assert_eq!(files[0].old_path, Some("src/lib.rs".into()));
+    assert_eq!(files[0].new_path, Some("src/lib.rs".into()));
+    assert_eq!(
+        files[0].hunks[0].lines,
+        [
+            PatchLine::Removed("-- a".into()),
+            PatchLine::Added("-- b".into())
+        ]
+    );
+    assert_eq!(files[1].path(), Some("Cargo.toml".into()));
+    assert_eq!(files[1].hunks[0].lines.len(), 3);
+}
+
+#[test]
+fn patch_keeps_paths_which_differ_beyond_the_first_component() {
+    let text = "\
+--- old/src/lib.rs
++++ new/src/main.rs
+@@ -1 +1 @@
+-a
++b
+--- src/lib.rs
++++ src/lib.rs
+@@ -1 +1 @@
+-a
++b
+";
+    let files = patch::parse(text).unwrap();
+    assert_eq!(files[0].old_path, Some("old/src/lib.rs".into()));
+    assert_eq!(files[0].new_path, Some("new/src/main.rs".into()));
+    assert_eq!(files[1].path(), Some("src/lib.rs".into()));
+}
+
+#[test]
+fn patch_removes_leading_current_directory() {
+    let text = "\
+--- ./old/lib.rs
++++ ./new/lib.rs
+@@ -1 +1 @@
+-a
++b
+";
+    let files = patch::parse(text).unwrap();
+    assert_eq!(files[0].path(), Some("lib.rs".into()));
+    let diff = patch::version_diff(&files).unwrap();
+    assert_eq!(diff.summary[camino::Utf8Path::new("lib.rs")], (1, 1));
+}
+
+#[test]
+fn patch_rejects_invalid_paths() {
+    let file = |path: &str| format!("--- {path}\n+++ {path}\n@@ -1 +1 @@\n-a\n+b\n");
+    for text in [
+        file("../lib.rs"),
+        file("src/../../lib.rs"),
+        file("src//lib.rs"),
+        file("src/./lib.rs"),
+        file("src/foo") + &file("src/foo/bar.rs"),
+    ] {
+        let files = patch::parse(&text).unwrap();
+        assert!(patch::version_diff(&files).is_err(), "{text}");
+    }
+}
+
+#[test]
+fn patch_rejects_invalid_input() {
+    assert!(patch::parse("").is_err());
+    assert!(patch::parse("just some text").is_err());
+    assert!(patch::parse("--- a/x\n+++ b/x\n@@ -1,2 +1,2 @@\n a\n").is_err());
+    assert!(patch::parse("--- a/x\n+++ b/x\n@@ -1 +1 @@\n*a\n").is_err());
+    assert!(patch::parse("--- a/x\n+++ b/x\n@@ -a +1 @@\n").is_err());
+}
+
+#[test]
+fn patch_round_trips_exported_patch() {
+    let diff = parse_canned_diff("log", "0.4.15", "0.4.16");
+    let files = export::changed_files(&diff, "".into());
+    let imported =
+        patch::version_diff(&patch::parse(&export::patch(&diff, &files)).unwrap()).unwrap();
+    for path in &files {
+        assert_eq!(imported.summary[path], diff.summary[path], "{path}");
+        let imported = imported.file(path).unwrap();
+        let original = diff.file(path).unwrap();
+        assert_eq!(imported.summary, original.summary, "{path}");
+    }
+}
+
+#[test]
+fn crate_response_adjacent_version_skips_yanked() {
+    let mut response = parse_canned_response("log").unwrap();
+    let mut versions: Vec<_> = response
+        .versions
+        .iter()
+        .map(|v| v.version.clone())
+        .collect();
+    versions.sort();
+    assert!(versions.len() > 3);
+
+    let adjacent = |response: &CrateResponse, version, forward| {
+        response
+            .adjacent_version(version, forward)
+            .map(|info| info.version.clone())
+    };
+    assert_eq!(
+        adjacent(&response, &versions[1], true),
+        Some(versions[2].clone())
+    );
+    assert_eq!(
+        adjacent(&response, &versions[1], false),
+        Some(versions[0].clone())
+    );
+    assert_eq!(adjacent(&response, versions.last().unwrap(), true), None);
+    assert_eq!(adjacent(&response, &versions[0], false), None);
+
+    for info in &mut response.versions {
+        info.yanked = info.version == versions[2];
+    }
+    assert_eq!(
+        adjacent(&response, &versions[1], true),
+        Some(versions[3].clone())
+    );
+    assert_eq!(
+        adjacent(&response, &versions[3], false),
+        Some(versions[1].clone())
+    );
+}
+
+#[test]
+fn shortcut_keys_are_unique() {
+    for shortcut in Shortcut::ALL {
+        assert_eq!(Shortcut::from_key(shortcut.key()), Some(shortcut));
+    }
+    assert_eq!(Shortcut::from_key("x"), None);
+}
+
+#[test]
+fn split_rows_pair_removed_and_added_lines() {
+    use ChangeTag::*;
+    let group = [
+        (Equal, "a"),
+        (Delete, "b"),
+        (Delete, "c"),
+        (Insert, "B"),
+        (Equal, "d"),
+        (Insert, "e"),
+    ];
+    let rows: Vec<_> = split_rows(&group, (10, 20))
+        .into_iter()
+        .map(|(left, right)| {
+            (
+                left.map(|(line, _, text)| (line, *text)),
+                right.map(|(line, _, text)| (line, *text)),
+            )
+        })
+        .collect();
+    assert_eq!(
+        rows,
+        [
+            (Some((10, "a")), Some((20, "a"))),
+            (Some((11, "b")), Some((21, "B"))),
+            (Some((12, "c")), None),
+            (Some((13, "d")), Some((22, "d"))),
+            (None, Some((23, "e"))),
+        ]
+    );
+}
+
+#[test]
+fn fuzzy_match_requires_characters_in_order() {
+    assert_eq!(
+        fuzzy_match("", "src/lib.rs").unwrap().positions,
+        Vec::<usize>::new()
+    );
+    assert_eq!(
+        fuzzy_match("LIB", "src/lib.rs").unwrap().positions,
+        [4, 5, 6]
+    );
+    assert_eq!(fuzzy_match("s l", "src/lib.rs").unwrap().positions, [0, 4]);
+    assert_eq!(fuzzy_match("bil", "src/lib.rs"), None);
+    assert_eq!(fuzzy_match("src/lib.rs/", "src/lib.rs"), None);
+}
+
+#[test]
+fn fuzzy_match_prefers_word_boundaries() {
+    // snake_case boundaries
+    assert_eq!(
+        fuzzy_match("ft", "src/file_tree.rs").unwrap().positions,
+        [4, 9]
+    );
+    // camelCase boundaries
+    assert_eq!(
+        fuzzy_match("fb", "src/FooBar.rs").unwrap().positions,
+        [4, 7]
+    );
+    // path segments
+    assert_eq!(
+        fuzzy_match("sm", "src/sema/mod.rs").unwrap().positions,
+        [4, 9]
+    );
+
+    let rank = |query, paths: &[&'static str]| -> Vec<&'static str> {
+        fuzzy_rank(query, paths.iter().copied())
+            .into_iter()
+            .map(|(index, _)| paths[index])
+            .collect()
+    };
+    assert_eq!(
+        rank("ft", &["src/fast.rs", "src/file_tree.rs", "README.md"]),
+        ["src/file_tree.rs", "src/fast.rs"]
+    );
+    assert_eq!(
+        rank("fb", &["src/fabric.rs", "src/FooBar.rs"]),
+        ["src/FooBar.rs", "src/fabric.rs"]
+    );
+    assert_eq!(
+        rank("kv", &["src/bookview.rs", "src/kv/mod.rs"]),
+        ["src/kv/mod.rs", "src/bookview.rs"]
+    );
+    // file names are preferred over directories, and shorter paths over longer ones
+    assert_eq!(
+        rank("lib", &["lib/mod.rs", "src/lib.rs", "src/lib/mod.rs"]),
+        ["src/lib.rs", "lib/mod.rs", "src/lib/mod.rs"]
+    );
+}
+
+fn search_diff() -> VersionDiff {
+    crafted_diff(
+        &[
+            ("crafted-0.1.0/Cargo.toml", b"[package]\n"),
+            (
+                "crafted-0.1.0/src/lib.rs",
+                b"fn safe() {}\nunsafe fn old() {}\nfn keep() {}\n",
+            ),
+            (
+                "crafted-0.1.0/src/removed.rs",
+                b"unsafe impl Send for Old {}\n",
+            ),
+            ("crafted-0.1.0/src/same.rs", b"// unsafe\n"),
+            ("crafted-0.1.0/image.png", b"unsafe\0"),
+        ],
+        &[
+            ("crafted-0.1.0/Cargo.toml", b"[package]\n"),
+            (
+                "crafted-0.1.0/src/lib.rs",
+                b"fn safe() {}\nfn keep() {}\nUNSAFE fn new() {}\n",
+            ),
+            ("crafted-0.1.0/src/added.rs", b"unsafe {}\n"),
+            ("crafted-0.1.0/src/same.rs", b"// unsafe\n"),
+            ("crafted-0.1.0/image.png", b"unsafe\0"),
+        ],
+    )
+}
+
+/// Diff the sources of a diff again with a lower limit for the size of files which are diffed.
+fn with_max_diff_size(diff: &VersionDiff, max_diff_size: u64) -> VersionDiff {
+    let limits = ArchiveLimits {
+        max_diff_size,
+        ..ArchiveLimits::DEFAULT
+    };
+    let changes = VersionDiff::changes(&diff.left, &diff.right, &limits, |_, _| {});
+    VersionDiff::from_changes(diff.left.clone(), diff.right.clone(), changes, limits)
+}
+
+/// Search the crafted diff, returning the path, anchor and text of every matching line.
+fn search_lines(options: SearchOptions) -> Vec<(String, String, String)> {
+    code_search::search(&search_diff(), &options)
+        .unwrap()
+        .files
+        .into_iter()
+        .flat_map(|file| {
+            file.lines
+                .into_iter()
+                .map(move |line| (file.path.to_string(), line.anchor(), line.text))
+        })
+        .collect()
+}
+
+#[test]
+fn code_search_finds_lines_of_both_versions() {
+    let options = SearchOptions {
+        query: "unsafe".into(),
+        ..Default::default()
+    };
+    let lines = search_lines(options.clone());
+    let found: Vec<_> = lines
+        .iter()
+        .map(|(path, anchor, _)| (path.as_str(), anchor.as_str()))
+        .collect();
+    // binary files are skipped, and matches are case insensitive
+    assert_eq!(
+        found,
+        [
+            ("src/added.rs", "R1"),
+            ("src/lib.rs", "L2"),
+            ("src/lib.rs", "R3"),
+            ("src/removed.rs", "L1"),
+            ("src/same.rs", "R1"),
+        ]
+    );
+
+    let case_sensitive = search_lines(SearchOptions {
+        case_sensitive: true,
+        ..options.clone()
+    });
+    assert!(case_sensitive
+        .iter()
+        .all(|(_, _, text)| text.contains("unsafe")));
+    assert_eq!(case_sensitive.len(), 4);
+}
+
+#[test]
+fn code_search_restricts_lines() {
+    let options = SearchOptions {
+        query: "unsafe".into(),
+        changed_only: true,
+        ..Default::default()
+    };
+    let anchors = |options| -> Vec<String> {
+        search_lines(options)
+            .into_iter()
+            .map(|(path, anchor, _)| format!("{path}#{anchor}"))
+            .collect()
+    };
+    assert_eq!(
+        anchors(options.clone()),
+        [
+            "src/added.rs#R1",
+            "src/lib.rs#L2",
+            "src/lib.rs#R3",
+            "src/removed.rs#L1"
+        ]
+    );
+    assert_eq!(
+        anchors(SearchOptions {
+            side: SearchSide::Old,
+            ..options.clone()
+        }),
+        ["src/lib.rs#L2", "src/removed.rs#L1"]
+    );
+    assert_eq!(
+        anchors(SearchOptions {
+            side: SearchSide::New,
+            ..options.clone()
+        }),
+        ["src/added.rs#R1", "src/lib.rs#R3"]
+    );
+}
+
+#[test]
+fn code_search_skips_files_too_large_to_diff() {
+    let diff = with_max_diff_size(&search_diff(), 16);
+    let options = SearchOptions {
+        query: "unsafe".into(),
+        ..Default::default()
+    };
+    let results = code_search::search(&diff, &options).unwrap();
+    assert_eq!(results.too_large, ["src/lib.rs"]);
+    assert!(results.files.iter().all(|file| file.path != "src/lib.rs"));
+    assert!(results.files.iter().any(|file| file.path == "src/added.rs"));
+}
+
+#[test]
+fn code_search_supports_regex() {
+    let options = SearchOptions {
+        query: r"fn \w+\(".into(),
+        regex: true,
+        ..Default::default()
+    };
+    let results = code_search::search(&search_diff(), &options).unwrap();
+    let lib = &results.files[0];
+    assert_eq!(lib.path, "src/lib.rs");
+    assert_eq!(lib.lines[0].text, "fn safe() {}");
+    assert_eq!(lib.lines[0].ranges, vec![Range { start: 0, end: 8 }]);
+    assert_eq!(lib.lines[0].old, Some(1));
+    assert_eq!(lib.lines[0].new, Some(1));
+
+    // plain text queries are not regular expressions
+    let plain = SearchOptions {
+        regex: false,
+        ..options.clone()
+    };
+    assert!(code_search::search(&search_diff(), &plain)
+        .unwrap()
+        .files
+        .is_empty());
+
+    let invalid = SearchOptions {
+        query: "(".into(),
+        ..options
+    };
+    assert!(code_search::search(&search_diff(), &invalid).is_err());
+}
+
+#[test]
+fn app_routes_do_not_mask_crates() {
+    use yew_router::Routable;
+
+    for name in [
+        "settings", "upload", "patch", "lockfile", "timeline", "blame", "all",
+    ] {
+        let route = Route::recognize(&format!("/{name}/"));
+        assert!(
+            matches!(&route, Some(Route::Crate { krate }) if krate == name),
+            "{name}"
+        );
+    }
+    assert!(matches!(
+        Route::recognize("/_/settings"),
+        Some(Route::Settings)
+    ));
+    assert!(matches!(
+        Route::recognize("/_/timeline/serde"),
+        Some(Route::Timeline { krate }) if krate == "serde"
+    ));
+}
@@ -0,0 +1,239 @@
+//! Color scheme of the application and theme of highlighted code.
+//!
+//! The color scheme follows the `prefers-color-scheme` of the browser, unless light or dark is
+//! chosen explicitly. Dark mode is enabled by the `dark` class of the document element, which
+//! the stylesheet uses for its dark variants. Highlighted code is colored by the stylesheet of
+//! the syntax theme for the current color scheme, which is swapped when either changes.
+
+use crate::syntax::{theme_css, DEFAULT_DARK_THEME, DEFAULT_LIGHT_THEME};
+use std::cell::{Cell, RefCell};
+use wasm_bindgen::{prelude::*, JsCast};
+use web_sys::{Document, Storage};
+use yew::prelude::*;
+
+/// Local storage key for the color scheme.
+const COLOR_SCHEME: &str = "diff-rs.color-scheme";
+
+/// Local storage key for the syntax theme used with the light color scheme.
+const LIGHT_THEME: &str = "diff-rs.light-theme";
+
+/// Local storage key for the syntax theme used with the dark color scheme.
+const DARK_THEME: &str = "diff-rs.dark-theme";
+
+/// Media query which matches if the browser prefers a dark color scheme.
+const PREFERS_DARK: &str = "(prefers-color-scheme: dark)";
+
+/// Identifier of the style element which holds the stylesheet of the syntax theme.
+const SYNTAX_THEME_STYLE: &str = "syntax-theme";
+
+/// Color scheme of the application.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ColorScheme {
+    /// Follow the color scheme preferred by the browser
+    #[default]
+    System,
+    Light,
+    Dark,
+}
+
+impl ColorScheme {
+    /// All color schemes, in the order in which they are offered.
+    pub const ALL: [ColorScheme; 3] = [Self::System, Self::Light, Self::Dark];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::System => "System",
+            Self::Light => "Light",
+            Self::Dark => "Dark",
+        }
+    }
+
+    /// Key of the color scheme in local storage.
+    pub fn key(&self) -> &'static str {
+        match self {
+            Self::System => "system",
+            Self::Light => "light",
+            Self::Dark => "dark",
+        }
+    }
+
+    /// Parse the key of a color scheme, see [`key`](Self::key).
+    pub fn from_key(key: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|scheme| scheme.key() == key)
+    }
+
+    /// Color scheme which follows this one when toggling through them.
+    pub fn next(&self) -> Self {
+        match self {
+            Self::System => Self::Light,
+            Self::Light => Self::Dark,
+            Self::Dark => Self::System,
+        }
+    }
+
+    /// Determines if this color scheme is dark, given whether the browser prefers dark.
+    pub fn is_dark(&self, prefers_dark: bool) -> bool {
+        match self {
+            Self::System => prefers_dark,
+            Self::Light => false,
+            Self::Dark => true,
+        }
+    }
+}
+
+/// Color scheme and syntax themes chosen by the user.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ThemeSettings {
+    pub scheme: ColorScheme,
+    /// Name of the syntax theme used with the light color scheme
+    pub light_theme: String,
+    /// Name of the syntax theme used with the dark color scheme
+    pub dark_theme: String,
+}
+
+impl Default for ThemeSettings {
+    fn default() -> Self {
+        Self {
+            scheme: ColorScheme::default(),
+            light_theme: DEFAULT_LIGHT_THEME.into(),
+            dark_theme: DEFAULT_DARK_THEME.into(),
+        }
+    }
+}
+
+impl ThemeSettings {
+    /// Name of the syntax theme for the light or dark color scheme.
+    pub fn syntax_theme(&self, dark: bool) -> &str {
+        match dark {
+            true => &self.dark_theme,
+            false => &self.light_theme,
+        }
+    }
+}
+
+thread_local! {
+    /// Theme settings which are currently applied
+    static SETTINGS: RefCell<ThemeSettings> = RefCell::new(ThemeSettings::default());
+    /// Callbacks of components which show the theme settings, along with their identifiers
+    static LISTENERS: RefCell<Vec<(usize, Callback<ThemeSettings>)>> = const { RefCell::new(Vec::new()) };
+    /// Identifier of the next listener
+    static NEXT_LISTENER: Cell<usize> = const { Cell::new(0) };
+}
+
+fn local_storage() -> Option<Storage> {
+    web_sys::window()?.local_storage().ok()?
+}
+
+fn document() -> Option<Document> {
+    web_sys::window()?.document()
+}
+
+/// Determines if the browser prefers a dark color scheme.
+fn prefers_dark() -> bool {
+    web_sys::window()
+        .and_then(|window| window.match_media(PREFERS_DARK).ok()?)
+        .is_some_and(|query| query.matches())
+}
+
+/// Apply the color scheme and the matching syntax theme to the document.
+fn apply(settings: &ThemeSettings) {
+    let Some(document) = document() else {
+        return;
+    };
+    let dark = settings.scheme.is_dark(prefers_dark());
+    if let Some(root) = document.document_element() {
+        let _ = root.class_list().toggle_with_force("dark", dark);
+    }
+
+    let css = theme_css(settings.syntax_theme(dark))
+        .or_else(|| theme_css(ThemeSettings::default().syntax_theme(dark)))
+        .unwrap_or_default();
+    let style = match document.get_element_by_id(SYNTAX_THEME_STYLE) {
+        Some(style) => style,
+        None => {
+            let Ok(style) = document.create_element("style") else {
+                return;
+            };
+            style.set_id(SYNTAX_THEME_STYLE);
+            if let Some(head) = document.head() {
+                let _ = head.append_child(&style);
+            }
+            style
+        }
+    };
+    style.set_text_content(Some(&css));
+}
+
+/// Get the theme settings which are currently applied.
+pub fn theme_settings() -> ThemeSettings {
+    SETTINGS.with(|settings| settings.borrow().clone())
+}
+
+/// Apply the theme settings and persist them in local storage.
+pub fn set_theme_settings(settings: ThemeSettings) {
+    if let Some(storage) = local_storage() {
+        let _ = storage.set_item(COLOR_SCHEME, settings.scheme.key());
+        let _ = storage.set_item(LIGHT_THEME, &settings.light_theme);
+        let _ = storage.set_item(DARK_THEME, &settings.dark_theme);
+    }
+    apply(&settings);
+    SETTINGS.with(|current| *current.borrow_mut() = settings.clone());
+    let listeners: Vec<_> = LISTENERS.with(|listeners| {
+        listeners
+            .borrow()
+            .iter()
+            .map(|(_, callback)| callback.clone())
+            .collect()
+    });
+    for listener in listeners {
+        listener.emit(settings.clone());
+    }
+}
+
+/// Apply the theme settings persisted in local storage, and follow changes of the color scheme
+/// preferred by the browser.
+///
+/// This should be called once when the application starts.
+pub fn restore_theme() {
+    let mut settings = ThemeSettings::default();
+    if let Some(storage) = local_storage() {
+        let item = |key| storage.get_item(key).ok().flatten();
+        if let Some(scheme) = item(COLOR_SCHEME).and_then(|key| ColorScheme::from_key(&key)) {
+            settings.scheme = scheme;
+        }
+        if let Some(theme) = item(LIGHT_THEME) {
+            settings.light_theme = theme;
+        }
+        if let Some(theme) = item(DARK_THEME) {
+            settings.dark_theme = theme;
+        }
+    }
+    apply(&settings);
+    SETTINGS.with(|current| *current.borrow_mut() = settings);
+
+    // the listener lives as long as the application
+    let query = web_sys::window().and_then(|window| window.match_media(PREFERS_DARK).ok()?);
+    if let Some(query) = query {
+        let listener = Closure::<dyn Fn()>::new(|| apply(&theme_settings()));
+        let _ = query.add_event_listener_with_callback("change", listener.as_ref().unchecked_ref());
+        listener.forget();
+    }
+}
+
+/// Get the current theme settings, re-rendering the component whenever they change.
+#[hook]
+pub fn use_theme_settings() -> ThemeSettings {
+    let settings = use_state_eq(theme_settings);
+    {
+        let settings = settings.clone();
+        use_effect_with((), move |_| {
+            let id = NEXT_LISTENER.with(|next| next.replace(next.get() + 1));
+            let callback = Callback::from(move |value| settings.set(value));
+            LISTENERS.with(|listeners| listeners.borrow_mut().push((id, callback)));
+            move || {
+                LISTENERS.with(|listeners| listeners.borrow_mut().retain(|(other, _)| *other != id))
+            }
+        });
+    }
+    (*settings).clone()
+}
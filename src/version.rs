@@ -5,11 +5,65 @@ use std::{
 };
 use strum::EnumString;
 
+/// Named version of a crate.
+///
+/// Unless noted otherwise, named versions only consider releases: versions which are neither
+/// yanked nor pre-releases. If a crate has no releases, unyanked pre-releases are considered, and
+/// if every version is yanked, all versions are.
 #[derive(Debug, PartialEq, Eq, EnumString, Clone, strum::Display)]
 #[strum(serialize_all = "kebab-case")]
 pub enum VersionNamed {
+    /// Newest release
     Latest,
+    /// Release before the newest release
     Previous,
+    /// Newest version which is not a pre-release and not yanked, without fallback
+    LatestStable,
+    /// Newest version which is not yanked, including pre-releases
+    LatestUnyanked,
+    /// Newest release which is not semver-compatible with the newest release
+    PreviousMajor,
+    /// Newest release with a different major or minor version than the newest release
+    PreviousMinor,
+}
+
+/// Calendar date, used to pick the version which was current at that date.
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy)]
+pub struct VersionDate {
+    pub year: u16,
+    pub month: u8,
+    pub day: u8,
+}
+
+impl FromStr for VersionDate {
+    type Err = ();
+
+    /// Parse a date in the `YYYY-MM-DD` format.
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        let mut parts = input.splitn(3, '-');
+        let mut part = |digits: usize| {
+            parts
+                .next()
+                .filter(|part| part.len() == digits && part.bytes().all(|c| c.is_ascii_digit()))
+                .and_then(|part| part.parse().ok())
+                .ok_or(())
+        };
+        let date = Self {
+            year: part(4)?,
+            month: part(2)? as u8,
+            day: part(2)? as u8,
+        };
+        if !(1..=12).contains(&date.month) || !(1..=31).contains(&date.day) {
+            return Err(());
+        }
+        Ok(date)
+    }
+}
+
+impl Display for VersionDate {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        write!(f, "{:04}-{:02}-{:02}", self.year, self.month, self.day)
+    }
 }
 
 #[derive(Debug, PartialEq, Eq, Clone)]
@@ -17,6 +71,11 @@ pub enum VersionId {
     Named(VersionNamed),
     Exact(Version),
     Requirement(VersionReq),
+    /// Release which is the given number of releases older than a named version, such as
+    /// `latest~3`.
+    Relative(VersionNamed, usize),
+    /// Newest release which was published on or before the given date, such as `@2024-01-01`.
+    Date(VersionDate),
 }
 
 impl FromStr for VersionId {
@@ -31,6 +90,18 @@ impl FromStr for VersionId {
             return Ok(exact.into());
         }
 
+        if let Some((named, offset)) = input.split_once('~') {
+            if let (Ok(named), Ok(offset)) = (VersionNamed::from_str(named), offset.parse()) {
+                return Ok(Self::Relative(named, offset));
+            }
+        }
+
+        if let Some(date) = input.strip_prefix('@') {
+            if let Ok(date) = date.parse() {
+                return Ok(Self::Date(date));
+            }
+        }
+
         Ok(VersionReq::from_str(input)?.into())
     }
 }
@@ -41,6 +112,8 @@ impl Display for VersionId {
             Self::Named(named) => Display::fmt(named, f),
             Self::Exact(version) => Display::fmt(version, f),
             Self::Requirement(req) => Display::fmt(req, f),
+            Self::Relative(named, offset) => write!(f, "{named}~{offset}"),
+            Self::Date(date) => write!(f, "@{date}"),
         }
     }
 }
@@ -59,6 +132,21 @@ from!(Version, Self::Exact);
 from!(VersionReq, Self::Requirement);
 from!(VersionNamed, Self::Named);
 
+/// Determines if `old` and `new` are not semver-compatible.
+///
+/// Like Cargo, this treats the leftmost non-zero component as the major version, so that
+/// `0.1.0` and `0.2.0` are incompatible, but `1.1.0` and `1.2.0` are compatible.
+pub fn is_breaking(old: &Version, new: &Version) -> bool {
+    let major = |version: &Version| match version {
+        Version {
+            major: 0, minor: 0, ..
+        } => (0, 0, version.patch),
+        Version { major: 0, .. } => (0, version.minor, 0),
+        _ => (version.major, 0, 0),
+    };
+    major(old) != major(new)
+}
+
 #[test]
 fn can_parse_version_id() {
     assert_eq!(
@@ -74,3 +162,203 @@ fn can_parse_version_id() {
         VersionId::Exact("0.1.0".parse().unwrap()),
     );
 }
+
+#[test]
+fn can_parse_named_version_id() {
+    let named = |input: &str| input.parse::<VersionId>().unwrap();
+    assert_eq!(
+        named("latest-stable"),
+        VersionId::Named(VersionNamed::LatestStable)
+    );
+    assert_eq!(
+        named("latest-unyanked"),
+        VersionId::Named(VersionNamed::LatestUnyanked)
+    );
+    assert_eq!(
+        named("previous-major"),
+        VersionId::Named(VersionNamed::PreviousMajor)
+    );
+    assert_eq!(
+        named("previous-minor"),
+        VersionId::Named(VersionNamed::PreviousMinor)
+    );
+}
+
+#[test]
+fn can_parse_relative_version_id() {
+    assert_eq!(
+        "latest~3".parse::<VersionId>().unwrap(),
+        VersionId::Relative(VersionNamed::Latest, 3)
+    );
+    assert_eq!(
+        "previous-major~0".parse::<VersionId>().unwrap(),
+        VersionId::Relative(VersionNamed::PreviousMajor, 0)
+    );
+    // tilde requirements are not confused with relative versions
+    assert_eq!(
+        "~1.2".parse::<VersionId>().unwrap(),
+        VersionId::Requirement("~1.2".parse().unwrap())
+    );
+    assert!("latest~x".parse::<VersionId>().is_err());
+    assert!("newest~1".parse::<VersionId>().is_err());
+}
+
+#[test]
+fn can_parse_date_version_id() {
+    assert_eq!(
+        "@2024-01-01".parse::<VersionId>().unwrap(),
+        VersionId::Date(VersionDate {
+            year: 2024,
+            month: 1,
+            day: 1
+        })
+    );
+    assert!("@2024-13-01".parse::<VersionId>().is_err());
+    assert!("@2024-1-1".parse::<VersionId>().is_err());
+    assert!("@yesterday".parse::<VersionId>().is_err());
+}
+
+#[test]
+fn version_id_display_roundtrips() {
+    for input in [
+        "latest",
+        "previous-minor",
+        "1.2.3-beta.1",
+        "^1.2",
+        "latest-stable~2",
+        "@2023-09-30",
+    ] {
+        let id: VersionId = input.parse().unwrap();
+        assert_eq!(id.to_string(), input);
+        assert_eq!(id.to_string().parse::<VersionId>().unwrap(), id);
+    }
+}
+
+#[cfg(test)]
+fn crate_response(versions: &[(&str, bool, &str)]) -> crate::data::CrateResponse {
+    use crate::data::{CrateDetail, CrateResponse, VersionInfo};
+    let version: Version = versions[0].0.parse().unwrap();
+    CrateResponse {
+        krate: CrateDetail {
+            id: "example".into(),
+            max_version: version.clone(),
+            max_stable_version: None,
+            newest_version: version,
+            description: String::new(),
+            downloads: 0,
+            recent_downloads: None,
+            exact_match: false,
+            homepage: None,
+            repository: None,
+            documentation: None,
+        },
+        versions: versions
+            .iter()
+            .map(|(version, yanked, published)| VersionInfo {
+                checksum: vec![],
+                krate: "example".into(),
+                dl_path: String::new(),
+                yanked: *yanked,
+                version: version.parse().unwrap(),
+                crate_size: None,
+                created_at: Some(format!("{published}T12:00:00.000000+00:00")),
+            })
+            .collect(),
+    }
+}
+
+#[cfg(test)]
+fn resolve(info: &crate::data::CrateResponse, id: &str) -> Option<String> {
+    info.version(id.parse().unwrap())
+        .map(|info| info.version.to_string())
+}
+
+#[test]
+fn named_versions_skip_yanked_and_pre_releases() {
+    let info = crate_response(&[
+        ("2.0.0-beta.1", false, "2024-03-01"),
+        ("1.2.1", true, "2024-02-15"),
+        ("1.2.0", false, "2024-02-01"),
+        ("1.1.0", false, "2024-01-10"),
+        ("1.0.0", false, "2023-12-01"),
+        ("0.9.0", false, "2023-06-01"),
+    ]);
+    let resolve = |id| resolve(&info, id);
+    assert_eq!(resolve("latest").as_deref(), Some("1.2.0"));
+    assert_eq!(resolve("previous").as_deref(), Some("1.1.0"));
+    assert_eq!(resolve("latest-stable").as_deref(), Some("1.2.0"));
+    assert_eq!(resolve("latest-unyanked").as_deref(), Some("2.0.0-beta.1"));
+    assert_eq!(resolve("previous-major").as_deref(), Some("0.9.0"));
+    assert_eq!(resolve("previous-minor").as_deref(), Some("1.1.0"));
+    // explicitly picked versions are resolved even when yanked
+    assert_eq!(resolve("1.2.1").as_deref(), Some("1.2.1"));
+    assert_eq!(resolve("2.0.0-beta.1").as_deref(), Some("2.0.0-beta.1"));
+}
+
+#[test]
+fn requirements_prefer_unyanked_versions() {
+    let info = crate_response(&[
+        ("1.2.1", true, "2024-02-15"),
+        ("1.2.0", false, "2024-02-01"),
+        ("1.1.0", false, "2024-01-10"),
+    ]);
+    assert_eq!(resolve(&info, "^1.2").as_deref(), Some("1.2.0"));
+    assert_eq!(resolve(&info, "=1.2.1").as_deref(), Some("1.2.1"));
+    assert_eq!(resolve(&info, "^2"), None);
+}
+
+#[test]
+fn relative_versions_count_releases() {
+    let info = crate_response(&[
+        ("1.2.1", true, "2024-02-15"),
+        ("1.2.0", false, "2024-02-01"),
+        ("1.1.0", false, "2024-01-10"),
+        ("1.0.0", false, "2023-12-01"),
+        ("0.9.0", false, "2023-06-01"),
+    ]);
+    assert_eq!(resolve(&info, "latest~0").as_deref(), Some("1.2.0"));
+    assert_eq!(resolve(&info, "latest~1").as_deref(), Some("1.1.0"));
+    assert_eq!(resolve(&info, "latest~3").as_deref(), Some("0.9.0"));
+    assert_eq!(resolve(&info, "latest~4"), None);
+    assert_eq!(resolve(&info, "previous~1").as_deref(), Some("1.0.0"));
+    assert_eq!(resolve(&info, "previous-major~0").as_deref(), Some("0.9.0"));
+}
+
+#[test]
+fn date_versions_pick_release_current_at_date() {
+    let info = crate_response(&[
+        ("1.2.1", true, "2024-02-15"),
+        ("1.2.0", false, "2024-02-01"),
+        ("1.1.0", false, "2024-01-10"),
+        ("1.0.0", false, "2023-12-01"),
+    ]);
+    assert_eq!(resolve(&info, "@2024-12-31").as_deref(), Some("1.2.0"));
+    assert_eq!(resolve(&info, "@2024-01-31").as_deref(), Some("1.1.0"));
+    assert_eq!(resolve(&info, "@2024-01-10").as_deref(), Some("1.1.0"));
+    assert_eq!(resolve(&info, "@2023-01-01"), None);
+}
+
+#[test]
+fn named_versions_fall_back_without_releases() {
+    let info = crate_response(&[
+        ("0.2.0-alpha.2", false, "2024-02-01"),
+        ("0.2.0-alpha.1", false, "2024-01-01"),
+    ]);
+    assert_eq!(resolve(&info, "latest").as_deref(), Some("0.2.0-alpha.2"));
+    assert_eq!(resolve(&info, "previous").as_deref(), Some("0.2.0-alpha.1"));
+    assert_eq!(resolve(&info, "latest-stable"), None);
+
+    let info = crate_response(&[("0.1.1", true, "2024-02-01"), ("0.1.0", true, "2024-01-01")]);
+    assert_eq!(resolve(&info, "latest").as_deref(), Some("0.1.1"));
+    assert_eq!(resolve(&info, "latest-unyanked"), None);
+}
+
+#[test]
+fn breaking_versions_use_leftmost_non_zero_component() {
+    let breaking = |old: &str, new: &str| is_breaking(&old.parse().unwrap(), &new.parse().unwrap());
+    assert!(breaking("1.9.0", "2.0.0"));
+    assert!(!breaking("1.1.0", "1.2.3"));
+    assert!(breaking("0.1.5", "0.2.0"));
+    assert!(!breaking("0.2.0", "0.2.7"));
+    assert!(breaking("0.0.1", "0.0.2"));
+}
@@ -6,10 +6,29 @@
 //! module, which contains components shared between views.
 
 mod about;
+mod blame;
 mod diff;
 mod home;
+mod lockfile;
 mod not_found;
+mod patch;
 mod repo;
 mod search;
+mod settings;
+mod timeline;
+mod upload;
 
-pub use self::{about::*, diff::*, home::Home, not_found::NotFound, repo::*, search::Search};
+pub use self::{
+    about::*,
+    blame::Blame,
+    diff::*,
+    home::Home,
+    lockfile::Lockfile,
+    not_found::NotFound,
+    patch::{Patch, PatchDiff},
+    repo::*,
+    search::Search,
+    settings::{restore_settings, Settings},
+    timeline::Timeline,
+    upload::{Upload, UploadDiff},
+};
@@ -0,0 +1,310 @@
+use crate::{
+    blame::{blame, BlameLine},
+    cache::CRATE_RESPONSE_CACHE,
+    components::*,
+    data::*,
+    syntax::{highlight_changes, infer_syntax_for_file},
+    version::VersionId,
+    worker::fetch_source,
+    Link, Route,
+};
+use camino::Utf8PathBuf;
+use similar::ChangeTag;
+use std::{cell::Cell, rc::Rc, sync::Arc};
+use yew::{platform::spawn_local, prelude::*, suspense::*};
+
+/// Maximum number of versions which are fetched to annotate a file.
+const MAX_VERSIONS: usize = 32;
+
+/// Props for which file to annotate, and across which versions.
+#[derive(Properties, PartialEq, Clone)]
+pub struct BlameProps {
+    pub krate: String,
+    pub old: VersionId,
+    pub new: VersionId,
+    pub path: Utf8PathBuf,
+}
+
+/// Blame view, annotates every line of a file with the version which introduced it.
+#[function_component]
+pub fn Blame(props: &BlameProps) -> Html {
+    let fallback = html! {
+        <Center>
+            <Loading title={"Loading crate"} status={"Loading crate metadata"} />
+        </Center>
+    };
+    html! {
+        <div class="flex flex-col min-h-screen">
+            <div class="flex-1">
+                <SimpleNavbar />
+                <Content>
+                    <Suspense {fallback}>
+                        <BlameFetcher ..props.clone() />
+                    </Suspense>
+                </Content>
+            </div>
+            <Footer />
+        </div>
+    }
+}
+
+/// Versions to follow a file through, from `old` to `new`.
+///
+/// Yanked versions are skipped, unless they were picked explicitly. At most [`MAX_VERSIONS`] of
+/// the newest versions are returned, along with whether older versions were left out.
+fn blame_versions(
+    info: &CrateResponse,
+    old: &VersionInfo,
+    new: &VersionInfo,
+) -> (Vec<VersionInfo>, bool) {
+    let (old, new) = if old.version <= new.version {
+        (old, new)
+    } else {
+        (new, old)
+    };
+    let mut versions: Vec<VersionInfo> = info
+        .versions
+        .iter()
+        .filter(|info| info.version >= old.version && info.version <= new.version)
+        .filter(|info| !info.yanked || info == &old || info == &new)
+        .cloned()
+        .collect();
+    versions.sort_by(|left, right| left.version.cmp(&right.version));
+    let skip = versions.len().saturating_sub(MAX_VERSIONS);
+    (versions.split_off(skip), skip > 0)
+}
+
+#[function_component]
+fn BlameFetcher(props: &BlameProps) -> HtmlResult {
+    let info = use_future_with(props.krate.clone(), |krate| async move {
+        CRATE_RESPONSE_CACHE.fetch_cached(&krate).await
+    })?;
+    let info = match &*info {
+        Ok(info) => info.clone(),
+        Err(error) => {
+            return Ok(html! {
+                <Center>
+                    <Error
+                        title={"Loading crate"}
+                        status={format!("Error: {} with {error}", props.krate)}
+                    />
+                </Center>
+            })
+        }
+    };
+
+    let (Some(old), Some(new)) = (
+        info.version(props.old.clone()),
+        info.version(props.new.clone()),
+    ) else {
+        return Ok(html! {
+            <Center>
+                <Error
+                    title={"Resolving version"}
+                    status={format!("Error: versions {} and {} of {} not found", props.old, props.new, props.krate)}
+                />
+            </Center>
+        });
+    };
+    let (versions, truncated) = blame_versions(&info, old, new);
+    Ok(html! {
+        <BlameLoader {info} {versions} {truncated} path={props.path.clone()} />
+    })
+}
+
+#[derive(Properties, PartialEq)]
+struct BlameLoaderProps {
+    info: Arc<CrateResponse>,
+    versions: Vec<VersionInfo>,
+    truncated: bool,
+    path: Utf8PathBuf,
+}
+
+/// Result of annotating a file, tagged with the versions and path.
+type BlameState = Option<(
+    (Vec<VersionInfo>, Utf8PathBuf),
+    Result<Rc<Vec<BlameLine>>, String>,
+)>;
+
+/// Fetch the crate source of every version, and annotate the file.
+#[function_component]
+fn BlameLoader(props: &BlameLoaderProps) -> Html {
+    let key = (props.versions.clone(), props.path.clone());
+    let progress = use_state(|| None::<(String, f64)>);
+    let result = use_state(|| BlameState::None);
+
+    {
+        let progress = progress.clone();
+        let result = result.clone();
+        use_effect_with(key.clone(), move |key| {
+            let key = key.clone();
+            let cancelled = Rc::new(Cell::new(false));
+            progress.set(None);
+            spawn_local({
+                let cancelled = cancelled.clone();
+                async move {
+                    let (versions, path) = &key;
+                    let mut contents = vec![];
+                    for (index, version) in versions.iter().enumerate() {
+                        if cancelled.get() {
+                            return;
+                        }
+                        let status = format!(
+                            "Fetching {} v{} ({} of {})",
+                            version.krate,
+                            version.version,
+                            index + 1,
+                            versions.len()
+                        );
+                        progress.set(Some((status, index as f64 / versions.len() as f64)));
+                        match fetch_source(version).await {
+                            Ok(source) => contents
+                                .push((version.version.clone(), source.files.get(path).cloned())),
+                            Err(error) => {
+                                if !cancelled.get() {
+                                    result.set(Some((key.clone(), Err(error.to_string()))));
+                                }
+                                return;
+                            }
+                        }
+                    }
+                    if !cancelled.get() {
+                        result.set(Some((key.clone(), Ok(Rc::new(blame(&contents))))));
+                    }
+                }
+            });
+            move || cancelled.set(true)
+        });
+    }
+
+    let lines = match &*result {
+        Some((other, Ok(lines))) if *other == key => lines.clone(),
+        Some((other, Err(error))) if *other == key => {
+            return html! {
+                <Center>
+                    <Error title={"Loading crate"} status={format!("Error: {error}")} />
+                </Center>
+            };
+        }
+        _ => {
+            let (status, progress) = match &*progress {
+                Some((status, progress)) => (status.clone(), Some(*progress)),
+                None => ("Loading crate sources".into(), None),
+            };
+            return html! {
+                <Center>
+                    <Loading title={"Annotating file"} {status} {progress} />
+                </Center>
+            };
+        }
+    };
+
+    html! {
+        <BlameTable
+            krate={props.info.krate.id.clone()}
+            versions={props.versions.clone()}
+            truncated={props.truncated}
+            path={props.path.clone()}
+            {lines}
+        />
+    }
+}
+
+#[derive(Properties, PartialEq)]
+struct BlameTableProps {
+    krate: String,
+    versions: Vec<VersionInfo>,
+    truncated: bool,
+    path: Utf8PathBuf,
+    lines: Rc<Vec<BlameLine>>,
+}
+
+/// Show the lines of a file along with the versions which introduced them.
+#[function_component]
+fn BlameTable(props: &BlameTableProps) -> Html {
+    let highlighted = use_memo(
+        (props.path.clone(), props.lines.clone()),
+        |(path, lines)| {
+            let syntax = infer_syntax_for_file(
+                path.as_str(),
+                lines
+                    .iter()
+                    .map(|line| std::str::from_utf8(&line.text).unwrap_or_default()),
+            );
+            let changes: Vec<_> = lines
+                .iter()
+                .map(|line| (ChangeTag::Equal, line.text.clone()))
+                .collect();
+            highlight_changes(syntax, &changes)
+        },
+    );
+
+    // link every version to the diff against the version before it
+    let route = |version: &semver::Version| {
+        let index = props
+            .versions
+            .iter()
+            .position(|info| &info.version == version)
+            .unwrap_or_default();
+        match index.checked_sub(1).map(|index| &props.versions[index]) {
+            Some(previous) => Route::SingleSourceFile {
+                krate: props.krate.clone(),
+                old_version: previous.version.clone().into(),
+                new_version: version.clone().into(),
+                path: props.path.clone(),
+            },
+            None => Route::BrowseFile {
+                krate: props.krate.clone(),
+                version: version.clone().into(),
+                path: props.path.clone(),
+            },
+        }
+    };
+
+    let (Some(oldest), Some(newest)) = (props.versions.first(), props.versions.last()) else {
+        return html! {};
+    };
+    let rows = props.lines.iter().zip(highlighted.iter()).enumerate().map(
+        |(index, (line, (_, highlighted)))| {
+            // only annotate the first line of a run of lines from the same version
+            let first = index == 0 || props.lines[index - 1].version != line.version;
+            html! {
+                <div class={classes!("line", first.then_some("first"))}>
+                    <div class="version">
+                        if first {
+                            <Link to={route(&line.version)}>{line.version.to_string()}</Link>
+                        }
+                    </div>
+                    <div class="line-number">{index + 1}</div>
+                    <div class="code-line">
+                        <CodeLine line={highlighted.clone()} />
+                    </div>
+                </div>
+            }
+        },
+    );
+
+    html! {
+        <section class="blame">
+            <h2 class="title">
+                <span class="filename">{props.path.as_str()}</span>
+            </h2>
+            <p class="description">
+                {format!(
+                    "Lines of {} v{} annotated with the version which introduced them, following the file through {} versions since v{}.",
+                    props.krate, newest.version, props.versions.len(), oldest.version,
+                )}
+                if props.truncated {
+                    {format!(" Only the last {MAX_VERSIONS} versions are considered, lines annotated with v{} may be older.", oldest.version)}
+                }
+            </p>
+            if props.lines.is_empty() {
+                <p class="description">{format!("The file does not exist in v{}.", newest.version)}</p>
+            } else {
+                <div class="content">
+                    { for rows }
+                </div>
+            }
+        </section>
+    }
+}
@@ -1,10 +1,21 @@
-use crate::{cache::*, components::*, data::*, version::VersionId, Route};
+use super::settings::format_size;
+use crate::{
+    cache::CRATE_RESPONSE_CACHE,
+    components::{Context, *},
+    data::*,
+    version::VersionId,
+    worker::{diff_upload, diff_versions, Progress},
+    Link, Route,
+};
 use camino::Utf8PathBuf;
 use semver::Version;
-use std::sync::Arc;
-use yew::{prelude::*, suspense::*};
+use std::{cell::Cell, rc::Rc, sync::Arc};
+use yew::{platform::spawn_local, prelude::*, suspense::*};
 use yew_router::prelude::*;
 
+/// Old and new crate name and version, as selected in the navbar.
+pub type VersionChange = ((String, Version), (String, Version));
+
 /// Props for which file to show.
 #[derive(Properties, PartialEq, Clone)]
 pub struct DiffProps {
@@ -14,6 +25,9 @@ pub struct DiffProps {
     pub new: VersionId,
     #[prop_or_default]
     pub path: Option<Utf8PathBuf>,
+    /// Show the diffs of all changed files at once, instead of a single file.
+    #[prop_or_default]
+    pub all_files: bool,
 }
 
 /// Show diff of a file change between two crate versions.
@@ -37,6 +51,7 @@ pub fn Diff(props: &DiffProps) -> Html {
                 old={props.old.clone()}
                 new={props.new.clone()}
                 path={props.path.clone()}
+                all_files={props.all_files}
             />
         </Suspense>
     }
@@ -47,9 +62,9 @@ fn CrateFetcher(props: &DiffProps) -> HtmlResult {
     let info = use_future_with(
         (props.src_name.clone(), props.dst_name.clone()),
         |names| async move {
-            (
-                CRATE_RESPONSE_CACHE.fetch_cached(&names.0).await,
-                CRATE_RESPONSE_CACHE.fetch_cached(&names.1).await,
+            futures::join!(
+                CRATE_RESPONSE_CACHE.fetch_cached(&names.0),
+                CRATE_RESPONSE_CACHE.fetch_cached(&names.1),
             )
         },
     )?;
@@ -63,6 +78,7 @@ fn CrateFetcher(props: &DiffProps) -> HtmlResult {
                     old={props.old.clone()}
                     new={props.new.clone()}
                     path={props.path.clone()}
+                    all_files={props.all_files}
                 />
             })
         }
@@ -96,6 +112,7 @@ struct VersionResolverProps {
     old: VersionId,
     new: VersionId,
     path: Option<Utf8PathBuf>,
+    all_files: bool,
 }
 
 #[function_component]
@@ -112,6 +129,7 @@ fn VersionResolver(props: &VersionResolverProps) -> Html {
                     old={old.clone()}
                     new={new.clone()}
                     path={props.path.clone()}
+                    all_files={props.all_files}
                 />
             }
         }
@@ -138,172 +156,477 @@ fn VersionResolver(props: &VersionResolverProps) -> Html {
 }
 
 #[derive(Properties, PartialEq, Clone)]
-struct SourceFetcherProps {
-    src_info: Arc<CrateResponse>,
-    dst_info: Arc<CrateResponse>,
-    old: VersionInfo,
-    new: VersionInfo,
-    path: Option<Utf8PathBuf>,
+pub(super) struct SourceFetcherProps {
+    pub src_info: Arc<CrateResponse>,
+    pub dst_info: Arc<CrateResponse>,
+    pub old: VersionInfo,
+    pub new: VersionInfo,
+    pub path: Option<Utf8PathBuf>,
+    /// Show the diffs of all changed files at once, instead of a single file.
+    #[prop_or_default]
+    pub all_files: bool,
+    /// Uploaded crate source to diff against instead of fetching `new`.
+    #[prop_or_default]
+    pub upload: Option<Arc<CrateSource>>,
+    /// Route to a file, defaults to [`Route::File`] for the versions of the diff.
+    #[prop_or_default]
+    pub file_route: Option<Callback<Utf8PathBuf, Route>>,
+    /// Called when versions are changed, defaults to navigating to [`Route::File`].
+    #[prop_or_default]
+    pub onchange: Option<Callback<VersionChange>>,
 }
 
-#[function_component]
-fn SourceFetcher(props: &SourceFetcherProps) -> Html {
-    let fallback = html! {
-        <>
-            <ComplexNavbar
-                src_name={props.src_info.krate.id.clone()}
-                dst_name={props.dst_info.krate.id.clone()}
-                old={props.old.version.clone()}
-                new={props.new.version.clone()}
-                src_info={props.src_info.clone()}
-                dst_info={props.dst_info.clone()}
-            />
-            <Center>
-                <Loading title={"Loading crate"} status={"Loading crate source"} />
-            </Center>
-        </>
-    };
-    html! {
-        <Suspense {fallback}>
-            <SourceFetcherInner
-                src_info={props.src_info.clone()}
-                dst_info={props.dst_info.clone()}
-                old={props.old.clone()}
-                new={props.new.clone()}
-                path={props.path.clone()}
-            />
-        </Suspense>
+/// Describe the progress of fetching and diffing crate sources.
+fn progress_status(progress: &Progress) -> String {
+    match progress {
+        Progress::Downloading {
+            krate,
+            version,
+            bytes,
+            total: Some(total),
+        } => format!(
+            "Downloading {krate} v{version} ({} of {})",
+            format_size(*bytes),
+            format_size(*total)
+        ),
+        Progress::Downloading {
+            krate,
+            version,
+            bytes,
+            total: None,
+        } => format!("Downloading {krate} v{version} ({})", format_size(*bytes)),
+        Progress::Unpacking {
+            krate,
+            version,
+            files,
+        } => format!("Unpacking {krate} v{version} ({files} files)"),
+        Progress::Diffing { files, total } => format!("Diffing files ({files} of {total})"),
     }
 }
 
+/// Result of diffing two crate versions, tagged with the versions.
+type DiffState = Option<((VersionInfo, VersionInfo), anyhow::Result<Rc<VersionDiff>>)>;
+
 #[function_component]
-fn SourceFetcherInner(props: &SourceFetcherProps) -> HtmlResult {
-    // fetch old version source
-    let old = use_future_with(props.old.clone(), |version| async move {
-        CRATE_SOURCE_CACHE.fetch_cached(&version).await
-    })?;
+pub(super) fn SourceFetcher(props: &SourceFetcherProps) -> Html {
+    let versions = (props.old.clone(), props.new.clone());
+    let progress = use_state(|| None::<Progress>);
+    let diff = use_state(|| DiffState::None);
 
-    // fetch new version source
-    let new = use_future_with(props.new.clone(), |version| async move {
-        CRATE_SOURCE_CACHE.fetch_cached(&version).await
-    })?;
+    // fetch and diff sources in the worker, ignoring progress of previous versions
+    {
+        let progress = progress.clone();
+        let diff = diff.clone();
+        let upload = props.upload.clone();
+        use_effect_with(versions.clone(), move |versions| {
+            let versions = versions.clone();
+            let cancelled = Rc::new(Cell::new(false));
+            progress.set(None);
+            spawn_local({
+                let cancelled = cancelled.clone();
+                async move {
+                    let report = {
+                        let cancelled = cancelled.clone();
+                        move |status| {
+                            if !cancelled.get() {
+                                progress.set(Some(status));
+                            }
+                        }
+                    };
+                    let result = match upload {
+                        Some(upload) => diff_upload(&versions.0, upload, report).await,
+                        None => diff_versions(&versions.0, &versions.1, report).await,
+                    };
+                    if !cancelled.get() {
+                        diff.set(Some((versions, result)));
+                    }
+                }
+            });
+            move || cancelled.set(true)
+        });
+    }
 
     let navigator = use_navigator().unwrap();
-    let (old, new) = match (&*old, &*new) {
-        (Ok(old), Ok(new)) => (old, new),
-        (Err(error), _) | (_, Err(error)) => {
-            return Ok(html! {
+    let onchange = props.onchange.clone().unwrap_or_else(|| {
+        let path = props.path.clone();
+        let all_files = props.all_files;
+        Callback::from(move |((src_name, old), (dst_name, new)): VersionChange| {
+            let route = if all_files {
+                Route::AllFiles {
+                    old_krate: src_name,
+                    new_krate: dst_name,
+                    old_version: old.into(),
+                    new_version: new.into(),
+                }
+            } else {
+                Route::File {
+                    old_krate: src_name,
+                    new_krate: dst_name,
+                    old_version: old.into(),
+                    new_version: new.into(),
+                    path: path.clone().unwrap_or_default(),
+                }
+            };
+            navigator.push(&route);
+        })
+    });
+    let navbar = html! {
+        <ComplexNavbar
+            src_name={props.src_info.krate.id.clone()}
+            dst_name={props.dst_info.krate.id.clone()}
+            old={props.old.version.clone()}
+            new={props.new.version.clone()}
+            src_info={props.src_info.clone()}
+            dst_info={props.dst_info.clone()}
+            onchange={onchange.clone()}
+        />
+    };
+
+    let diff = match &*diff {
+        Some((key, Ok(diff))) if *key == versions => diff.clone(),
+        Some((key, Err(error))) if *key == versions => {
+            return html! {
                 <>
-                <ComplexNavbar
-                    src_name={props.src_info.krate.id.clone()}
-                    dst_name={props.dst_info.krate.id.clone()}
-                    old={props.old.version.clone()}
-                    new={props.new.version.clone()}
-                    src_info={props.src_info.clone()}
-                    dst_info={props.dst_info.clone()}
-                    onchange={
-                        let path = props.path.clone();
-                        move |((src_name, old), (dst_name, new)): ((String, Version), (String, Version))| {
-                            navigator.push(&Route::File {
-                                old_krate: src_name.clone(),
-                                new_krate: dst_name.clone(),
-                                old_version: old.clone().into(),
-                                new_version: new.clone().into(),
-                                path: path.clone().unwrap_or_default().into(),
-                            });
-                        }
-                    }
-                />
+                {navbar}
                 <Content>
                     <Center>
                         <Error title={"Loading crate"} status={format!("Error: {error}")} />
                     </Center>
                 </Content>
                 </>
-            })
+            }
+        }
+        _ => {
+            let status = match &*progress {
+                Some(progress) => progress_status(progress),
+                None => "Loading crate source".into(),
+            };
+            let progress = progress.as_ref().and_then(Progress::fraction);
+            return html! {
+                <>
+                {navbar}
+                <Center>
+                    <Loading title={"Loading crate"} {status} {progress} />
+                </Center>
+                </>
+            };
         }
     };
 
-    dbg!(&props.path);
     let path = match &props.path {
+        // the tree highlights no file until one is selected
+        None if props.all_files => Utf8PathBuf::default(),
         None => {
-            return Ok(html! {
-                <Redirect<Route> to={Route::File {
+            let to = match &props.file_route {
+                Some(file_route) => file_route.emit("Cargo.toml".into()),
+                None => Route::File {
                     old_krate: props.src_info.krate.id.clone(),
                     new_krate: props.dst_info.krate.id.clone(),
                     old_version: props.old.version.clone().into(),
                     new_version: props.new.version.clone().into(),
                     path: "Cargo.toml".into(),
-                }} />
-            })
+                },
+            };
+            return html! {
+                <Redirect<Route> {to} />
+            };
         }
         Some(path) => path.clone(),
     };
 
-    Ok(html! {
+    html! {
         <div class="">
             <SourceView
                 src_info={props.src_info.clone()}
                 dst_info={props.dst_info.clone()}
-                {old}
-                {new}
+                {diff}
                 {path}
+                all_files={props.all_files}
+                file_route={props.file_route.clone()}
+                {onchange}
             />
         </div>
-    })
+    }
 }
 
 #[derive(Properties, PartialEq, Clone)]
 pub struct SourceViewProps {
     pub src_info: Arc<CrateResponse>,
     pub dst_info: Arc<CrateResponse>,
-    pub old: Arc<CrateSource>,
-    pub new: Arc<CrateSource>,
+    pub diff: Rc<VersionDiff>,
     pub path: Utf8PathBuf,
+    /// Show the diffs of all changed files at once, the file tree scrolls to them.
+    #[prop_or_default]
+    pub all_files: bool,
+    /// Route to a file, defaults to [`Route::File`] for the versions of the diff.
+    #[prop_or_default]
+    pub file_route: Option<Callback<Utf8PathBuf, Route>>,
+    /// Called when versions are changed, defaults to navigating to [`Route::File`].
+    #[prop_or_default]
+    pub onchange: Option<Callback<VersionChange>>,
+}
+
+/// Dialog which is shown over the diff view.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Overlay {
+    Help,
+    FindFile,
+    SearchCode,
 }
 
 #[function_component]
 pub fn SourceView(props: &SourceViewProps) -> Html {
-    let diff = use_memo((props.old.clone(), props.new.clone()), |(old, new)| {
-        VersionDiff::new(old.clone(), new.clone())
-    });
+    let diff = &props.diff;
     let navigator = use_navigator().unwrap();
+    let onchange = props.onchange.clone().unwrap_or_else(|| {
+        let navigator = navigator.clone();
+        let path = props.path.clone();
+        Callback::from(move |((src_name, old), (dst_name, new)): VersionChange| {
+            navigator.push(&Route::File {
+                old_krate: src_name.clone(),
+                new_krate: dst_name.clone(),
+                old_version: old.clone().into(),
+                new_version: new.clone().into(),
+                path: path.clone(),
+            });
+        })
+    });
+
+    // file which was last selected in the file tree, when showing all files
+    let selected = use_state(|| props.path.clone());
+    let onselect = props.all_files.then(|| {
+        let selected = selected.clone();
+        Callback::from(move |path: Utf8PathBuf| {
+            scroll_to_file(&path);
+            selected.set(path);
+        })
+    });
+    let path = match props.all_files {
+        true => (*selected).clone(),
+        false => props.path.clone(),
+    };
+
+    let style = use_state(DiffStyle::default);
+    let expand_all = use_state(|| false);
+    let overlay = use_state(|| None::<Overlay>);
+
+    // show a file, scrolling to it when showing all files
+    let open_file = {
+        let context = Context::new(diff, props.file_route.clone(), onselect.clone());
+        Callback::from(move |path: Utf8PathBuf| context.open(&navigator, path))
+    };
+
+    // compare the previous or next versions of both crates
+    let step_versions = {
+        let (src_info, dst_info) = (props.src_info.clone(), props.dst_info.clone());
+        let (old, new) = (
+            diff.left.version.version.clone(),
+            diff.right.version.version.clone(),
+        );
+        let onchange = onchange.clone();
+        move |forward: bool| {
+            let old = src_info.adjacent_version(&old, forward);
+            // uploads are the only version of the new crate, so only the old version moves
+            let new = match dst_info.versions.len() {
+                1 => dst_info.versions.first(),
+                _ => dst_info.adjacent_version(&new, forward),
+            };
+            if let (Some(old), Some(new)) = (old, new) {
+                onchange.emit((
+                    (src_info.krate.id.clone(), old.version.clone()),
+                    (dst_info.krate.id.clone(), new.version.clone()),
+                ));
+            }
+        }
+    };
+
+    use_shortcuts({
+        let diff = diff.clone();
+        let path = path.clone();
+        let style = style.clone();
+        let expand_all = expand_all.clone();
+        let overlay = overlay.clone();
+        let open_file = open_file.clone();
+        Callback::from(move |shortcut| match shortcut {
+            Shortcut::NextHunk => scroll_to_hunk(true),
+            Shortcut::PreviousHunk => scroll_to_hunk(false),
+            Shortcut::NextFile | Shortcut::PreviousFile => {
+                if let Some(path) = diff.adjacent_file(&path, shortcut == Shortcut::NextFile) {
+                    open_file.emit(path);
+                }
+            }
+            Shortcut::FocusFilter => focus_element(FILE_FILTER_ID),
+            Shortcut::FindFile => overlay.set(Some(Overlay::FindFile)),
+            Shortcut::SearchCode => overlay.set(Some(Overlay::SearchCode)),
+            Shortcut::Unified => style.set(DiffStyle::Unified),
+            Shortcut::Split => style.set(DiffStyle::Split),
+            Shortcut::ExpandAll => expand_all.set(!*expand_all),
+            Shortcut::PreviousVersions => step_versions(false),
+            Shortcut::NextVersions => step_versions(true),
+            Shortcut::Help => overlay.set(Some(Overlay::Help)),
+            Shortcut::Close => overlay.set(None),
+        })
+    });
+
+    let onclose = {
+        let overlay = overlay.clone();
+        Callback::from(move |_| overlay.set(None))
+    };
+    let overlay_html = match *overlay {
+        Some(Overlay::Help) => html! { <ShortcutHelp {onclose} /> },
+        Some(Overlay::FindFile) => html! {
+            <FileFinder
+                diff={diff.clone()}
+                file_route={props.file_route.clone()}
+                onselect={onselect.clone()}
+                {onclose}
+            />
+        },
+        Some(Overlay::SearchCode) => html! {
+            <CodeSearch diff={diff.clone()} file_route={props.file_route.clone()} {onclose} />
+        },
+        None => html! {},
+    };
+    let style_toggle = {
+        let button = |value: DiffStyle, label: &str| {
+            let style = style.clone();
+            html! {
+                <button
+                    class={classes!((*style == value).then_some("active"))}
+                    onclick={move |_| style.set(value)}
+                >
+                    {label}
+                </button>
+            }
+        };
+        let open = |value: Overlay| {
+            let overlay = overlay.clone();
+            move |_| overlay.set(Some(value))
+        };
+        html! {
+            <>
+                <div class="view-mode">
+                    {button(DiffStyle::Unified, "Unified")}
+                    {button(DiffStyle::Split, "Split")}
+                </div>
+                <button class="shortcuts-button" title="Search code" onclick={open(Overlay::SearchCode)}>
+                    {"Search"}
+                </button>
+                <button class="shortcuts-button" title="Keyboard shortcuts" onclick={open(Overlay::Help)}>
+                    {"?"}
+                </button>
+            </>
+        }
+    };
+
+    // switching between a single file and all files is not supported for uploads
+    let mode_toggle = props.file_route.is_none().then(|| {
+        let (old, new) = (&diff.left.version, &diff.right.version);
+        let single = Route::File {
+            old_krate: old.krate.clone(),
+            old_version: old.version.clone().into(),
+            new_krate: new.krate.clone(),
+            new_version: new.version.clone().into(),
+            path: match path.as_str() {
+                "" => "Cargo.toml".into(),
+                _ => path.clone(),
+            },
+        }
+        .simplify();
+        let all = Route::AllFiles {
+            old_krate: old.krate.clone(),
+            old_version: old.version.clone().into(),
+            new_krate: new.krate.clone(),
+            new_version: new.version.clone().into(),
+        };
+        html! {
+            <div class="view-mode">
+                <Link to={single} classes={classes!((!props.all_files).then_some("active"))}>
+                    {"Single file"}
+                </Link>
+                <Link to={all} classes={classes!(props.all_files.then_some("active"))}>
+                    {"All files"}
+                </Link>
+            </div>
+        }
+    });
+
     html! {
         <>
             <ComplexNavbar
                 src_name={props.src_info.krate.id.clone()}
                 dst_name={props.dst_info.krate.id.clone()}
-                old={props.old.version.version.clone()}
-                new={props.new.version.version.clone()}
+                old={diff.left.version.version.clone()}
+                new={diff.right.version.version.clone()}
                 src_info={props.src_info.clone()}
                 dst_info={props.dst_info.clone()}
-                onchange={
-                    let path = props.path.clone();
-                    let navigator = navigator;
-                    move |((src_name, old), (dst_name, new)): ((String, Version), (String, Version))| {
-                        navigator.push(&Route::File {
-                            old_krate: src_name.clone(),
-                            new_krate: dst_name.clone(),
-                            old_version: old.clone().into(),
-                            new_version: new.clone().into(),
-                            path: path.clone(),
-                        });
-                    }
-                }
+                {onchange}
             />
             <Content>
                 <main class="flex flex-col md:flex-row gap-2 lg:gap-4 p-2">
                     <nav id="files" class="md:w-72 lg:w-84 xl:w-96" aria-label="Files">
                         <FileTree
                             diff={diff.clone()}
-                            path={props.path.clone()}
+                            path={path.clone()}
+                            file_route={props.file_route.clone()}
+                            {onselect}
                         />
                     </nav>
                     <div id="diff-view" class="flex-1">
-                        <DiffView {diff} path={props.path.clone()} />
+                        <div class="diff-toolbar">
+                            {mode_toggle}
+                            {style_toggle}
+                            <ExportMenu diff={diff.clone()} path={path.clone()} />
+                        </div>
+                        <SourceWarnings diff={diff.clone()} />
+                        <ChangelogPanel diff={diff.clone()} />
+                        if props.all_files {
+                            <AllFilesView
+                                diff={diff.clone()}
+                                style={*style}
+                                expand_all={*expand_all}
+                            />
+                        } else {
+                            <DiffView
+                                diff={diff.clone()}
+                                path={props.path.clone()}
+                                style={*style}
+                                expand_all={*expand_all}
+                            />
+                        }
                     </div>
                 </main>
             </Content>
+            {overlay_html}
         </>
     }
 }
+
+#[derive(Properties, PartialEq, Clone)]
+struct SourceWarningsProps {
+    diff: Rc<VersionDiff>,
+}
+
+/// Show warnings about suspicious contents of the crate sources.
+#[function_component]
+fn SourceWarnings(props: &SourceWarningsProps) -> Html {
+    let diff = &props.diff;
+    let mut sources = vec![&diff.left];
+    if diff.left.version != diff.right.version {
+        sources.push(&diff.right);
+    }
+    let warnings: Vec<_> = sources
+        .into_iter()
+        .flat_map(|source| {
+            source.warnings.iter().map(|warning| {
+                let VersionInfo { krate, version, .. } = &source.version;
+                format!("Warning: {krate} v{version} {warning}")
+            })
+        })
+        .collect();
+    html! {
+        for warnings.into_iter().map(|warning| html! {
+            <div class="source-warning">{warning}</div>
+        })
+    }
+}
@@ -0,0 +1,320 @@
+use crate::{
+    components::*,
+    lockfile::{changes, parse, parse_diff, LockChange},
+    version::is_breaking,
+    Link, Route,
+};
+use anyhow::Result;
+use gloo_file::futures::read_as_text;
+use web_sys::{HtmlInputElement, HtmlTextAreaElement};
+use yew::{platform::spawn_local, prelude::*};
+
+/// Which input the lockfile changes are read from.
+#[derive(Clone, Copy, PartialEq)]
+enum LockfileInput {
+    /// Old and new `Cargo.lock` files
+    Files,
+    /// Unified diff of a `Cargo.lock` file
+    Diff,
+}
+
+/// Determine the package changes from the inputs.
+fn lockfile_changes(
+    input: LockfileInput,
+    old: &str,
+    new: &str,
+    diff: &str,
+) -> Option<Result<Vec<LockChange>, String>> {
+    match input {
+        LockfileInput::Files if !old.trim().is_empty() && !new.trim().is_empty() => {
+            let parse = |text: &str, name: &str| {
+                parse(text).map_err(|error| format!("Error parsing {name} lockfile: {error}"))
+            };
+            Some(parse(old, "old").and_then(|old| Ok(changes(&old, &parse(new, "new")?))))
+        }
+        LockfileInput::Diff if !diff.trim().is_empty() => {
+            let (old, new) = parse_diff(diff);
+            Some(Ok(changes(&old, &new)))
+        }
+        _ => None,
+    }
+}
+
+#[derive(Properties, PartialEq)]
+struct LockfileTextProps {
+    label: AttrValue,
+    placeholder: AttrValue,
+    accept: AttrValue,
+    value: UseStateHandle<String>,
+}
+
+/// Text area for a lockfile, which can also be filled by choosing a file.
+#[function_component]
+fn LockfileText(props: &LockfileTextProps) -> Html {
+    let oninput = {
+        let value = props.value.clone();
+        move |event: InputEvent| {
+            if let Some(input) = event.target_dyn_into::<HtmlTextAreaElement>() {
+                value.set(input.value());
+            }
+        }
+    };
+    let onchange = {
+        let value = props.value.clone();
+        move |event: Event| {
+            let file = event
+                .target_dyn_into::<HtmlInputElement>()
+                .and_then(|input| input.files())
+                .and_then(|files| files.get(0));
+            if let Some(file) = file {
+                let value = value.clone();
+                spawn_local(async move {
+                    match read_as_text(&gloo_file::File::from(file)).await {
+                        Ok(text) => value.set(text),
+                        Err(error) => value.set(format!("# Error reading file: {error}")),
+                    }
+                });
+            }
+        }
+    };
+    html! {
+        <div class="input">
+            <div class="input-header">
+                <span>{props.label.clone()}</span>
+                <label class="button">
+                    {"Choose file"}
+                    <input type="file" accept={props.accept.clone()} class="hidden" {onchange} />
+                </label>
+            </div>
+            <textarea
+                aria-label={props.label.clone()}
+                placeholder={props.placeholder.clone()}
+                spellcheck="false"
+                value={(*props.value).clone()}
+                {oninput}
+            />
+        </div>
+    }
+}
+
+/// Lockfile view, lists the packages which changed between two `Cargo.lock` files.
+#[function_component]
+pub fn Lockfile() -> Html {
+    let input = use_state(|| LockfileInput::Files);
+    let old = use_state(String::new);
+    let new = use_state(String::new);
+    let diff = use_state(String::new);
+    let result = use_memo(
+        (*input, (*old).clone(), (*new).clone(), (*diff).clone()),
+        |(input, old, new, diff)| lockfile_changes(*input, old, new, diff),
+    );
+
+    let tab = |value: LockfileInput, label: &'static str| {
+        let input = input.clone();
+        html! {
+            <button
+                class={classes!("tab", (*input == value).then_some("active"))}
+                onclick={move |_| input.set(value)}
+            >
+                {label}
+            </button>
+        }
+    };
+
+    let inputs = match *input {
+        LockfileInput::Files => html! {
+            <div class="inputs">
+                <LockfileText
+                    label="Old Cargo.lock"
+                    placeholder="Paste the old Cargo.lock here"
+                    accept=".lock"
+                    value={old.clone()}
+                />
+                <LockfileText
+                    label="New Cargo.lock"
+                    placeholder="Paste the new Cargo.lock here"
+                    accept=".lock"
+                    value={new.clone()}
+                />
+            </div>
+        },
+        LockfileInput::Diff => html! {
+            <div class="inputs">
+                <LockfileText
+                    label="Cargo.lock diff"
+                    placeholder="Paste the output of git diff Cargo.lock here"
+                    accept=".diff,.patch"
+                    value={diff.clone()}
+                />
+            </div>
+        },
+    };
+
+    let results = match &*result {
+        None => html! {},
+        Some(Err(error)) => html! {
+            <Error title={"Parsing lockfile"} status={error.clone()} />
+        },
+        Some(Ok(changes)) if changes.is_empty() => html! {
+            <p class="description">{"No packages were changed."}</p>
+        },
+        Some(Ok(changes)) => html! { <LockfileChanges changes={changes.clone()} /> },
+    };
+
+    html! {
+        <div class="flex flex-col min-h-screen">
+            <div class="flex-1">
+                <SimpleNavbar />
+                <Content>
+                    <section class="lockfile">
+                        <h2 class="title">{"Review a lockfile update"}</h2>
+                        <p class="description">
+                            {"Paste or choose the Cargo.lock from before and after an update, or a diff of it, to list the packages which changed. "}
+                            {"Every updated package links to the diff between its versions."}
+                        </p>
+                        <div class="tabs">
+                            {tab(LockfileInput::Files, "Two lockfiles")}
+                            {tab(LockfileInput::Diff, "Lockfile diff")}
+                        </div>
+                        {inputs}
+                        {results}
+                    </section>
+                </Content>
+            </div>
+            <Footer />
+        </div>
+    }
+}
+
+#[derive(Properties, PartialEq)]
+struct LockfileChangesProps {
+    changes: Vec<LockChange>,
+}
+
+/// Table of the package changes between two lockfiles.
+#[function_component]
+fn LockfileChanges(props: &LockfileChangesProps) -> Html {
+    let counts = use_reducer(LineCounts::default);
+    // only updated packages from crates.io can be diffed
+    let pairs: Vec<VersionPair> = props
+        .changes
+        .iter()
+        .filter(|change| change.registry)
+        .filter_map(|change| {
+            Some((
+                change.name.clone(),
+                change.old.clone()?,
+                change.new.clone()?,
+            ))
+        })
+        .collect();
+
+    let (added, removed, counted) = counts.total();
+    let total = match counted {
+        0 => html! { <span class="muted">{"not counted yet"}</span> },
+        counted => html! {
+            <>
+                <span class="added">{format!("+{added}")}</span>
+                {" "}
+                <span class="removed">{format!("-{removed}")}</span>
+                if counted < pairs.len() {
+                    <span class="muted">{format!(" ({counted} of {} packages)", pairs.len())}</span>
+                }
+            </>
+        },
+    };
+    let updated = pairs.len();
+    let count_all = {
+        let counts = counts.clone();
+        move |_| count_all_lines(pairs.clone(), &counts)
+    };
+
+    let rows = props.changes.iter().map(|change| {
+        let krate = change.name.clone();
+        let version = |version: &Option<semver::Version>| {
+            version
+                .as_ref()
+                .map(ToString::to_string)
+                .unwrap_or_default()
+        };
+        let (kind, link) = match (&change.old, &change.new) {
+            (Some(old), Some(new)) => (
+                is_breaking(old, new).then_some("breaking"),
+                Route::SingleSourceDiff {
+                    krate: krate.clone(),
+                    old_version: old.clone().into(),
+                    new_version: new.clone().into(),
+                },
+            ),
+            (None, Some(version)) => (
+                Some("added"),
+                Route::Browse {
+                    krate: krate.clone(),
+                    version: version.clone().into(),
+                },
+            ),
+            (Some(version), None) => (
+                Some("removed"),
+                Route::Browse {
+                    krate: krate.clone(),
+                    version: version.clone().into(),
+                },
+            ),
+            (None, None) => return html! {},
+        };
+        let changes = match (&change.old, &change.new) {
+            (Some(old), Some(new)) if change.registry => html! {
+                <LineCountView
+                    pair={(krate.clone(), old.clone(), new.clone())}
+                    counts={counts.clone()}
+                />
+            },
+            _ => html! {},
+        };
+        html! {
+            <tr key={format!("{krate} {} {}", version(&change.old), version(&change.new))}>
+                <td class="package">
+                    if change.registry {
+                        <Link to={link}>{krate.clone()}</Link>
+                    } else {
+                        <span title="Not published on crates.io">{krate.clone()}</span>
+                    }
+                    if let Some(kind) = kind {
+                        <span class={classes!("tag", kind)}>{kind}</span>
+                    }
+                </td>
+                <td class="version">{version(&change.old)}</td>
+                <td class="version">{version(&change.new)}</td>
+                <td class="changes">{changes}</td>
+            </tr>
+        }
+    });
+
+    html! {
+        <>
+            <div class="summary-line">
+                <span>{format!("{} packages changed, {updated} of them updated", props.changes.len())}</span>
+                <button class="button" onclick={count_all}>{"Count all changes"}</button>
+            </div>
+            <table>
+                <thead>
+                    <tr>
+                        <th>{"Package"}</th>
+                        <th>{"Old"}</th>
+                        <th>{"New"}</th>
+                        <th>{"Changes"}</th>
+                    </tr>
+                </thead>
+                <tbody>
+                    { for rows }
+                </tbody>
+                <tfoot>
+                    <tr>
+                        <td colspan="3">{"Total"}</td>
+                        <td class="changes">{total}</td>
+                    </tr>
+                </tfoot>
+            </table>
+        </>
+    }
+}
@@ -0,0 +1,231 @@
+use crate::{components::*, data::VersionDiff, patch, Route};
+use camino::Utf8PathBuf;
+use gloo_file::futures::read_as_text;
+use std::{cell::RefCell, rc::Rc};
+use web_sys::{HtmlInputElement, HtmlTextAreaElement};
+use yew::{platform::spawn_local, prelude::*};
+use yew_router::prelude::*;
+
+/// Patch which was imported by the user.
+struct ImportedPatch {
+    diff: Rc<VersionDiff>,
+    /// Binary files changed by the patch, which cannot be shown
+    binary: Vec<Utf8PathBuf>,
+}
+
+thread_local! {
+    /// Patches imported by the user, indexed by their patch id.
+    ///
+    /// Patches are only kept in memory, they are lost when the page is reloaded.
+    static PATCHES: RefCell<Vec<Rc<ImportedPatch>>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Parse and register a patch, returning its patch id.
+fn register_patch(text: &str) -> anyhow::Result<usize> {
+    let files = patch::parse(text)?;
+    let patch = ImportedPatch {
+        diff: Rc::new(patch::version_diff(&files)?),
+        binary: files
+            .iter()
+            .filter(|file| file.binary)
+            .filter_map(|file| file.path().map(Into::into))
+            .collect(),
+    };
+    Ok(PATCHES.with_borrow_mut(|patches| {
+        patches.push(Rc::new(patch));
+        patches.len() - 1
+    }))
+}
+
+/// Look up an imported patch by its patch id.
+fn lookup_patch(id: usize) -> Option<Rc<ImportedPatch>> {
+    PATCHES.with_borrow(|patches| patches.get(id).cloned())
+}
+
+/// Read a dropped or chosen patch file into the text.
+fn read_patch(file: web_sys::File, text: UseStateHandle<String>) {
+    spawn_local(async move {
+        match read_as_text(&gloo_file::File::from(file)).await {
+            Ok(value) => text.set(value),
+            Err(error) => text.set(format!("Error reading file: {error}")),
+        }
+    });
+}
+
+/// Patch view, allows viewing a unified diff like a diff between crate versions.
+#[function_component]
+pub fn Patch() -> Html {
+    let text = use_state(String::new);
+    let error = use_state(|| None::<String>);
+    let dragging = use_state(|| false);
+    let navigator = use_navigator().unwrap();
+
+    let oninput = {
+        let text = text.clone();
+        move |event: InputEvent| {
+            if let Some(input) = event.target_dyn_into::<HtmlTextAreaElement>() {
+                text.set(input.value());
+            }
+        }
+    };
+    let onchange = {
+        let text = text.clone();
+        move |event: Event| {
+            let file = event
+                .target_dyn_into::<HtmlInputElement>()
+                .and_then(|input| input.files())
+                .and_then(|files| files.get(0));
+            if let Some(file) = file {
+                read_patch(file, text.clone());
+            }
+        }
+    };
+    let ondragover = {
+        let dragging = dragging.clone();
+        move |event: DragEvent| {
+            event.prevent_default();
+            dragging.set(true);
+        }
+    };
+    let ondragleave = {
+        let dragging = dragging.clone();
+        move |_: DragEvent| dragging.set(false)
+    };
+    let ondrop = {
+        let dragging = dragging.clone();
+        let text = text.clone();
+        move |event: DragEvent| {
+            event.prevent_default();
+            dragging.set(false);
+            let file = event
+                .data_transfer()
+                .and_then(|transfer| transfer.files())
+                .and_then(|files| files.get(0));
+            if let Some(file) = file {
+                read_patch(file, text.clone());
+            }
+        }
+    };
+    let onclick = {
+        let text = text.clone();
+        let error = error.clone();
+        move |_| match register_patch(&text) {
+            Ok(id) => navigator.push(&Route::PatchDiff { id }),
+            Err(error_) => error.set(Some(format!("Error: {error_:#}"))),
+        }
+    };
+
+    html! {
+        <div class="flex flex-col min-h-screen">
+            <div class="flex-1">
+                <SimpleNavbar />
+                <Content>
+                    <section class="patch">
+                        <h2 class="title">{"View a patch"}</h2>
+                        <p class="description">
+                            {"Paste or drop a unified diff, such as the output of git diff, to view it with syntax highlighting and a file tree. "}
+                            {"Patches only contain the lines around the changes, other lines are left blank. "}
+                            {"Patches are only kept while the page is open."}
+                        </p>
+                        <div
+                            class={classes!("input", dragging.then_some("active"))}
+                            {ondragover}
+                            {ondragleave}
+                            {ondrop}
+                        >
+                            <div class="input-header">
+                                <span>{"Patch"}</span>
+                                <label class="button">
+                                    {"Choose file"}
+                                    <input type="file" accept=".patch,.diff,text/x-diff,text/plain" class="hidden" {onchange} />
+                                </label>
+                            </div>
+                            <textarea
+                                aria-label="Patch"
+                                placeholder="diff --git a/src/lib.rs b/src/lib.rs"
+                                spellcheck="false"
+                                value={(*text).clone()}
+                                {oninput}
+                            />
+                        </div>
+                        <button class="button submit" {onclick} disabled={text.trim().is_empty()}>
+                            {"View patch"}
+                        </button>
+                        if let Some(status) = &*error {
+                            <Error title={"Parsing patch"} status={status.clone()} />
+                        }
+                    </section>
+                </Content>
+            </div>
+            <Footer />
+        </div>
+    }
+}
+
+/// Props for which file of an imported patch to show.
+#[derive(Properties, PartialEq, Clone)]
+pub struct PatchDiffProps {
+    pub id: usize,
+    #[prop_or_default]
+    pub path: Option<Utf8PathBuf>,
+}
+
+/// Show the diff of a file changed by an imported patch.
+#[function_component]
+pub fn PatchDiff(props: &PatchDiffProps) -> Html {
+    let Some(patch) = lookup_patch(props.id) else {
+        return html! {
+            <>
+                <SimpleNavbar />
+                <Content>
+                    <Center>
+                        <Error
+                            title={"Patch not found"}
+                            status={"Patches are only kept while the page is open, please import the patch again."}
+                        />
+                    </Center>
+                </Content>
+            </>
+        };
+    };
+    let id = props.id;
+    let diff = patch.diff.clone();
+    let Some(path) = props.path.clone() else {
+        // show the first changed file
+        let path = diff
+            .tree_files(true)
+            .into_iter()
+            .map(|(path, _)| path)
+            .next()
+            .or_else(|| patch.binary.first().cloned())
+            .unwrap_or_default();
+        return html! {
+            <Redirect<Route> to={Route::PatchFile { id, path }} />
+        };
+    };
+    let file_route = Callback::from(move |path| Route::PatchFile { id, path });
+
+    html! {
+        <>
+            <SimpleNavbar />
+            <Content>
+                <main class="flex flex-col md:flex-row gap-2 lg:gap-4 p-2">
+                    <nav id="files" class="md:w-72 lg:w-84 xl:w-96" aria-label="Files">
+                        <FileTree diff={diff.clone()} path={path.clone()} {file_route} />
+                    </nav>
+                    <div id="diff-view" class="flex-1">
+                        if !patch.binary.is_empty() {
+                            <div class="patch-binary">
+                                {format!("{} binary files changed by the patch are not shown: ", patch.binary.len())}
+                                <span class="paths">
+                                    {patch.binary.iter().map(|path| path.as_str()).collect::<Vec<_>>().join(", ")}
+                                </span>
+                            </div>
+                        }
+                        <DiffView {diff} {path} />
+                    </div>
+                </main>
+            </Content>
+        </>
+    }
+}
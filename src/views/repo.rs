@@ -3,6 +3,7 @@ use crate::{
     components::*,
     data::{CrateResponse, CrateSource, RepositoryInfo, VersionDiff, VersionInfo},
     version::VersionId,
+    worker::fetch_source,
 };
 use camino::Utf8PathBuf;
 use std::{rc::Rc, sync::Arc};
@@ -112,7 +113,7 @@ fn CrateSourceFetcher(props: &CrateSourceFetcherProps) -> Html {
 #[function_component]
 fn CrateSourceFetcherInner(props: &CrateSourceFetcherProps) -> HtmlResult {
     let source = use_future_with(props.version.clone(), |version| async move {
-        CRATE_SOURCE_CACHE.fetch_cached(&version).await
+        fetch_source(&version).await
     })?;
 
     let source = match &*source {
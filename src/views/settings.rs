@@ -0,0 +1,486 @@
+use crate::{
+    cache::{
+        now, CacheEntry, CacheStats, CrateSourceCache, EntryKind, CRATE_RESPONSE_CACHE,
+        CRATE_SOURCE_CACHE, PERSISTENT_CACHE,
+    },
+    components::*,
+    data::ArchiveLimits,
+    syntax::{theme_names, DEFAULT_DARK_THEME, DEFAULT_LIGHT_THEME},
+    theme::{restore_theme, set_theme_settings, use_theme_settings, ColorScheme, ThemeSettings},
+};
+use web_sys::{HtmlSelectElement, Storage};
+use yew::{platform::spawn_local, prelude::*, suspense::*};
+
+/// Local storage key for the crate source cache budget.
+const SOURCE_CACHE_BUDGET: &str = "diff-rs.source-cache-budget";
+
+/// Budgets which can be selected for the crate source cache.
+const SOURCE_CACHE_BUDGETS: [usize; 5] = [
+    64 * 1024 * 1024,
+    128 * 1024 * 1024,
+    256 * 1024 * 1024,
+    512 * 1024 * 1024,
+    1024 * 1024 * 1024,
+];
+
+/// Local storage key for the limits applied to crate archives.
+const ARCHIVE_LIMITS: &str = "diff-rs.archive-limits";
+
+/// Sizes which can be selected for the largest file which is diffed without asking.
+const MAX_DIFF_SIZES: [u64; 5] = [
+    512 * 1024,
+    1024 * 1024,
+    2 * 1024 * 1024,
+    8 * 1024 * 1024,
+    32 * 1024 * 1024,
+];
+
+/// Sizes which can be selected for the largest compressed crate archive.
+const MAX_COMPRESSED_SIZES: [u64; 4] = [
+    16 * 1024 * 1024,
+    64 * 1024 * 1024,
+    256 * 1024 * 1024,
+    1024 * 1024 * 1024,
+];
+
+/// Sizes which can be selected for the largest uncompressed crate archive.
+const MAX_UNCOMPRESSED_SIZES: [u64; 4] = [
+    128 * 1024 * 1024,
+    512 * 1024 * 1024,
+    1024 * 1024 * 1024,
+    2048 * 1024 * 1024,
+];
+
+fn local_storage() -> Option<Storage> {
+    web_sys::window()?.local_storage().ok()?
+}
+
+/// Apply settings persisted in local storage.
+///
+/// This should be called once when the application starts.
+pub fn restore_settings() {
+    restore_theme();
+    let budget = local_storage()
+        .and_then(|storage| storage.get_item(SOURCE_CACHE_BUDGET).ok()?)
+        .and_then(|budget| budget.parse().ok());
+    if let Some(budget) = budget {
+        CRATE_SOURCE_CACHE.set_budget(budget);
+    }
+    let limits = local_storage()
+        .and_then(|storage| storage.get_item(ARCHIVE_LIMITS).ok()?)
+        .and_then(|limits| serde_json::from_str(&limits).ok());
+    if let Some(limits) = limits {
+        ArchiveLimits::set_current(limits);
+    }
+}
+
+/// Format a size in bytes as a human-readable string.
+pub(crate) fn format_size(size: u64) -> String {
+    const UNITS: [&str; 4] = ["B", "KiB", "MiB", "GiB"];
+    let mut size = size as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{size} {}", UNITS[unit])
+    } else {
+        format!("{size:.1} {}", UNITS[unit])
+    }
+}
+
+/// Format a duration in milliseconds as a human-readable age.
+fn format_age(millis: u64) -> String {
+    match millis / 1000 {
+        seconds @ 0..60 => format!("{seconds}s ago"),
+        seconds @ 60..3600 => format!("{}m ago", seconds / 60),
+        seconds @ 3600..86400 => format!("{}h ago", seconds / 3600),
+        seconds => format!("{}d ago", seconds / 86400),
+    }
+}
+
+#[derive(Properties, PartialEq)]
+struct CacheEntryRowProps {
+    entry: CacheEntry,
+    now: u64,
+    onremove: Callback<String>,
+}
+
+#[function_component]
+fn CacheEntryRow(props: &CacheEntryRowProps) -> Html {
+    let entry = &props.entry;
+    let onclick = {
+        let onremove = props.onremove.clone();
+        let key = entry.key.clone();
+        move |_| onremove.emit(key.clone())
+    };
+    let kind = match entry.kind {
+        EntryKind::Metadata => "metadata",
+        EntryKind::Source => "source",
+    };
+    html! {
+        <tr>
+            <td class="key">{&entry.key}</td>
+            <td>{kind}</td>
+            <td>{format_size(entry.size)}</td>
+            <td>{format_age(props.now.saturating_sub(entry.stored))}</td>
+            <td>{format_age(props.now.saturating_sub(entry.accessed))}</td>
+            <td><button class="button" {onclick}>{"Remove"}</button></td>
+        </tr>
+    }
+}
+
+#[derive(Properties, PartialEq)]
+struct CacheTableProps {
+    generation: UseStateHandle<usize>,
+}
+
+#[function_component]
+fn CacheTable(props: &CacheTableProps) -> HtmlResult {
+    let entries = use_future_with(*props.generation, |_| async move {
+        PERSISTENT_CACHE.entries().await
+    })?;
+
+    let entries = match &*entries {
+        Ok(entries) => entries,
+        Err(error) => {
+            return Ok(html! {
+                <Error title={"Loading cache"} status={format!("Error: {error}")} />
+            })
+        }
+    };
+
+    let refresh = {
+        let generation = props.generation.clone();
+        move || generation.set(*generation + 1)
+    };
+    let onremove = {
+        let refresh = refresh.clone();
+        Callback::from(move |key: String| {
+            let refresh = refresh.clone();
+            spawn_local(async move {
+                if let Err(error) = PERSISTENT_CACHE.remove(&key).await {
+                    log::error!("Error removing {key} from persistent cache: {error}");
+                }
+                refresh();
+            });
+        })
+    };
+    let onclear = move |_| {
+        let refresh = refresh.clone();
+        spawn_local(async move {
+            if let Err(error) = PERSISTENT_CACHE.clear().await {
+                log::error!("Error clearing persistent cache: {error}");
+            }
+            refresh();
+        });
+    };
+
+    let total: u64 = entries.iter().map(|entry| entry.size).sum();
+    let limit = PERSISTENT_CACHE.limits().max_bytes;
+    let now = now();
+
+    Ok(html! {
+        <>
+            <div class="summary-line">
+                <span>
+                    {format!("{} entries, using {} of {}", entries.len(), format_size(total), format_size(limit))}
+                </span>
+                <button class="button" onclick={onclear} disabled={entries.is_empty()}>{"Clear cache"}</button>
+            </div>
+            if !entries.is_empty() {
+                <table>
+                    <thead>
+                        <tr>
+                            <th>{"Key"}</th>
+                            <th>{"Kind"}</th>
+                            <th>{"Size"}</th>
+                            <th>{"Stored"}</th>
+                            <th>{"Last used"}</th>
+                            <th></th>
+                        </tr>
+                    </thead>
+                    <tbody>
+                    {
+                        for entries.iter().map(|entry| html! {
+                            <CacheEntryRow
+                                key={entry.key.clone()}
+                                entry={entry.clone()}
+                                {now}
+                                onremove={onremove.clone()}
+                            />
+                        })
+                    }
+                    </tbody>
+                </table>
+            }
+        </>
+    })
+}
+
+#[derive(Properties, PartialEq)]
+struct MemoryCacheRowProps {
+    name: AttrValue,
+    stats: CacheStats,
+}
+
+#[function_component]
+fn MemoryCacheRow(props: &MemoryCacheRowProps) -> Html {
+    let stats = &props.stats;
+    html! {
+        <tr>
+            <td>{&props.name}</td>
+            <td>{stats.entries}</td>
+            <td>{format!("{} of {}", format_size(stats.bytes as u64), format_size(stats.budget as u64))}</td>
+            <td>{stats.hits}</td>
+            <td>{stats.misses}</td>
+            <td>{stats.evictions}</td>
+        </tr>
+    }
+}
+
+#[function_component]
+fn MemoryCache() -> Html {
+    let source_stats = use_state(|| CRATE_SOURCE_CACHE.stats());
+    let onchange = {
+        let source_stats = source_stats.clone();
+        move |event: Event| {
+            let target: HtmlSelectElement = event.target_unchecked_into();
+            let Ok(budget) = target.value().parse() else {
+                return;
+            };
+            CRATE_SOURCE_CACHE.set_budget(budget);
+            if let Some(storage) = local_storage() {
+                let _ = storage.set_item(SOURCE_CACHE_BUDGET, &budget.to_string());
+            }
+            source_stats.set(CRATE_SOURCE_CACHE.stats());
+        }
+    };
+
+    html! {
+        <>
+            <div class="summary-line">
+                <label for="source-cache-budget">{"Memory budget for crate sources"}</label>
+                <select id="source-cache-budget" {onchange}>
+                {
+                    for SOURCE_CACHE_BUDGETS.iter().map(|budget| html! {
+                        <option
+                            value={budget.to_string()}
+                            selected={*budget == source_stats.budget}
+                        >
+                            {format_size(*budget as u64)}
+                            if *budget == CrateSourceCache::DEFAULT_BUDGET {
+                                {" (default)"}
+                            }
+                        </option>
+                    })
+                }
+                </select>
+            </div>
+            <table>
+                <thead>
+                    <tr>
+                        <th>{"Cache"}</th>
+                        <th>{"Entries"}</th>
+                        <th>{"Size"}</th>
+                        <th>{"Hits"}</th>
+                        <th>{"Misses"}</th>
+                        <th>{"Evictions"}</th>
+                    </tr>
+                </thead>
+                <tbody>
+                    <MemoryCacheRow name="Crate metadata" stats={CRATE_RESPONSE_CACHE.stats()} />
+                    <MemoryCacheRow name="Crate sources" stats={*source_stats} />
+                </tbody>
+            </table>
+        </>
+    }
+}
+
+/// Select for a size limit of crate archives.
+fn limit_select(
+    id: &'static str,
+    sizes: &[u64],
+    get: fn(&ArchiveLimits) -> u64,
+    set: fn(&mut ArchiveLimits, u64),
+    limits: &UseStateHandle<ArchiveLimits>,
+) -> Html {
+    let onchange = {
+        let limits = limits.clone();
+        move |event: Event| {
+            let target: HtmlSelectElement = event.target_unchecked_into();
+            let Ok(size) = target.value().parse() else {
+                return;
+            };
+            let mut current = *limits;
+            set(&mut current, size);
+            ArchiveLimits::set_current(current);
+            if let (Some(storage), Ok(value)) = (local_storage(), serde_json::to_string(&current)) {
+                let _ = storage.set_item(ARCHIVE_LIMITS, &value);
+            }
+            limits.set(current);
+        }
+    };
+    let selected = get(limits);
+    let default = get(&ArchiveLimits::DEFAULT);
+    html! {
+        <select {id} {onchange}>
+        {
+            for sizes.iter().map(|size| html! {
+                <option value={size.to_string()} selected={*size == selected}>
+                    {format_size(*size)}
+                    if *size == default {
+                        {" (default)"}
+                    }
+                </option>
+            })
+        }
+        </select>
+    }
+}
+
+#[function_component]
+fn Limits() -> Html {
+    let limits = use_state(ArchiveLimits::current);
+    html! {
+        <>
+            <div class="summary-line">
+                <label for="max-diff-size">{"Largest file which is diffed without asking"}</label>
+                {limit_select("max-diff-size", &MAX_DIFF_SIZES, |limits| limits.max_diff_size, |limits, size| limits.max_diff_size = size, &limits)}
+            </div>
+            <div class="summary-line">
+                <label for="max-compressed-size">{"Largest crate archive"}</label>
+                {limit_select("max-compressed-size", &MAX_COMPRESSED_SIZES, |limits| limits.max_compressed_size, |limits, size| limits.max_compressed_size = size, &limits)}
+            </div>
+            <div class="summary-line">
+                <label for="max-uncompressed-size">{"Largest unpacked crate"}</label>
+                {limit_select("max-uncompressed-size", &MAX_UNCOMPRESSED_SIZES, |limits| limits.max_uncompressed_size, |limits, size| limits.max_uncompressed_size = size, &limits)}
+            </div>
+        </>
+    }
+}
+
+/// Select for the syntax theme of a color scheme.
+fn theme_select(
+    id: &'static str,
+    selected: &str,
+    default: &str,
+    set: fn(&mut ThemeSettings, String),
+    settings: &ThemeSettings,
+) -> Html {
+    let onchange = {
+        let settings = settings.clone();
+        move |event: Event| {
+            let target: HtmlSelectElement = event.target_unchecked_into();
+            let mut settings = settings.clone();
+            set(&mut settings, target.value());
+            set_theme_settings(settings);
+        }
+    };
+    html! {
+        <select {id} {onchange}>
+        {
+            for theme_names().map(|name| html! {
+                <option value={name} selected={name == selected}>
+                    {name}
+                    if name == default {
+                        {" (default)"}
+                    }
+                </option>
+            })
+        }
+        </select>
+    }
+}
+
+#[function_component]
+fn Theme() -> Html {
+    let settings = use_theme_settings();
+    let onchange = {
+        let settings = settings.clone();
+        move |event: Event| {
+            let target: HtmlSelectElement = event.target_unchecked_into();
+            let Some(scheme) = ColorScheme::from_key(&target.value()) else {
+                return;
+            };
+            set_theme_settings(ThemeSettings {
+                scheme,
+                ..settings.clone()
+            });
+        }
+    };
+
+    html! {
+        <>
+            <div class="summary-line">
+                <label for="color-scheme">{"Color scheme"}</label>
+                <select id="color-scheme" {onchange}>
+                {
+                    for ColorScheme::ALL.into_iter().map(|scheme| html! {
+                        <option value={scheme.key()} selected={scheme == settings.scheme}>
+                            {scheme.name()}
+                        </option>
+                    })
+                }
+                </select>
+            </div>
+            <div class="summary-line">
+                <label for="light-theme">{"Syntax theme for the light color scheme"}</label>
+                {theme_select("light-theme", &settings.light_theme, DEFAULT_LIGHT_THEME, |settings, theme| settings.light_theme = theme, &settings)}
+            </div>
+            <div class="summary-line">
+                <label for="dark-theme">{"Syntax theme for the dark color scheme"}</label>
+                {theme_select("dark-theme", &settings.dark_theme, DEFAULT_DARK_THEME, |settings, theme| settings.dark_theme = theme, &settings)}
+            </div>
+        </>
+    }
+}
+
+/// Settings page, allows choosing the theme and limits, and inspecting and clearing the persistent
+/// cache.
+#[function_component]
+pub fn Settings() -> Html {
+    let generation = use_state(|| 0);
+    let ttl = PERSISTENT_CACHE.limits().metadata_ttl / 60_000;
+    let fallback = html! {
+        <Loading title={"Loading cache"} status={"Loading cache entries"} />
+    };
+    html! {
+        <div class="flex flex-col min-h-screen">
+            <div class="flex-1">
+                <SimpleNavbar />
+                <Content>
+                    <section class="settings">
+                        <h2 class="title">{"Theme"}</h2>
+                        <p class="description">
+                            {"The color scheme follows the preference of your browser unless chosen here, highlighted code uses the syntax theme of the color scheme."}
+                        </p>
+                        <Theme />
+                        <h2 class="title">{"Memory"}</h2>
+                        <p class="description">
+                            {"Crate metadata and unpacked crate sources are kept in memory while the page is open. "}
+                            {"When the budget is exceeded, the least recently used crate sources are dropped."}
+                        </p>
+                        <MemoryCache />
+                        <h2 class="title">{"Limits"}</h2>
+                        <p class="description">
+                            {"Crates which exceed these limits are not unpacked, to keep the page responsive. "}
+                            {"Changed files which are too large are only diffed when you ask for it, their changes are estimated by counting all of their lines as changed."}
+                        </p>
+                        <Limits />
+                        <h2 class="title">{"Cache"}</h2>
+                        <p class="description">
+                            {"Crate metadata and crate sources are stored in your browser, so that they do not need to be downloaded again. "}
+                            {format!("Crate sources never change once published and are kept until the cache is full, crate metadata is refreshed after {ttl} minutes.")}
+                        </p>
+                        <Suspense {fallback}>
+                            <CacheTable {generation} />
+                        </Suspense>
+                    </section>
+                </Content>
+            </div>
+            <Footer />
+        </div>
+    }
+}
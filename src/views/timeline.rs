@@ -0,0 +1,217 @@
+use super::settings::format_size;
+use crate::{
+    cache::CRATE_RESPONSE_CACHE, components::*, data::*, version::is_breaking, Link, Route,
+};
+use std::sync::Arc;
+use yew::{prelude::*, suspense::*};
+
+/// Props for the crate to show the timeline of.
+#[derive(Properties, PartialEq, Clone)]
+pub struct TimelineProps {
+    pub krate: String,
+}
+
+/// Timeline view, lists all versions of a crate and allows picking two of them to diff.
+#[function_component]
+pub fn Timeline(props: &TimelineProps) -> Html {
+    let fallback = html! {
+        <Center>
+            <Loading title={"Loading crate"} status={"Loading crate metadata"} />
+        </Center>
+    };
+    html! {
+        <div class="flex flex-col min-h-screen">
+            <div class="flex-1">
+                <SimpleNavbar />
+                <Content>
+                    <Suspense {fallback}>
+                        <TimelineFetcher krate={props.krate.clone()} />
+                    </Suspense>
+                </Content>
+            </div>
+            <Footer />
+        </div>
+    }
+}
+
+#[function_component]
+fn TimelineFetcher(props: &TimelineProps) -> HtmlResult {
+    let info = use_future_with(props.krate.clone(), |krate| async move {
+        CRATE_RESPONSE_CACHE.fetch_cached(&krate).await
+    })?;
+    Ok(match &*info {
+        Ok(info) => html! { <TimelineTable info={info.clone()} /> },
+        Err(error) => html! {
+            <Center>
+                <Error
+                    title={"Loading crate"}
+                    status={format!("Error: {} with {error}", props.krate)}
+                />
+            </Center>
+        },
+    })
+}
+
+#[derive(Properties, PartialEq)]
+struct TimelineTableProps {
+    info: Arc<CrateResponse>,
+}
+
+#[function_component]
+fn TimelineTable(props: &TimelineTableProps) -> Html {
+    let krate = props.info.krate.id.clone();
+    let versions = use_memo(props.info.clone(), |info| {
+        let mut versions = info.versions.clone();
+        versions.sort_by(|left, right| right.version.cmp(&left.version));
+        versions
+    });
+    // every version is diffed against its predecessor, the next older version
+    let pairs: Vec<VersionPair> = versions
+        .windows(2)
+        .map(|pair| {
+            (
+                krate.clone(),
+                pair[1].version.clone(),
+                pair[0].version.clone(),
+            )
+        })
+        .collect();
+
+    // versions picked for diffing, defaults to the latest version and its predecessor
+    let selected = use_state({
+        let versions = versions.clone();
+        move || {
+            let version = |index: usize| versions.get(index).map(|info| info.version.clone());
+            (version(1).or(version(0)), version(0))
+        }
+    });
+    let counts = use_reducer(LineCounts::default);
+
+    let count_all = {
+        let counts = counts.clone();
+        move |_| count_all_lines(pairs.clone(), &counts)
+    };
+
+    let compare = match &*selected {
+        (Some(old), Some(new)) => html! {
+            <Link
+                classes="button"
+                to={Route::SingleSourceDiff {
+                    krate: krate.clone(),
+                    old_version: old.clone().into(),
+                    new_version: new.clone().into(),
+                }}
+            >
+                {format!("Compare {old} and {new}")}
+            </Link>
+        },
+        _ => html! {
+            <button class="button" disabled={true}>{"Compare"}</button>
+        },
+    };
+
+    let rows = versions.iter().enumerate().map(|(index, info)| {
+        let previous = versions.get(index + 1);
+        let version = info.version.clone();
+        let breaking = previous.is_some_and(|previous| is_breaking(&previous.version, &version));
+        let pick = |old: bool| {
+            let selected = selected.clone();
+            let version = version.clone();
+            let checked = if old { &selected.0 } else { &selected.1 }.as_ref() == Some(&version);
+            let label = format!("Pick {version} as {} version", if old { "old" } else { "new" });
+            let onchange = move |_| {
+                let (mut picked_old, mut picked_new) = (*selected).clone();
+                if old {
+                    picked_old = Some(version.clone());
+                } else {
+                    picked_new = Some(version.clone());
+                }
+                selected.set((picked_old, picked_new));
+            };
+            html! {
+                <input
+                    type="radio"
+                    name={if old { "old" } else { "new" }}
+                    aria-label={label}
+                    {checked}
+                    {onchange}
+                />
+            }
+        };
+        let changes = match previous {
+            None => html! { <span class="muted">{"first release"}</span> },
+            Some(previous) => html! {
+                <LineCountView
+                    pair={(krate.clone(), previous.version.clone(), version.clone())}
+                    counts={counts.clone()}
+                />
+            },
+        };
+        let link = match previous {
+            Some(previous) => html! {
+                <Link to={Route::SingleSourceDiff {
+                    krate: krate.clone(),
+                    old_version: previous.version.clone().into(),
+                    new_version: version.clone().into(),
+                }}>
+                    {version.to_string()}
+                </Link>
+            },
+            None => html! {
+                <Link to={Route::Browse {
+                    krate: krate.clone(),
+                    version: version.clone().into(),
+                }}>
+                    {version.to_string()}
+                </Link>
+            },
+        };
+        html! {
+            <tr key={version.to_string()} class={classes!(breaking.then_some("breaking"), info.yanked.then_some("yanked"))}>
+                <td>{pick(true)}</td>
+                <td>{pick(false)}</td>
+                <td class="version">
+                    {link}
+                    if breaking {
+                        <span class="tag breaking">{"breaking"}</span>
+                    }
+                    if info.yanked {
+                        <span class="tag yanked">{"yanked"}</span>
+                    }
+                </td>
+                <td>{info.published().unwrap_or_default()}</td>
+                <td>{info.crate_size.map(format_size).unwrap_or_default()}</td>
+                <td class="changes">{changes}</td>
+            </tr>
+        }
+    });
+
+    html! {
+        <section class="timeline">
+            <h2 class="title">{format!("Versions of {krate}")}</h2>
+            <p class="description">
+                {"Pick an old and a new version to compare them, or follow a version to compare it against its predecessor. "}
+                {"Versions which are not semver-compatible with their predecessor are marked as breaking."}
+            </p>
+            <div class="summary-line">
+                {compare}
+                <button class="button" onclick={count_all}>{"Count all changes"}</button>
+            </div>
+            <table>
+                <thead>
+                    <tr>
+                        <th>{"Old"}</th>
+                        <th>{"New"}</th>
+                        <th>{"Version"}</th>
+                        <th>{"Published"}</th>
+                        <th>{"Size"}</th>
+                        <th>{"Changes"}</th>
+                    </tr>
+                </thead>
+                <tbody>
+                    { for rows }
+                </tbody>
+            </table>
+        </section>
+    }
+}
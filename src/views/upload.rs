@@ -0,0 +1,457 @@
+use super::diff::{SourceFetcher, VersionChange};
+use crate::{
+    cache::CRATE_RESPONSE_CACHE,
+    components::*,
+    data::*,
+    version::{VersionId, VersionNamed},
+    Route,
+};
+use anyhow::{anyhow, bail, Result};
+use bytes::Bytes;
+use camino::Utf8PathBuf;
+use futures::channel::oneshot;
+use gloo_file::futures::read_as_bytes;
+use js_sys::{Array, Function, Reflect};
+use std::{cell::RefCell, future::Future, rc::Rc, sync::Arc};
+use wasm_bindgen::{prelude::*, JsCast};
+use web_sys::{
+    DataTransfer, File, FileList, FileSystemDirectoryEntry, FileSystemEntry, FileSystemFileEntry,
+    HtmlInputElement,
+};
+use yew::{platform::spawn_local, prelude::*, suspense::*};
+use yew_router::prelude::*;
+
+thread_local! {
+    /// Crate sources uploaded by the user, indexed by their upload id.
+    ///
+    /// Uploads are only kept in memory, they are lost when the page is reloaded.
+    static UPLOADS: RefCell<Vec<Arc<CrateSource>>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Register an uploaded crate source, returning its upload id.
+fn register_upload(source: CrateSource) -> usize {
+    UPLOADS.with_borrow_mut(|uploads| {
+        uploads.push(Arc::new(source));
+        uploads.len() - 1
+    })
+}
+
+/// Look up an uploaded crate source by its upload id.
+fn lookup_upload(id: usize) -> Option<Arc<CrateSource>> {
+    UPLOADS.with_borrow(|uploads| uploads.get(id).cloned())
+}
+
+/// Determines if a path in a crate directory is not part of the crate sources.
+fn is_ignored(path: &str) -> bool {
+    path.split('/').next() == Some("target") || path.split('/').any(|component| component == ".git")
+}
+
+/// Path of a file in an uploaded directory relative to the crate root, or `None` if the file is
+/// not part of the crate sources.
+///
+/// Paths of uploaded files start with the name of the uploaded directory, which is stripped.
+fn crate_path(path: &str) -> Option<&str> {
+    let (_, path) = path.split_once('/')?;
+    (!is_ignored(path)).then_some(path)
+}
+
+/// Call a function which reports its result using a success and an error callback.
+async fn with_callbacks<T: JsCast>(
+    call: impl FnOnce(&Function, &Function) -> Result<(), JsValue>,
+) -> Result<T> {
+    let (sender, receiver) = oneshot::channel();
+    let sender = Rc::new(RefCell::new(Some(sender)));
+    let respond = |result: bool| {
+        let sender = sender.clone();
+        Closure::once(move |value: JsValue| {
+            if let Some(sender) = sender.borrow_mut().take() {
+                let _ = sender.send(if result { Ok(value) } else { Err(value) });
+            }
+        })
+    };
+    let success = respond(true);
+    let error = respond(false);
+    call(
+        success.as_ref().unchecked_ref(),
+        error.as_ref().unchecked_ref(),
+    )
+    .map_err(|error| anyhow!("{error:?}"))?;
+    receiver
+        .await?
+        .map_err(|error| anyhow!("{error:?}"))?
+        .dyn_into()
+        .map_err(|value| anyhow!("unexpected callback value {value:?}"))
+}
+
+/// Read the contents of a file.
+async fn read_file(file: File) -> Result<Bytes> {
+    let data = read_as_bytes(&gloo_file::File::from(file)).await?;
+    Ok(data.into())
+}
+
+/// Parse an uploaded crate archive, as created by `cargo package`.
+async fn read_archive(file: File) -> Result<CrateSource> {
+    let data = read_file(file).await?;
+    let mut decoder = ArchiveDecoder::with_limits(ArchiveLimits::current());
+    decoder.write(&data)?;
+    Ok(decoder.finish_unverified()?)
+}
+
+/// Parse an uploaded crate directory, given its files and their paths.
+async fn read_directory(files: Vec<(String, File)>) -> Result<CrateSource> {
+    let mut entries = vec![];
+    for (path, file) in files {
+        if let Some(path) = crate_path(&path) {
+            entries.push((path.to_string(), read_file(file).await?));
+        }
+    }
+    Ok(CrateSource::from_directory_unverified(
+        entries,
+        &ArchiveLimits::current(),
+    )?)
+}
+
+/// Collect all files of a dropped directory, skipping directories which are not part of the crate
+/// sources.
+async fn walk_directory(root: FileSystemDirectoryEntry) -> Result<Vec<(String, File)>> {
+    let mut files = vec![];
+    let mut directories = vec![(root.name(), root)];
+    while let Some((path, directory)) = directories.pop() {
+        let reader = directory.create_reader();
+        // entries are returned in batches, until an empty batch is returned
+        loop {
+            let entries: Array = with_callbacks(|success, error| {
+                reader.read_entries_with_callback_and_callback(success, error)
+            })
+            .await?;
+            if entries.length() == 0 {
+                break;
+            }
+            for entry in entries.iter() {
+                let entry: FileSystemEntry = entry.unchecked_into();
+                let path = format!("{path}/{}", entry.name());
+                if crate_path(&path).is_none() {
+                    continue;
+                }
+                if entry.is_directory() {
+                    directories.push((path, entry.unchecked_into()));
+                } else {
+                    let entry: FileSystemFileEntry = entry.unchecked_into();
+                    let file = with_callbacks(|success, error| {
+                        entry.file_with_callback_and_callback(success, error);
+                        Ok(())
+                    })
+                    .await?;
+                    files.push((path, file));
+                }
+            }
+        }
+    }
+    Ok(files)
+}
+
+/// Parse the crate archive or crate directory which was dropped.
+///
+/// Entries need to be taken from the [`DataTransfer`] while handling the drop event, they are not
+/// accessible afterwards.
+fn read_dropped(transfer: DataTransfer) -> impl Future<Output = Result<CrateSource>> {
+    let items = transfer.items();
+    let entries: Vec<FileSystemEntry> = (0..items.length())
+        .filter_map(|index| items.get(index)?.webkit_get_as_entry().ok()?)
+        .collect();
+    async move {
+        let [entry] = &entries[..] else {
+            bail!("Expected a single .crate file or crate directory");
+        };
+        if entry.is_directory() {
+            let files = walk_directory(entry.clone().unchecked_into()).await?;
+            read_directory(files).await
+        } else if entry.name().ends_with(".crate") {
+            let entry: &FileSystemFileEntry = entry.unchecked_ref();
+            let file = with_callbacks(|success, error| {
+                entry.file_with_callback_and_callback(success, error);
+                Ok(())
+            })
+            .await?;
+            read_archive(file).await
+        } else {
+            bail!("Expected a .crate file or a crate directory")
+        }
+    }
+}
+
+/// Get the files selected in a file input.
+fn input_files(event: &Event) -> Vec<File> {
+    let files = event
+        .target_dyn_into::<HtmlInputElement>()
+        .and_then(|input| input.files());
+    let Some(files): Option<FileList> = files else {
+        return vec![];
+    };
+    (0..files.length())
+        .filter_map(|index| files.get(index))
+        .collect()
+}
+
+/// Path of a file selected in a directory input, relative to the parent of the directory.
+fn relative_path(file: &File) -> String {
+    Reflect::get(file, &"webkitRelativePath".into())
+        .ok()
+        .and_then(|path| path.as_string())
+        .unwrap_or_else(|| file.name())
+}
+
+/// State of reading an upload.
+#[derive(Clone, PartialEq)]
+enum UploadState {
+    Idle,
+    Reading,
+    Failed(String),
+}
+
+/// Upload view, allows comparing a crate archive or crate directory against published versions.
+#[function_component]
+pub fn Upload() -> Html {
+    let state = use_state(|| UploadState::Idle);
+    let dragging = use_state(|| false);
+    let navigator = use_navigator().unwrap();
+
+    // parse the upload, and show its diff against the latest published version
+    let load = {
+        let state = state.clone();
+        move |upload: std::pin::Pin<Box<dyn Future<Output = Result<CrateSource>>>>| {
+            let state = state.clone();
+            let navigator = navigator.clone();
+            state.set(UploadState::Reading);
+            spawn_local(async move {
+                match upload.await {
+                    Ok(source) => {
+                        let id = register_upload(source);
+                        navigator.push(&Route::UploadDiff {
+                            id,
+                            version: VersionId::Named(VersionNamed::Latest),
+                        });
+                    }
+                    Err(error) => state.set(UploadState::Failed(format!("Error: {error}"))),
+                }
+            });
+        }
+    };
+
+    let ondragover = {
+        let dragging = dragging.clone();
+        move |event: DragEvent| {
+            event.prevent_default();
+            dragging.set(true);
+        }
+    };
+    let ondragleave = {
+        let dragging = dragging.clone();
+        move |_: DragEvent| dragging.set(false)
+    };
+    let ondrop = {
+        let dragging = dragging.clone();
+        let load = load.clone();
+        move |event: DragEvent| {
+            event.prevent_default();
+            dragging.set(false);
+            if let Some(transfer) = event.data_transfer() {
+                load(Box::pin(read_dropped(transfer)));
+            }
+        }
+    };
+    let onarchive = {
+        let load = load.clone();
+        move |event: Event| {
+            if let Some(file) = input_files(&event).into_iter().next() {
+                load(Box::pin(read_archive(file)));
+            }
+        }
+    };
+    let ondirectory = move |event: Event| {
+        let files = input_files(&event)
+            .into_iter()
+            .map(|file| (relative_path(&file), file))
+            .collect();
+        load(Box::pin(read_directory(files)));
+    };
+
+    let status = match &*state {
+        UploadState::Idle => html! {},
+        UploadState::Reading => html! {
+            <Loading title={"Reading upload"} status={"Reading and unpacking crate"} />
+        },
+        UploadState::Failed(status) => html! {
+            <Error title={"Reading upload"} status={status.clone()} />
+        },
+    };
+
+    html! {
+        <div class="flex flex-col min-h-screen">
+            <div class="flex-1">
+                <SimpleNavbar />
+                <Content>
+                    <section class="upload">
+                        <h2 class="title">{"Compare a local crate"}</h2>
+                        <p class="description">
+                            {"Drop a .crate file created by cargo package or a crate directory here to compare it against the published versions of the crate. "}
+                            {"Uploads are not verified against crates.io, and are only kept while the page is open."}
+                        </p>
+                        <div
+                            class={classes!("drop-zone", dragging.then_some("active"))}
+                            {ondragover}
+                            {ondragleave}
+                            {ondrop}
+                        >
+                            <p>{"Drop a .crate file or a crate directory here, or choose one"}</p>
+                            <div class="buttons">
+                                <label class="button">
+                                    {"Choose .crate file"}
+                                    <input type="file" accept=".crate" class="hidden" onchange={onarchive} />
+                                </label>
+                                <label class="button">
+                                    {"Choose directory"}
+                                    <input type="file" webkitdirectory={true} class="hidden" onchange={ondirectory} />
+                                </label>
+                            </div>
+                        </div>
+                        {status}
+                    </section>
+                </Content>
+            </div>
+            <Footer />
+        </div>
+    }
+}
+
+/// Props for which file of an upload to show.
+#[derive(Properties, PartialEq, Clone)]
+pub struct UploadDiffProps {
+    pub id: usize,
+    pub version: VersionId,
+    #[prop_or_default]
+    pub path: Option<Utf8PathBuf>,
+}
+
+/// Show diff of a file change between a published crate version and an uploaded crate.
+#[function_component]
+pub fn UploadDiff(props: &UploadDiffProps) -> Html {
+    let Some(upload) = lookup_upload(props.id) else {
+        return html! {
+            <>
+                <SimpleNavbar />
+                <Content>
+                    <Center>
+                        <Error
+                            title={"Upload not found"}
+                            status={"Uploads are only kept while the page is open, please upload the crate again."}
+                        />
+                    </Center>
+                </Content>
+            </>
+        };
+    };
+    let fallback = html! {
+        <>
+            <SimpleNavbar />
+            <Content>
+                <Center>
+                    <Loading title={"Loading crate"} status={"Loading crate metadata"} />
+                </Center>
+            </Content>
+        </>
+    };
+    html! {
+        <Suspense {fallback}>
+            <UploadFetcher
+                {upload}
+                id={props.id}
+                version={props.version.clone()}
+                path={props.path.clone()}
+            />
+        </Suspense>
+    }
+}
+
+#[derive(Properties, PartialEq, Clone)]
+struct UploadFetcherProps {
+    upload: Arc<CrateSource>,
+    id: usize,
+    version: VersionId,
+    path: Option<Utf8PathBuf>,
+}
+
+#[function_component]
+fn UploadFetcher(props: &UploadFetcherProps) -> HtmlResult {
+    let krate = props.upload.version.krate.clone();
+    let info = use_future_with(krate, |krate| async move {
+        CRATE_RESPONSE_CACHE.fetch_cached(&krate).await
+    })?;
+    let navigator = use_navigator().unwrap();
+
+    let error = |title: &'static str, status: String| {
+        Ok(html! {
+            <>
+                <SimpleNavbar />
+                <Content>
+                    <Center>
+                        <Error {title} {status} />
+                    </Center>
+                </Content>
+            </>
+        })
+    };
+    let src_info = match &*info {
+        Ok(info) => info.clone(),
+        Err(error_) => {
+            let krate = &props.upload.version.krate;
+            return error("Loading crate", format!("Error: {krate} with {error_}"));
+        }
+    };
+    let Some(old) = src_info.version(props.version.clone()).cloned() else {
+        let krate = &src_info.krate.id;
+        return error(
+            "Resolving version",
+            format!("Error: version {} of {krate} not found", props.version),
+        );
+    };
+
+    // the uploaded crate is shown as the only version of the new crate
+    let dst_info = Arc::new(CrateResponse {
+        krate: src_info.krate.clone(),
+        versions: vec![props.upload.version.clone()],
+    });
+    let file_route = {
+        let id = props.id;
+        let version: VersionId = old.version.clone().into();
+        Callback::from(move |path| Route::UploadFile {
+            id,
+            version: version.clone(),
+            path,
+        })
+    };
+    let onchange = {
+        let id = props.id;
+        let path = props.path.clone().unwrap_or_default();
+        Callback::from(move |((_, old), _): VersionChange| {
+            navigator.push(&Route::UploadFile {
+                id,
+                version: old.into(),
+                path: path.clone(),
+            });
+        })
+    };
+
+    Ok(html! {
+        <SourceFetcher
+            {src_info}
+            {dst_info}
+            {old}
+            new={props.upload.version.clone()}
+            path={props.path.clone()}
+            upload={props.upload.clone()}
+            {file_route}
+            {onchange}
+        />
+    })
+}
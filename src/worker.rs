@@ -0,0 +1,320 @@
+//! Web worker which fetches, verifies, unpacks and diffs crate sources.
+//!
+//! Decompressing and hashing crate archives and counting the changes between two crate sources
+//! can take several seconds for large crates. Doing this on the main thread freezes the user
+//! interface, so it is done in a dedicated worker instead. The worker reports progress while it
+//! works, and responds with the changes between the crate sources, along with the crate sources
+//! which it fetched. The main thread only assembles the [`VersionDiff`] from these, which is
+//! cheap.
+//!
+//! The worker does not keep crate sources, so that they are only held once, in the crate source
+//! cache of the main thread. The main thread sends the crate sources which it holds along with the
+//! request, so that the worker does not fetch them again.
+
+use crate::{cache::*, data::*};
+use anyhow::{anyhow, Result};
+use camino::Utf8PathBuf;
+use futures::channel::oneshot;
+use gloo_worker::{HandlerId, Spawnable, Worker, WorkerBridge, WorkerScope};
+use semver::Version;
+use serde::{Deserialize, Serialize};
+use std::{cell::RefCell, collections::BTreeMap, rc::Rc, sync::Arc};
+use yew::platform::spawn_local;
+
+/// Path of the loader script which trunk generates for the worker binary.
+const WORKER_LOADER: &str = "/diff-rs-worker_loader.js";
+
+/// How many files to process between progress reports.
+pub(crate) const PROGRESS_INTERVAL: usize = 32;
+
+/// Request to the worker to diff two crate versions, or to fetch a single one.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct DiffRequest {
+    pub old: VersionInfo,
+    /// Version to diff against, or `None` to only fetch the `old` crate source.
+    pub new: Option<VersionInfo>,
+    /// Crate source of `old` which the main thread holds, so that it is not fetched.
+    pub old_source: Option<CrateSource>,
+    /// Crate source of `new` which the main thread holds, so that it is not fetched. This is
+    /// also used for crates uploaded by the user.
+    pub new_source: Option<CrateSource>,
+    pub limits: ArchiveLimits,
+}
+
+/// Progress of a [`DiffRequest`].
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub enum Progress {
+    /// Downloading a crate archive, with the number of bytes downloaded so far and the size of
+    /// the archive, if known.
+    Downloading {
+        krate: String,
+        version: Version,
+        bytes: u64,
+        total: Option<u64>,
+    },
+    /// Unpacking a crate archive, with the number of files unpacked so far.
+    Unpacking {
+        krate: String,
+        version: Version,
+        files: usize,
+    },
+    /// Counting changes, with the number of files diffed so far.
+    Diffing { files: usize, total: usize },
+}
+
+impl Progress {
+    /// Fraction of the current step which is done, if known.
+    pub fn fraction(&self) -> Option<f64> {
+        match self {
+            Progress::Diffing { files, total } if *total > 0 => Some(*files as f64 / *total as f64),
+            _ => None,
+        }
+    }
+}
+
+/// Result of a [`DiffRequest`].
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct DiffResult {
+    /// The `old` crate source, if the worker fetched it.
+    pub old: Option<CrateSource>,
+    /// The `new` crate source, if the worker fetched it and it is not the `old` one.
+    pub new: Option<CrateSource>,
+    pub changes: BTreeMap<Utf8PathBuf, Changes>,
+    pub limits: ArchiveLimits,
+}
+
+/// Message sent from the worker in response to a [`DiffRequest`].
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub enum DiffResponse {
+    Progress(Progress),
+    Done(Result<Box<DiffResult>, String>),
+}
+
+/// Worker which handles [`DiffRequest`]s.
+pub struct DiffWorker;
+
+impl Worker for DiffWorker {
+    type Message = ();
+    type Input = DiffRequest;
+    type Output = DiffResponse;
+
+    fn create(_scope: &WorkerScope<Self>) -> Self {
+        DiffWorker
+    }
+
+    fn update(&mut self, _scope: &WorkerScope<Self>, _msg: Self::Message) {}
+
+    fn received(&mut self, scope: &WorkerScope<Self>, request: Self::Input, id: HandlerId) {
+        let scope = scope.clone();
+        spawn_local(async move {
+            let progress = |progress| scope.respond(id, DiffResponse::Progress(progress));
+            let result = Self::diff(request, &progress)
+                .await
+                .map_err(|error| error.to_string());
+            scope.respond(id, DiffResponse::Done(result));
+        });
+    }
+}
+
+impl DiffWorker {
+    /// Fetch both crate sources and count the changes between them.
+    ///
+    /// If only the `old` crate source is requested, it is fetched without diffing anything.
+    async fn diff(request: DiffRequest, progress: &dyn Fn(Progress)) -> Result<Box<DiffResult>> {
+        let limits = request.limits;
+        let Some(new) = &request.new else {
+            let (old, fetched) =
+                Self::source(&request.old, request.old_source, limits, progress).await?;
+            return Ok(Box::new(DiffResult {
+                old: fetched.then(|| (*old).clone()),
+                new: None,
+                changes: Default::default(),
+                limits,
+            }));
+        };
+
+        let ((old, old_fetched), (new_source, new_fetched)) = match request.new_source {
+            // when browsing a single version, its crate source is only fetched and sent once
+            None if request.old == *new => {
+                let (old, fetched) =
+                    Self::source(&request.old, request.old_source, limits, progress).await?;
+                ((old.clone(), fetched), (old, false))
+            }
+            held => {
+                let (old, new) = futures::join!(
+                    Self::source(&request.old, request.old_source, limits, progress),
+                    Self::source(new, held, limits, progress),
+                );
+                (old?, new?)
+            }
+        };
+
+        let changes = VersionDiff::changes(&old, &new_source, &limits, |files, total| {
+            if files % PROGRESS_INTERVAL == 0 || files == total {
+                progress(Progress::Diffing { files, total });
+            }
+        });
+
+        Ok(Box::new(DiffResult {
+            old: old_fetched.then(|| (*old).clone()),
+            new: new_fetched.then(|| (*new_source).clone()),
+            changes,
+            limits,
+        }))
+    }
+
+    /// Use the crate source which the main thread sent along, or fetch it.
+    ///
+    /// Returns the crate source along with whether it was fetched. Fetching the same version
+    /// twice shares a single fetch.
+    async fn source(
+        version: &VersionInfo,
+        held: Option<CrateSource>,
+        limits: ArchiveLimits,
+        progress: &dyn Fn(Progress),
+    ) -> Result<(Arc<CrateSource>, bool)> {
+        match held {
+            Some(source) => Ok((Arc::new(source), false)),
+            None => {
+                let source = CRATE_SOURCE_CACHE
+                    .fetch_coalesced(version, limits, progress)
+                    .await?;
+                Ok((source, true))
+            }
+        }
+    }
+}
+
+thread_local! {
+    /// Bridge to the diff worker, which is spawned when it is first used.
+    static DIFF_WORKER: WorkerBridge<DiffWorker> =
+        DiffWorker::spawner().spawn_with_loader(WORKER_LOADER);
+}
+
+/// Send a request to the diff worker and wait for the result.
+async fn request_diff(
+    request: DiffRequest,
+    progress: impl Fn(Progress) + 'static,
+) -> Result<Box<DiffResult>> {
+    let (sender, receiver) = oneshot::channel();
+    let sender = RefCell::new(Some(sender));
+    let callback = move |response| match response {
+        DiffResponse::Progress(status) => progress(status),
+        DiffResponse::Done(result) => {
+            if let Some(sender) = sender.borrow_mut().take() {
+                let _ = sender.send(result);
+            }
+        }
+    };
+
+    // the bridge needs to be kept alive until the response arrives
+    let bridge = DIFF_WORKER.with(|worker| worker.fork(Some(callback)));
+    bridge.send(request);
+    let result = receiver.await?.map_err(anyhow::Error::msg)?;
+    drop(bridge);
+    Ok(result)
+}
+
+/// Use the crate source which the main thread holds, or cache the one sent back by the worker.
+fn received(held: Option<Arc<CrateSource>>, sent: Option<CrateSource>) -> Result<Arc<CrateSource>> {
+    match (held, sent) {
+        (Some(held), _) => Ok(held),
+        (None, Some(sent)) => Ok(CRATE_SOURCE_CACHE.cache(sent)),
+        (None, None) => Err(anyhow!("Diff worker did not send crate source")),
+    }
+}
+
+/// Fetch a crate source in the diff worker.
+///
+/// The crate source is looked up in the crate source cache first, so that it is only decompressed
+/// and verified in the worker. Concurrent calls for the same version share a single request.
+pub async fn fetch_source(version: &VersionInfo) -> Result<Arc<CrateSource>> {
+    CRATE_SOURCE_CACHE
+        .fetch_cached_with(version, || async {
+            let request = DiffRequest {
+                old: version.clone(),
+                new: None,
+                old_source: None,
+                new_source: None,
+                limits: ArchiveLimits::current(),
+            };
+            let result = request_diff(request, |_| {}).await?;
+            result
+                .old
+                .ok_or_else(|| anyhow!("Diff worker did not send crate source"))
+        })
+        .await
+}
+
+/// Fetch and diff two crate versions in the diff worker.
+///
+/// Progress is reported using the `progress` callback. If the diff has been computed before, it
+/// is returned from the cache without involving the worker, and concurrent calls for the same
+/// versions share a single request, reporting progress only to the first caller. Crate sources
+/// which are in the crate source cache are sent to the worker rather than fetched again, the
+/// others are added to the crate source cache.
+pub async fn diff_versions(
+    old: &VersionInfo,
+    new: &VersionInfo,
+    progress: impl Fn(Progress) + 'static,
+) -> Result<Rc<VersionDiff>> {
+    let limits = ArchiveLimits::current();
+    let cache = VERSION_DIFF_CACHE.with(Rc::clone);
+    cache
+        .fetch_cached_with(old, new, &limits, || async {
+            let held_old = CRATE_SOURCE_CACHE.cached(old);
+            let held_new = CRATE_SOURCE_CACHE.cached(new);
+            let request = DiffRequest {
+                old: old.clone(),
+                new: Some(new.clone()),
+                old_source: held_old.as_deref().cloned(),
+                new_source: held_new.as_deref().filter(|_| old != new).cloned(),
+                limits,
+            };
+            let result = request_diff(request, progress).await?;
+            let old_source = received(held_old, result.old)?;
+            let new_source = match old == new {
+                true => old_source.clone(),
+                false => received(held_new, result.new)?,
+            };
+            Ok(Rc::new(VersionDiff::from_changes(
+                old_source,
+                new_source,
+                result.changes,
+                result.limits,
+            )))
+        })
+        .await
+}
+
+/// Fetch a crate version and diff it against an uploaded crate source in the diff worker.
+///
+/// Unlike [`diff_versions`], the uploaded crate source is not added to the crate source cache,
+/// where it could be mistaken for the published crate source of the same version.
+pub async fn diff_upload(
+    old: &VersionInfo,
+    upload: Arc<CrateSource>,
+    progress: impl Fn(Progress) + 'static,
+) -> Result<Rc<VersionDiff>> {
+    let limits = ArchiveLimits::current();
+    let cache = VERSION_DIFF_CACHE.with(Rc::clone);
+    cache
+        .fetch_cached_with(old, &upload.version, &limits, || async {
+            let held_old = CRATE_SOURCE_CACHE.cached(old);
+            let request = DiffRequest {
+                old: old.clone(),
+                new: Some(upload.version.clone()),
+                old_source: held_old.as_deref().cloned(),
+                new_source: Some((*upload).clone()),
+                limits,
+            };
+            let result = request_diff(request, progress).await?;
+            Ok(Rc::new(VersionDiff::from_changes(
+                received(held_old, result.old)?,
+                upload.clone(),
+                result.changes,
+                result.limits,
+            )))
+        })
+        .await
+}
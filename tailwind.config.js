@@ -1,4 +1,5 @@
 module.exports = {
+    darkMode: 'class',
     content: ["{src,static}/**/*.{html,js,rs,css,rs}", "index.html"],
     plugins: [
         require('@tailwindcss/typography')